@@ -0,0 +1,72 @@
+//! Compare the number of allocations and the time spent parsing NTPv4 packets with extensions
+//!
+//! Run with `cargo bench`, and `cargo bench --features smallvec` to compare the storage of
+//! extensions in a `Vec` and in a `SmallVec`.
+
+use ntp_parser::parse_ntpv4;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+// Same as `std::hint::black_box`, which requires Rust 1.66
+fn black_box<T>(x: T) -> T {
+    // SAFETY: `x` is moved out by the volatile read, and forgotten
+    unsafe {
+        let ret = std::ptr::read_volatile(&x);
+        std::mem::forget(x);
+        ret
+    }
+}
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// NTPv4 client request, with one extension and a MAC
+static NTP_REQ_EXT: &[u8] = &[
+    0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x25, 0xcc, 0x13, 0x2b, 0x02, 0x10, 0x00,
+    0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x52, 0x80, 0x0c, 0x2b, 0x59, 0x00, 0x64, 0x66,
+    0x84, 0xf4, 0x4c, 0xa4, 0xee, 0xce, 0x12, 0xb8,
+];
+
+const ITERATIONS: usize = 1_000_000;
+
+fn main() {
+    let storage = if cfg!(feature = "smallvec") {
+        "SmallVec"
+    } else {
+        "Vec"
+    };
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let res = parse_ntpv4(black_box(NTP_REQ_EXT));
+        black_box(res.expect("parsing failed"));
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "extensions storage: {}, {} packets: {:?} ({:?}/packet), {} allocations",
+        storage,
+        ITERATIONS,
+        elapsed,
+        elapsed / ITERATIONS as u32,
+        allocations
+    );
+}
@@ -0,0 +1,54 @@
+//! Compare the time spent parsing the header of NTP packets, with and without decoding the
+//! timestamps
+//!
+//! Run with `cargo bench --bench header`.
+
+use ntp_parser::{parse_ntp_header_lazy, parse_ntpv4};
+use std::time::{Duration, Instant};
+
+// Same as `std::hint::black_box`, which requires Rust 1.66
+fn black_box<T>(x: T) -> T {
+    // SAFETY: `x` is moved out by the volatile read, and forgotten
+    unsafe {
+        let ret = std::ptr::read_volatile(&x);
+        std::mem::forget(x);
+        ret
+    }
+}
+
+// NTPv4 server reply, without extensions or MAC
+static NTP_REPLY: &[u8] = &[
+    0x24, 0x02, 0x06, 0xec, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20, 0xc0, 0xa8, 0x00, 0x01,
+    0xe9, 0x8a, 0xf8, 0x60, 0x12, 0x34, 0x56, 0x78, 0xe9, 0x8a, 0xf8, 0x70, 0x9a, 0xbc, 0xde, 0xf0,
+    0xe9, 0x8a, 0xf8, 0x70, 0xa0, 0x00, 0x00, 0x00, 0xe9, 0x8a, 0xf8, 0x70, 0xa0, 0x01, 0x00, 0x00,
+];
+
+const ITERATIONS: usize = 10_000_000;
+
+fn measure<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let full = measure(|| {
+        let res = parse_ntpv4(black_box(NTP_REPLY));
+        black_box(res.expect("parsing failed"));
+    });
+    let lazy = measure(|| {
+        let res = parse_ntp_header_lazy(black_box(NTP_REPLY));
+        black_box(res.expect("parsing failed"));
+    });
+    for (name, elapsed) in &[("parse_ntpv4", full), ("parse_ntp_header_lazy", lazy)] {
+        println!(
+            "{}: {} packets: {:?} ({:?}/packet)",
+            name,
+            ITERATIONS,
+            elapsed,
+            *elapsed / ITERATIONS as u32
+        );
+    }
+}
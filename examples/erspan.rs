@@ -0,0 +1,48 @@
+//! Parse an NTP packet captured on a mirror port, encapsulated in ERSPAN (type II)
+//!
+//! The mirrored frame is carried in GRE, in the following layers:
+//!
+//! - outer Ethernet header (14 bytes)
+//! - outer IPv4 header (20 bytes, without options)
+//! - GRE header (8 bytes: 4 bytes, and the sequence number set by ERSPAN)
+//! - ERSPAN type II header (8 bytes)
+//! - mirrored Ethernet header (14 bytes)
+//! - mirrored IPv4 header (20 bytes, without options)
+//! - UDP header (8 bytes)
+//!
+//! If the encapsulation is fixed (no VLAN tags or IP options), the NTP packet always starts at
+//! the same offset, and the headers can be skipped without decoding them.
+
+use ntp_parser::*;
+
+/// Offset of the NTP packet in an ERSPAN type II frame
+const ERSPAN_NTP_OFFSET: usize = 14 + 20 + 8 + 8 + 14 + 20 + 8;
+
+fn main() {
+    // an NTP client request, in an ERSPAN frame (headers are zeroed, except the fields
+    // checked below)
+    let mut frame = vec![0; ERSPAN_NTP_OFFSET];
+    // outer IPv4 protocol: GRE
+    frame[14 + 9] = 47;
+    // GRE flags: sequence number present, protocol type: ERSPAN type II
+    frame[14 + 20] = 0x10;
+    frame[14 + 20 + 2..14 + 20 + 4].copy_from_slice(&[0x88, 0xbe]);
+    // mirrored IPv4 protocol: UDP
+    frame[14 + 20 + 16 + 14 + 9] = 17;
+    // UDP destination port: 123
+    frame[ERSPAN_NTP_OFFSET - 6..ERSPAN_NTP_OFFSET - 4].copy_from_slice(&123u16.to_be_bytes());
+    let mut request = vec![0; 48];
+    request[0] = 0x23;
+    request[40..48].copy_from_slice(&0xe3e2_1a3a_8000_0000u64.to_be_bytes());
+    frame.extend_from_slice(&request);
+
+    let res = parse_ntp_skipping(&frame, ERSPAN_NTP_OFFSET);
+    match res {
+        Ok((_, NtpPacket::V4(pkt))) => {
+            println!("mode: {}", pkt.mode);
+            println!("transmit timestamp: {:#x}", pkt.ts_xmit.0);
+        }
+        Ok((_, pkt)) => println!("unexpected packet: {:?}", pkt),
+        Err(e) => println!("parsing failed: {:?}", e),
+    }
+}
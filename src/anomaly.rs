@@ -0,0 +1,137 @@
+//! Detection of implausible packets
+
+use crate::ntp::{NtpMode, NtpV3Packet, NtpV4Packet};
+use core::ops::{BitOr, BitOrAssign};
+
+/// Set of implausible field values found in a packet, see
+/// [`NtpV4Packet::anomaly_flags`]
+///
+/// Fake servers (spoofing tools, honeypots) often send such values, while well-behaved
+/// implementations do not. A single flag is not proof of an attack, but combinations are a
+/// useful signal when scanning large datasets.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct AnomalyFlags(pub u16);
+
+#[allow(non_upper_case_globals)]
+impl AnomalyFlags {
+    /// Stratum 1 (primary server) with a root dispersion of 1 second or more
+    pub const PrimaryHighDispersion: AnomalyFlags = AnomalyFlags(1 << 0);
+    /// Precision above 0 (clock resolution coarser than 1 second)
+    pub const PositivePrecision: AnomalyFlags = AnomalyFlags(1 << 1);
+    /// Poll exponent out of the range allowed by RFC 5905 (0 to 17)
+    pub const PollOutOfRange: AnomalyFlags = AnomalyFlags(1 << 2);
+    /// Stratum above 16
+    pub const InvalidStratum: AnomalyFlags = AnomalyFlags(1 << 3);
+    /// Stratum 0 without a kiss code in the reference identifier
+    pub const StratumZeroWithoutKissCode: AnomalyFlags = AnomalyFlags(1 << 4);
+    /// Reference timestamp later than the transmit timestamp
+    pub const ReferenceAfterTransmit: AnomalyFlags = AnomalyFlags(1 << 5);
+    /// Server or broadcast packet with an unset transmit timestamp
+    pub const ZeroTransmit: AnomalyFlags = AnomalyFlags(1 << 6);
+
+    /// Return true if no flag is set
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return true if all the flags of `other` are set
+    #[inline]
+    pub fn contains(self, other: AnomalyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for AnomalyFlags {
+    type Output = AnomalyFlags;
+
+    fn bitor(self, rhs: AnomalyFlags) -> AnomalyFlags {
+        AnomalyFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for AnomalyFlags {
+    fn bitor_assign(&mut self, rhs: AnomalyFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+macro_rules! impl_anomaly_flags {
+    ($t:ident) => {
+        impl<'a> $t<'a> {
+            /// Check the header for implausible field values
+            ///
+            /// See the constants of [`AnomalyFlags`] for the checks. Timestamps are compared
+            /// using wrapping arithmetic, and only if both are set.
+            pub fn anomaly_flags(&self) -> AnomalyFlags {
+                let mut flags = AnomalyFlags::default();
+                let checks = [
+                    (
+                        self.stratum == 1 && self.root_dispersion.to_seconds() >= 1.0,
+                        AnomalyFlags::PrimaryHighDispersion,
+                    ),
+                    (self.precision.0 > 0, AnomalyFlags::PositivePrecision),
+                    (!(0..=17).contains(&self.poll), AnomalyFlags::PollOutOfRange),
+                    (self.stratum > 16, AnomalyFlags::InvalidStratum),
+                    (
+                        self.stratum == 0 && self.kiss_code().is_none(),
+                        AnomalyFlags::StratumZeroWithoutKissCode,
+                    ),
+                    (
+                        !self.ts_ref.is_unset()
+                            && !self.ts_xmit.is_unset()
+                            && self.ts_ref.wrapping_diff(self.ts_xmit) > 0,
+                        AnomalyFlags::ReferenceAfterTransmit,
+                    ),
+                    (
+                        matches!(self.mode, NtpMode::Server | NtpMode::Broadcast)
+                            && self.ts_xmit.is_unset(),
+                        AnomalyFlags::ZeroTransmit,
+                    ),
+                ];
+                for (cond, flag) in checks.iter() {
+                    if *cond {
+                        flags |= *flag;
+                    }
+                }
+                flags
+            }
+        }
+    };
+}
+
+impl_anomaly_flags!(NtpV3Packet);
+impl_anomaly_flags!(NtpV4Packet);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::{NtpShortFormat, NtpTimestamp, Precision};
+
+    #[test]
+    fn test_anomaly_flags() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0x4750_5300, NtpTimestamp(1), NtpTimestamp(2));
+        pkt.ts_ref = NtpTimestamp(1);
+        assert!(pkt.anomaly_flags().is_empty());
+        pkt.root_dispersion = NtpShortFormat(0x0002_0000);
+        pkt.precision = Precision(10);
+        pkt.ts_ref = NtpTimestamp(3);
+        let flags = pkt.anomaly_flags();
+        assert_eq!(
+            flags,
+            AnomalyFlags::PrimaryHighDispersion
+                | AnomalyFlags::PositivePrecision
+                | AnomalyFlags::ReferenceAfterTransmit
+        );
+        assert!(flags.contains(AnomalyFlags::PositivePrecision));
+        assert!(!flags.contains(AnomalyFlags::ZeroTransmit));
+        let mut pkt = NtpV4Packet::server_reply(0, 0, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.poll = 20;
+        assert_eq!(
+            pkt.anomaly_flags(),
+            AnomalyFlags::PollOutOfRange
+                | AnomalyFlags::StratumZeroWithoutKissCode
+                | AnomalyFlags::ZeroTransmit
+        );
+    }
+}
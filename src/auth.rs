@@ -0,0 +1,380 @@
+//! Symmetric key authentication (MAC)
+
+use crate::extension::{
+    NTP_EXT_NTS_AUTHENTICATOR, NTP_EXT_NTS_COOKIE, NTP_EXT_NTS_COOKIE_PLACEHOLDER,
+    NTP_EXT_UNIQUE_IDENTIFIER,
+};
+use crate::kiss::KissCode;
+use crate::ntp::{parse_ntp, NtpMac, NtpPacket, NtpV4Packet};
+#[cfg(feature = "crypto")]
+use crate::owned::{NtpMacOwned, NtpV4PacketOwned};
+#[cfg(feature = "crypto")]
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Digest algorithm of a MAC
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MacAlgorithm {
+    /// MD5 (16-byte digest)
+    Md5,
+    /// SHA-1 (20-byte digest)
+    Sha1,
+    /// AES-128-CMAC (16-byte digest, [RFC8573])
+    ///
+    /// [RFC8573]: https://tools.ietf.org/html/rfc8573
+    AesCmac,
+}
+
+impl MacAlgorithm {
+    /// Return the length of the digest, in bytes
+    pub const fn digest_len(&self) -> usize {
+        match self {
+            MacAlgorithm::Md5 => 16,
+            MacAlgorithm::Sha1 => 20,
+            MacAlgorithm::AesCmac => 16,
+        }
+    }
+}
+
+/// Infer the digest algorithm from the length of the MAC region (key identifier and digest)
+///
+/// A 20-byte MAC uses MD5, and a 24-byte MAC uses SHA-1. Other lengths return `None`.
+///
+/// AES-CMAC MACs are also 20 bytes long, and cannot be distinguished from MD5 MACs: the
+/// algorithm is a property of the key. Use [`mac_algorithm_candidates`] to get all the
+/// possible algorithms.
+pub fn infer_mac_algorithm(mac_region_len: usize) -> Option<MacAlgorithm> {
+    match mac_region_len.checked_sub(4)? {
+        16 => Some(MacAlgorithm::Md5),
+        20 => Some(MacAlgorithm::Sha1),
+        _ => None,
+    }
+}
+
+/// Classification of the data following the header and extension fields of a packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MacTail {
+    /// No MAC
+    Empty,
+    /// Key identifier 0 without digest: a crypto-NAK, sent by a server which could not
+    /// authenticate the request
+    CryptoNak,
+    /// Non-zero key identifier without digest: a malformed MAC, sent by some test tools
+    KeyIdOnly(u32),
+    /// Between 5 and 19 bytes: a truncated MAC, which cannot be parsed
+    Truncated { len: usize },
+    /// Key identifier and digest (at least 20 bytes)
+    Mac,
+}
+
+impl MacTail {
+    /// Classify the MAC region `tail` (key identifier and digest) of a packet
+    ///
+    /// Short tails of 4 bytes are parsed as a MAC with an empty digest by
+    /// [`parse_ntpv4`](crate::parse_ntpv4), while truncated MACs are a parsing error. This
+    /// distinguishes the two cases of a 4-byte tail, and reports the length of truncated
+    /// MACs.
+    pub fn classify(tail: &[u8]) -> MacTail {
+        match tail {
+            [] => MacTail::Empty,
+            [0, 0, 0, 0] => MacTail::CryptoNak,
+            &[a, b, c, d] => MacTail::KeyIdOnly(u32::from_be_bytes([a, b, c, d])),
+            _ if tail.len() < 20 => MacTail::Truncated { len: tail.len() },
+            _ => MacTail::Mac,
+        }
+    }
+}
+
+impl<'a> NtpMac<'a> {
+    /// Return true if this MAC is a crypto-NAK (zero key identifier, without digest)
+    pub fn is_crypto_nak(&self) -> bool {
+        self.key_id == 0 && self.mac.is_empty()
+    }
+}
+
+/// Return all the digest algorithms possible for a MAC region (key identifier and digest) of
+/// this length
+///
+/// A 20-byte MAC uses MD5 or AES-CMAC (most likely first), and a 24-byte MAC uses SHA-1. The
+/// length of the MAC is the same for MD5 and AES-CMAC, so the split between extension fields
+/// and MAC does not depend on the algorithm. Other lengths return an empty list.
+pub fn mac_algorithm_candidates(mac_region_len: usize) -> &'static [MacAlgorithm] {
+    match mac_region_len.checked_sub(4) {
+        Some(16) => &[MacAlgorithm::Md5, MacAlgorithm::AesCmac],
+        Some(20) => &[MacAlgorithm::Sha1],
+        _ => &[],
+    }
+}
+
+/// Summary of the authentication state of a packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SecuritySummary {
+    /// The packet has a MAC, or an NTS authenticator
+    pub authenticated: bool,
+    /// Digest algorithm of the MAC, inferred from its length
+    pub algorithm: Option<MacAlgorithm>,
+    /// The packet contains NTS extension fields
+    pub has_nts: bool,
+    /// The packet is a crypto-NAK (MAC with a zero key identifier) or an NTS NAK
+    pub crypto_nak: bool,
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Summarize the authentication state of the packet
+    pub fn security_summary(&self) -> SecuritySummary {
+        let is_nts = |t: u16| {
+            t == NTP_EXT_UNIQUE_IDENTIFIER
+                || t == NTP_EXT_NTS_COOKIE
+                || t == NTP_EXT_NTS_COOKIE_PLACEHOLDER
+                || t == NTP_EXT_NTS_AUTHENTICATOR
+        };
+        let has_nts = self.extensions.iter().any(|e| is_nts(e.field_type));
+        let nts_auth = self
+            .extensions
+            .iter()
+            .any(|e| e.field_type == NTP_EXT_NTS_AUTHENTICATOR);
+        let algorithm = self
+            .auth
+            .as_ref()
+            .and_then(|m| infer_mac_algorithm(4 + m.mac.len()));
+        let crypto_nak = matches!(self.auth, Some(ref m) if m.key_id == 0)
+            || self.kiss_code() == Some(KissCode::Ntsn);
+        SecuritySummary {
+            authenticated: self.auth.is_some() || nts_auth,
+            algorithm,
+            has_nts,
+            crypto_nak,
+        }
+    }
+}
+
+/// Return the range of the bytes of a packet covered by its MAC
+///
+/// `wire` is the complete packet. The digest is computed over the data preceding the key
+/// identifier of the MAC (the header and the extension fields). Returns `None` if the packet
+/// cannot be parsed or is not authenticated.
+pub fn mac_digest_range(wire: &[u8]) -> Option<Range<usize>> {
+    let (rem, pkt) = parse_ntp(wire).ok()?;
+    let end = wire.len() - rem.len();
+    let mac_len = match pkt {
+        NtpPacket::V3(pkt) => pkt.authenticator?.len(),
+        NtpPacket::V4(pkt) => 4 + pkt.auth?.mac.len(),
+        NtpPacket::Control(msg) => 4 + msg.auth?.mac.len(),
+        #[cfg(feature = "ntpv5")]
+        NtpPacket::V5(_) => return None,
+    };
+    Some(0..end - mac_len)
+}
+
+/// Compute the digest of `data` using `key`, as done by the reference implementation
+///
+/// The digest is the hash of the key followed by the data.
+///
+/// # Panics
+///
+/// Panics if `algo` is [`MacAlgorithm::AesCmac`], which is not supported (this crate does not
+/// include an AES implementation). Use [`try_compute_digest`] if the algorithm is not known in
+/// advance.
+#[cfg(feature = "crypto")]
+pub fn compute_digest(algo: MacAlgorithm, key: &[u8], data: &[u8]) -> Vec<u8> {
+    try_compute_digest(algo, key, data).expect("AES-CMAC is not supported")
+}
+
+/// Compute the digest of `data` using `key`, or return `None` if the algorithm is not supported
+///
+/// This is the same as [`compute_digest`], but returns `None` for AES-CMAC instead of
+/// panicking.
+#[cfg(feature = "crypto")]
+pub fn try_compute_digest(algo: MacAlgorithm, key: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    use md5::Digest;
+    match algo {
+        MacAlgorithm::Md5 => {
+            let mut h = md5::Md5::new();
+            h.update(key);
+            h.update(data);
+            Some(h.finalize().to_vec())
+        }
+        MacAlgorithm::Sha1 => {
+            let mut h = sha1::Sha1::new();
+            h.update(key);
+            h.update(data);
+            Some(h.finalize().to_vec())
+        }
+        MacAlgorithm::AesCmac => None,
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl NtpV4PacketOwned {
+    /// Compute the MAC of the packet and store it in the `auth` field
+    ///
+    /// The digest covers the header and the extension fields, any previous MAC is replaced.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `algo` is [`MacAlgorithm::AesCmac`], see [`compute_digest`].
+    pub fn set_mac(&mut self, key_id: u32, key: &[u8], algo: MacAlgorithm) {
+        let data = self.as_packet().canonical_header_bytes();
+        let mac = compute_digest(algo, key, &data);
+        self.auth = Some(NtpMacOwned { key_id, mac });
+    }
+
+    /// Compute the MAC of the packet and store it in the `auth` field, if the algorithm is
+    /// supported
+    ///
+    /// Returns false, and leaves the packet unchanged, if the algorithm is not supported by
+    /// [`try_compute_digest`].
+    pub fn try_set_mac(&mut self, key_id: u32, key: &[u8], algo: MacAlgorithm) -> bool {
+        let data = self.as_packet().canonical_header_bytes();
+        match try_compute_digest(algo, key, &data) {
+            Some(mac) => {
+                self.auth = Some(NtpMacOwned { key_id, mac });
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_mac_algorithm() {
+        assert_eq!(infer_mac_algorithm(20), Some(MacAlgorithm::Md5));
+        assert_eq!(infer_mac_algorithm(24), Some(MacAlgorithm::Sha1));
+        assert_eq!(infer_mac_algorithm(0), None);
+        assert_eq!(infer_mac_algorithm(16), None);
+    }
+
+    // Client request with an AES-128-CMAC (RFC 8573) MAC, key identifier 10. AES-CMAC cannot
+    // be computed by this crate, so the digest is not verified: only the layout is checked.
+    static NTP_REQ_AES_CMAC: &[u8] = &[
+        0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe9, 0x8a, 0xf8, 0x70, 0x00,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x0a, 0x1b, 0x3e, 0xb2, 0x45, 0x7e, 0x8e, 0x31, 0x5c,
+        0x81, 0x39, 0x3e, 0x61, 0x9c, 0x77, 0x23, 0xeb,
+    ];
+
+    #[test]
+    fn test_aes_cmac_mac() {
+        use crate::ntp::parse_ntpv4;
+
+        let (rem, pkt) = parse_ntpv4(NTP_REQ_AES_CMAC).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(pkt.extensions.is_empty());
+        let auth = pkt.auth.as_ref().expect("missing MAC");
+        assert_eq!(auth.key_id, 10);
+        assert_eq!(auth.mac.len(), MacAlgorithm::AesCmac.digest_len());
+        assert_eq!(mac_digest_range(NTP_REQ_AES_CMAC), Some(0..48));
+        let candidates = mac_algorithm_candidates(4 + auth.mac.len());
+        assert_eq!(candidates, [MacAlgorithm::Md5, MacAlgorithm::AesCmac]);
+        assert_eq!(mac_algorithm_candidates(24), [MacAlgorithm::Sha1]);
+        assert!(mac_algorithm_candidates(28).is_empty());
+    }
+
+    #[test]
+    fn test_mac_tail() {
+        use crate::ntp::parse_ntpv4;
+
+        let mut buf = vec![0x24];
+        buf.resize(48, 0);
+        // crypto-NAK
+        let mut nak = buf.clone();
+        nak.extend_from_slice(&[0, 0, 0, 0]);
+        let (rem, pkt) = parse_ntpv4(&nak).expect("parsing failed");
+        assert!(rem.is_empty());
+        let mac = pkt.auth.expect("missing MAC");
+        assert!(mac.is_crypto_nak());
+        assert_eq!(MacTail::classify(&nak[48..]), MacTail::CryptoNak);
+        // key identifier without digest
+        let mut key_only = buf.clone();
+        key_only.extend_from_slice(&[0, 0, 0, 7]);
+        let (_, pkt) = parse_ntpv4(&key_only).expect("parsing failed");
+        let mac = pkt.auth.expect("missing MAC");
+        assert_eq!((mac.key_id, mac.mac), (7, &[][..]));
+        assert!(!mac.is_crypto_nak());
+        assert_eq!(MacTail::classify(&key_only[48..]), MacTail::KeyIdOnly(7));
+        // truncated MAC
+        let mut truncated = buf.clone();
+        truncated.extend_from_slice(&[0, 0, 0, 7, 1, 2, 3, 4, 5, 6]);
+        assert!(parse_ntpv4(&truncated).is_err());
+        assert_eq!(
+            MacTail::classify(&truncated[48..]),
+            MacTail::Truncated { len: 10 }
+        );
+        assert_eq!(MacTail::classify(&[]), MacTail::Empty);
+        assert_eq!(MacTail::classify(&[0; 20]), MacTail::Mac);
+    }
+
+    #[test]
+    fn test_security_summary() {
+        use crate::ntp::{parse_ntpv4, NtpExtension};
+        use crate::timestamp::NtpTimestamp;
+
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0xaa; 16]);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        let summary = pkt.security_summary();
+        assert!(summary.authenticated);
+        assert_eq!(summary.algorithm, Some(MacAlgorithm::Md5));
+        assert!(!summary.has_nts && !summary.crypto_nak);
+
+        let mut pkt = NtpV4Packet::server_reply(0, 0x4e54_534e, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.extensions.push(NtpExtension {
+            field_type: NTP_EXT_UNIQUE_IDENTIFIER,
+            length: 4,
+            value: &[],
+        });
+        let summary = pkt.security_summary();
+        assert!(!summary.authenticated);
+        assert_eq!(summary.algorithm, None);
+        assert!(summary.has_nts && summary.crypto_nak);
+    }
+
+    #[test]
+    fn test_mac_digest_range() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        assert_eq!(mac_digest_range(&buf), None);
+        buf.extend_from_slice(&[0x00, 0x02, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0; 20]);
+        assert_eq!(mac_digest_range(&buf), Some(0..56));
+        assert_eq!(mac_digest_range(&buf[..40]), None);
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn test_set_mac() {
+        use crate::ntp::NtpV4Packet;
+        use crate::timestamp::NtpTimestamp;
+
+        let reply = NtpV4Packet::server_reply(2, 0x7f00_0001, NtpTimestamp(1), NtpTimestamp(2));
+        let mut pkt = reply.into_owned();
+        pkt.set_mac(1, b"secret", MacAlgorithm::Md5);
+        let auth = pkt.auth.as_ref().expect("MAC not set");
+        assert_eq!(auth.key_id, 1);
+        assert_eq!(
+            auth.mac,
+            [
+                0x46, 0xc4, 0x00, 0xa9, 0xcb, 0x83, 0xd2, 0xd3, 0x33, 0x75, 0x12, 0x7d, 0xd1, 0xec,
+                0xe2, 0x09
+            ]
+        );
+        assert!(pkt.try_set_mac(2, b"secret", MacAlgorithm::Sha1));
+        let auth = pkt.auth.as_ref().expect("MAC not set");
+        assert_eq!(auth.key_id, 2);
+        assert_eq!(auth.mac.len(), MacAlgorithm::Sha1.digest_len());
+        let before = pkt.clone();
+        assert!(!pkt.try_set_mac(3, &[0; 16], MacAlgorithm::AesCmac));
+        assert_eq!(pkt, before);
+        assert_eq!(
+            try_compute_digest(MacAlgorithm::Md5, b"secret", b"data"),
+            Some(compute_digest(MacAlgorithm::Md5, b"secret", b"data"))
+        );
+    }
+}
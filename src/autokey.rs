@@ -0,0 +1,165 @@
+//! Structural decoding of Autokey extension fields ([RFC5906])
+//!
+//! Only the structure of the messages is decoded, the signatures are not verified.
+//!
+//! [RFC5906]: https://tools.ietf.org/html/rfc5906
+
+use crate::ntp::NtpExtension;
+use nom::bytes::complete::take;
+use nom::combinator::{complete, opt};
+use nom::number::complete::be_u32;
+use nom::IResult;
+
+/// Version of the Autokey protocol, stored in the low byte of the field type
+pub const AUTOKEY_VERSION: u8 = 2;
+
+/// Operation code of an Autokey message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AutokeyCode(pub u8);
+
+#[allow(non_upper_case_globals)]
+impl AutokeyCode {
+    pub const NoOperation: AutokeyCode = AutokeyCode(0);
+    pub const Association: AutokeyCode = AutokeyCode(1);
+    pub const Certificate: AutokeyCode = AutokeyCode(2);
+    pub const Cookie: AutokeyCode = AutokeyCode(3);
+    pub const Autokey: AutokeyCode = AutokeyCode(4);
+    pub const Leapseconds: AutokeyCode = AutokeyCode(5);
+    pub const Sign: AutokeyCode = AutokeyCode(6);
+    pub const IdentityIff: AutokeyCode = AutokeyCode(7);
+    pub const IdentityGq: AutokeyCode = AutokeyCode(8);
+    pub const IdentityMv: AutokeyCode = AutokeyCode(9);
+}
+
+/// An Autokey message, carried in an extension field
+///
+/// Requests may only contain the association identifier, in which case the other fields
+/// are zero or empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutokeyMessage<'a> {
+    /// Response bit: set in responses, clear in requests
+    pub response: bool,
+    /// Error bit: set in responses to report an error
+    pub error: bool,
+    pub code: AutokeyCode,
+    pub version: u8,
+    /// Association identifier, used to match requests and responses
+    pub association_id: u32,
+    pub timestamp: u32,
+    pub filestamp: u32,
+    pub value: &'a [u8],
+    pub signature: &'a [u8],
+}
+
+// Parse a 32-bit length followed by the data, and skip the padding to a 32-bit boundary
+//
+// The padding may be missing at the end of the field value, the padding of the extension
+// field being already removed.
+fn length_data(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    let (i, len) = be_u32(i)?;
+    let (i, data) = take(len)(i)?;
+    let padding = ((4 - len % 4) % 4) as usize;
+    Ok((&i[padding.min(i.len())..], data))
+}
+
+fn parse_autokey_body(field_type: u16, i: &[u8]) -> IResult<&[u8], AutokeyMessage<'_>> {
+    let [flags, version] = field_type.to_be_bytes();
+    let (i, association_id) = be_u32(i)?;
+    let mut msg = AutokeyMessage {
+        response: flags & 0x80 != 0,
+        error: flags & 0x40 != 0,
+        code: AutokeyCode(flags & 0x3f),
+        version,
+        association_id,
+        timestamp: 0,
+        filestamp: 0,
+        value: &[],
+        signature: &[],
+    };
+    if i.is_empty() {
+        return Ok((i, msg));
+    }
+    let (i, timestamp) = be_u32(i)?;
+    let (i, filestamp) = be_u32(i)?;
+    let (i, value) = length_data(i)?;
+    let (i, signature) = opt(complete(length_data))(i)?;
+    msg.timestamp = timestamp;
+    msg.filestamp = filestamp;
+    msg.value = value;
+    msg.signature = signature.unwrap_or(&[]);
+    Ok((i, msg))
+}
+
+impl<'a> NtpExtension<'a> {
+    /// Decode the extension field as an Autokey message
+    ///
+    /// Returns `None` if the version (low byte of the field type) is not the Autokey version,
+    /// or if the value is malformed. Padding after the signature is ignored.
+    pub fn parse_autokey(&self) -> Option<AutokeyMessage<'a>> {
+        if self.field_type as u8 != AUTOKEY_VERSION {
+            return None;
+        }
+        parse_autokey_body(self.field_type, self.value)
+            .ok()
+            .map(|(_, msg)| msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_autokey_messages() {
+        // association request
+        let ext = NtpExtension {
+            field_type: 0x0102,
+            length: 8,
+            value: &[0x00, 0x00, 0xbe, 0xef],
+        };
+        let msg = ext.parse_autokey().expect("decoding failed");
+        assert!(!msg.response);
+        assert_eq!(msg.code, AutokeyCode::Association);
+        assert_eq!(msg.association_id, 0xbeef);
+        assert!(msg.value.is_empty());
+        // cookie response, with a value and a signature
+        let value = &[
+            0x00, 0x00, 0xbe, 0xef, 0xd0, 0x00, 0x00, 0x01, 0xd0, 0x00, 0x00, 0x02, 0x00, 0x00,
+            0x00, 0x04, 0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x02, 0xaa, 0xbb, 0x00, 0x00,
+        ];
+        let ext = NtpExtension {
+            field_type: 0x8302,
+            length: 4 + value.len() as u16,
+            value,
+        };
+        let msg = ext.parse_autokey().expect("decoding failed");
+        assert!(msg.response);
+        assert!(!msg.error);
+        assert_eq!(msg.code, AutokeyCode::Cookie);
+        assert_eq!(msg.timestamp, 0xd000_0001);
+        assert_eq!(msg.filestamp, 0xd000_0002);
+        assert_eq!(msg.value, &[1, 2, 3, 4]);
+        assert_eq!(msg.signature, &[0xaa, 0xbb]);
+        // 5-byte value, padded, followed by a signature
+        let value = &[
+            0x00, 0x00, 0xbe, 0xef, 0xd0, 0x00, 0x00, 0x01, 0xd0, 0x00, 0x00, 0x02, 0x00, 0x00,
+            0x00, 0x05, 0x01, 0x02, 0x03, 0x04, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04,
+            0xaa, 0xbb, 0xcc, 0xdd,
+        ];
+        let ext = NtpExtension {
+            field_type: 0x8302,
+            length: 4 + value.len() as u16,
+            value,
+        };
+        let msg = ext.parse_autokey().expect("decoding failed");
+        assert_eq!(msg.value, &[1, 2, 3, 4, 5]);
+        assert_eq!(msg.signature, &[0xaa, 0xbb, 0xcc, 0xdd]);
+        // not an Autokey field
+        let ext = NtpExtension {
+            field_type: 0x0104,
+            length: 8,
+            value: &[0; 4],
+        };
+        assert!(ext.parse_autokey().is_none());
+    }
+}
@@ -0,0 +1,111 @@
+//! Detection of broadcast associations
+
+use crate::ntp::{NtpMode, NtpV4Packet};
+use crate::timestamp::NtpTimestamp;
+
+/// State of a broadcast association
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BroadcastState {
+    /// No exchange was seen
+    Idle,
+    /// The client sent a request (mode 3) to the server
+    ClientRequest,
+    /// The server answered the request (mode 4), the client can calibrate the delay
+    Exchanged,
+    /// The server sent broadcast packets (mode 5) after the exchange
+    Established,
+}
+
+/// Detector of the establishment of a broadcast association
+///
+/// A broadcast client first exchanges a client request and a server reply with the server,
+/// to calibrate the propagation delay, and then accepts the broadcast packets of this
+/// server. The detector must be given the packets exchanged between one client and one
+/// server (in both directions), in order.
+#[derive(Clone, Debug)]
+pub struct BroadcastAssociation {
+    state: BroadcastState,
+    request_xmit: NtpTimestamp,
+}
+
+impl Default for BroadcastAssociation {
+    fn default() -> Self {
+        BroadcastAssociation::new()
+    }
+}
+
+impl BroadcastAssociation {
+    /// Create a detector, in the idle state
+    pub fn new() -> BroadcastAssociation {
+        BroadcastAssociation {
+            state: BroadcastState::Idle,
+            request_xmit: NtpTimestamp(0),
+        }
+    }
+
+    /// Return the current state of the association
+    pub fn state(&self) -> BroadcastState {
+        self.state
+    }
+
+    /// Update the state with the next packet, and return true if the association has just
+    /// been established
+    ///
+    /// A server reply is only accepted if its origin timestamp matches the transmit timestamp
+    /// of the request.
+    pub fn observe(&mut self, pkt: &NtpV4Packet) -> bool {
+        if self.state == BroadcastState::Established {
+            return false;
+        }
+        match pkt.mode {
+            NtpMode::Client => {
+                self.state = BroadcastState::ClientRequest;
+                self.request_xmit = pkt.ts_xmit;
+            }
+            NtpMode::Server
+                if self.state == BroadcastState::ClientRequest
+                    && pkt.ts_orig == self.request_xmit =>
+            {
+                self.state = BroadcastState::Exchanged;
+            }
+            NtpMode::Broadcast if self.state == BroadcastState::Exchanged => {
+                self.state = BroadcastState::Established;
+                return true;
+            }
+            _ => (),
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::NtpTimestamp;
+
+    #[test]
+    fn test_broadcast_association() {
+        let mut request = NtpV4Packet::server_reply(0, 0, NtpTimestamp(0), NtpTimestamp(0x1234));
+        request.mode = NtpMode::Client;
+        let mut reply = NtpV4Packet::server_reply(2, 0, NtpTimestamp(1), NtpTimestamp(2));
+        reply.ts_orig = NtpTimestamp(0x1234);
+        let mut broadcast = NtpV4Packet::server_reply(2, 0, NtpTimestamp(3), NtpTimestamp(4));
+        broadcast.mode = NtpMode::Broadcast;
+
+        let mut assoc = BroadcastAssociation::new();
+        // broadcast without an exchange
+        assert!(!assoc.observe(&broadcast));
+        assert_eq!(assoc.state(), BroadcastState::Idle);
+        assert!(!assoc.observe(&request));
+        // reply to another request
+        let mut other = reply.clone();
+        other.ts_orig = NtpTimestamp(0x5678);
+        assert!(!assoc.observe(&other));
+        assert_eq!(assoc.state(), BroadcastState::ClientRequest);
+        assert!(!assoc.observe(&reply));
+        assert_eq!(assoc.state(), BroadcastState::Exchanged);
+        assert!(assoc.observe(&broadcast));
+        assert!(!assoc.observe(&broadcast));
+        assert_eq!(assoc.state(), BroadcastState::Established);
+    }
+}
@@ -0,0 +1,97 @@
+//! Detection of bursts of client requests (`burst` and `iburst`)
+
+use crate::ntp::{NtpMode, NtpV4Packet};
+use alloc::vec::Vec;
+
+/// Detector of bursts of requests sent by a client
+///
+/// With the `iburst` option, a client sends a rapid sequence of requests when the server is
+/// unreachable (at startup), and with the `burst` option, at each poll. The detector must be
+/// given the packets sent by one client to one server, with their capture time in seconds,
+/// in order. A burst is detected when at least `count` client requests are seen within
+/// `window` seconds.
+#[derive(Clone, Debug)]
+pub struct BurstDetector {
+    times: Vec<f64>,
+    count: usize,
+    window: f64,
+    bursts: usize,
+}
+
+impl Default for BurstDetector {
+    /// Detect at least 4 requests within 7 seconds, which matches the default `iburst`
+    /// spacing of 2 seconds
+    fn default() -> Self {
+        BurstDetector::new(4, 7.0)
+    }
+}
+
+impl BurstDetector {
+    /// Create a detector of `count` requests within `window` seconds
+    pub fn new(count: usize, window: f64) -> BurstDetector {
+        BurstDetector {
+            times: Vec::with_capacity(count),
+            count,
+            window,
+            bursts: 0,
+        }
+    }
+
+    /// Record the packet, captured at `time` (in seconds), and return true if a burst has
+    /// just been detected
+    ///
+    /// Packets which are not client requests are ignored. Once a burst is detected, the
+    /// requests are forgotten, so that the next burst is detected separately.
+    pub fn observe(&mut self, time: f64, pkt: &NtpV4Packet) -> bool {
+        if pkt.mode != NtpMode::Client || self.count == 0 {
+            return false;
+        }
+        let window = self.window;
+        self.times.retain(|&t| time - t <= window);
+        self.times.push(time);
+        if self.times.len() < self.count {
+            return false;
+        }
+        self.times.clear();
+        self.bursts += 1;
+        true
+    }
+
+    /// Return the number of bursts detected
+    pub fn bursts(&self) -> usize {
+        self.bursts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp::parse_ntpv4;
+
+    #[test]
+    fn test_burst_detector() {
+        let mut request = vec![0x23];
+        request.resize(48, 0);
+        let mut reply = request.clone();
+        reply[0] = 0x24;
+        let (_, request) = parse_ntpv4(&request).expect("parsing failed");
+        let (_, reply) = parse_ntpv4(&reply).expect("parsing failed");
+        let mut detector = BurstDetector::default();
+        // regular polling
+        for i in 0..8 {
+            assert!(!detector.observe(f64::from(i) * 64.0, &request));
+        }
+        // iburst, replies are ignored
+        let results: Vec<bool> = (0..8)
+            .map(|i| {
+                detector.observe(1000.0 + f64::from(i) * 2.0, &reply);
+                detector.observe(1000.0 + f64::from(i) * 2.0, &request)
+            })
+            .collect();
+        assert_eq!(
+            results,
+            [false, false, false, true, false, false, false, true]
+        );
+        assert_eq!(detector.bursts(), 2);
+    }
+}
@@ -0,0 +1,804 @@
+//! NTP control messages (mode 6)
+//!
+//! See [RFC9327] (previously appendix B of RFC 1305).
+//!
+//! [RFC9327]: https://tools.ietf.org/html/rfc9327
+
+use crate::ntp::{LeapIndicator, NtpMac, NtpMode};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use nom::bytes::streaming::take;
+use nom::number::streaming::{be_u32, be_u8};
+use nom::IResult;
+use nom_derive::*;
+
+/// Operation code of a control message
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpControlOpcode(pub u8);
+
+#[allow(non_upper_case_globals)]
+impl NtpControlOpcode {
+    pub const Unspecified: NtpControlOpcode = NtpControlOpcode(0);
+    pub const ReadStatus: NtpControlOpcode = NtpControlOpcode(1);
+    pub const ReadVariables: NtpControlOpcode = NtpControlOpcode(2);
+    pub const WriteVariables: NtpControlOpcode = NtpControlOpcode(3);
+    pub const ReadClockVariables: NtpControlOpcode = NtpControlOpcode(4);
+    pub const WriteClockVariables: NtpControlOpcode = NtpControlOpcode(5);
+    pub const SetTrap: NtpControlOpcode = NtpControlOpcode(6);
+    pub const AsyncMessage: NtpControlOpcode = NtpControlOpcode(7);
+    pub const Configure: NtpControlOpcode = NtpControlOpcode(8);
+    pub const SaveConfig: NtpControlOpcode = NtpControlOpcode(9);
+    pub const ReadMru: NtpControlOpcode = NtpControlOpcode(10);
+    pub const ReadOrderedList: NtpControlOpcode = NtpControlOpcode(11);
+    pub const RequestNonce: NtpControlOpcode = NtpControlOpcode(12);
+    pub const UnsetTrap: NtpControlOpcode = NtpControlOpcode(31);
+}
+
+/// An NTP control message (mode 6)
+#[derive(Clone, Debug, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpControlMessage<'a> {
+    #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
+    #[nom(Value(b0 >> 6))]
+    pub li: u8,
+    #[nom(Value((b0 >> 3) & 0b111))]
+    pub version: u8,
+    #[nom(Value(NtpMode(b0 & 0b111)))]
+    pub mode: NtpMode,
+    /// Response bit: set in responses, clear in requests
+    #[nom(PreExec = "let (i, b1) = be_u8(i)?;")]
+    #[nom(Value(b1 & 0x80 != 0))]
+    pub response: bool,
+    /// Error bit: set in responses to report an error
+    #[nom(Value(b1 & 0x40 != 0))]
+    pub error: bool,
+    /// More bit: set if the response is fragmented and more fragments follow
+    #[nom(Value(b1 & 0x20 != 0))]
+    pub more: bool,
+    #[nom(Value(NtpControlOpcode(b1 & 0x1f)))]
+    pub opcode: NtpControlOpcode,
+    pub sequence: u16,
+    pub status: u16,
+    pub association_id: u16,
+    /// Offset of the data in the complete (reassembled) response
+    pub offset: u16,
+    /// Length of the data, in bytes
+    pub count: u16,
+    #[nom(Parse = "take(count)")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, serialize_with = "crate::serde_util::serialize_bytes")
+    )]
+    pub data: &'a [u8],
+    /// Padding of the data, before the authenticator
+    ///
+    /// The data is padded to a 4-byte boundary. The reference implementation pads
+    /// authenticated messages to an 8-byte boundary.
+    #[nom(PreExec = "let (i, (padding, auth)) = take_padding_and_mac(count, i)?;")]
+    #[nom(Value(padding))]
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, serialize_with = "crate::serde_util::serialize_bytes")
+    )]
+    pub padding: &'a [u8],
+    /// Authenticator (MAC), following the padding: 20 bytes (MD5) or 24 bytes (SHA-1)
+    #[nom(Value(auth))]
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub auth: Option<NtpMac<'a>>,
+}
+
+// Split the data following the message data into the padding and the MAC.
+//
+// The MAC follows the data padded to an 8-byte boundary (of the whole message, as sent by the
+// reference implementation) or to a 4-byte boundary, and is 20 bytes (MD5) or 24 bytes (SHA-1)
+// long. A SHA-1 MAC after 4-byte padding has the length of an MD5 MAC after 8-byte padding
+// (for ex. with 5 bytes of data): the latter is assumed. Without a MAC, the padding is only required before an authenticator, so accept
+// packets where it is missing or truncated.
+fn take_padding_and_mac(count: u16, i: &[u8]) -> IResult<&[u8], (&[u8], Option<NtpMac<'_>>)> {
+    let pad4 = (4 - (count as usize % 4)) % 4;
+    let pad8 = (8 - (12 + count as usize) % 8) % 8;
+    for &len in &[pad8, pad4] {
+        if let Some(20) | Some(24) = i.len().checked_sub(len) {
+            let (padding, mac) = i.split_at(len);
+            let (digest, key_id) = be_u32(mac)?;
+            let mac = NtpMac {
+                key_id,
+                mac: digest,
+            };
+            return Ok((&digest[digest.len()..], (padding, Some(mac))));
+        }
+    }
+    let (rem, padding) = take(pad4.min(i.len()))(i)?;
+    Ok((rem, (padding, None)))
+}
+
+impl<'a> NtpControlMessage<'a> {
+    /// Return true if this message is a response reporting that the request failed
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        self.response && self.error
+    }
+
+    /// Return the error code of an error response, or `None` if this is not an error response
+    ///
+    /// The error code is stored in the high byte of the status word. Codes defined by
+    /// RFC 9327 are: 0 (unspecified), 1 (authentication failure), 2 (invalid message length or
+    /// format), 3 (invalid opcode), 4 (unknown association identifier), 5 (unknown variable
+    /// name), 6 (invalid variable value) and 7 (administratively prohibited).
+    pub fn error_code(&self) -> Option<u8> {
+        if self.is_error() {
+            Some((self.status >> 8) as u8)
+        } else {
+            None
+        }
+    }
+
+    /// Decode the variables of a "read clock variables" response
+    ///
+    /// The data of the response is an ASCII string of comma-separated `name=value` pairs,
+    /// as displayed by `ntpq -c cv`. Surrounding whitespace and double quotes are removed from
+    /// values, and variables without a value have an empty value.
+    ///
+    /// Returns `None` if this message is not a "read clock variables" response, or if the data
+    /// is not valid UTF-8.
+    pub fn parse_clock_variables(&self) -> Option<Vec<(&'a str, &'a str)>> {
+        if !self.response || self.opcode != NtpControlOpcode::ReadClockVariables {
+            return None;
+        }
+        parse_variables(self.data)
+    }
+
+    /// Decode the assignments of a "write variables" or "write clock variables" request
+    ///
+    /// The data of the request has the same format as a read response (comma-separated
+    /// `name=value` pairs, see [`parse_clock_variables`](Self::parse_clock_variables)), and
+    /// lists the variables the client attempts to set, for the association given by
+    /// `association_id` (0 for the system).
+    ///
+    /// Returns `None` if this message is not a write request, or if the data is not valid
+    /// UTF-8.
+    pub fn parse_write_variables(&self) -> Option<Vec<(&'a str, &'a str)>> {
+        let write = self.opcode == NtpControlOpcode::WriteVariables
+            || self.opcode == NtpControlOpcode::WriteClockVariables;
+        if self.response || !write {
+            return None;
+        }
+        parse_variables(self.data)
+    }
+
+    /// Decode the system status word of a response for the system
+    ///
+    /// Returns `None` for requests, error responses, and responses for an association (which
+    /// carry a peer status word).
+    pub fn system_status(&self) -> Option<SystemStatus> {
+        if !self.response || self.error || self.association_id != 0 {
+            return None;
+        }
+        Some(SystemStatus::from_word(self.status))
+    }
+
+    /// Check that the leap indicator of the system status word matches the leap indicator
+    /// of the header
+    ///
+    /// The reference implementation copies the leap indicator of the system in both places,
+    /// so a mismatch indicates a malformed or forged response. Returns `None` if the message
+    /// has no system status word (see [`system_status`](Self::system_status)).
+    pub fn leap_consistent(&self) -> Option<bool> {
+        self.system_status()
+            .map(|status| status.leap == LeapIndicator(self.li))
+    }
+
+    /// Decode the association identifiers and peer status words of a "read status" response
+    ///
+    /// The data of a response for the system (association identifier 0) is a list of 16-bit
+    /// association identifiers, each followed by the status word of the association.
+    ///
+    /// Returns `None` if this message is not such a response, or if the length of the data is
+    /// not a multiple of 4.
+    pub fn parse_peer_statuses(&self) -> Option<Vec<(u16, PeerStatus)>> {
+        if !self.response || self.opcode != NtpControlOpcode::ReadStatus || self.association_id != 0
+        {
+            return None;
+        }
+        let chunks = self.data.chunks_exact(4);
+        if !chunks.remainder().is_empty() {
+            return None;
+        }
+        let statuses = chunks
+            .map(|c| {
+                let id = u16::from_be_bytes([c[0], c[1]]);
+                let word = u16::from_be_bytes([c[2], c[3]]);
+                (id, PeerStatus::from_word(word))
+            })
+            .collect();
+        Some(statuses)
+    }
+}
+
+/// Decoded system status word ([RFC9327] section 2.1)
+///
+/// This is the status word of responses for the system (association identifier 0).
+///
+/// [RFC9327]: https://tools.ietf.org/html/rfc9327
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SystemStatus {
+    pub leap: LeapIndicator,
+    /// Source of synchronization (6 bits, for ex. 6 for an NTP server, 4 for a satellite
+    /// receiver such as GPS, 1 for an atomic clock or a PPS signal)
+    pub clock_source: u8,
+    /// Number of events since the last report (4 bits)
+    pub event_count: u8,
+    /// Code of the last event (4 bits)
+    pub event_code: u8,
+}
+
+impl SystemStatus {
+    /// Decode a system status word
+    pub fn from_word(word: u16) -> SystemStatus {
+        SystemStatus {
+            leap: LeapIndicator((word >> 14) as u8),
+            clock_source: ((word >> 8) & 0x3f) as u8,
+            event_count: ((word >> 4) & 0xf) as u8,
+            event_code: (word & 0xf) as u8,
+        }
+    }
+}
+
+/// Selection state of a peer, as reported in the peer status word
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PeerSelection {
+    /// Rejected (unreachable or invalid)
+    Reject,
+    /// Discarded by the intersection algorithm (falseticker)
+    Falseticker,
+    /// Discarded by the table overflow (not used)
+    Excess,
+    /// Discarded by the cluster algorithm (outlier)
+    Outlier,
+    /// Included by the combine algorithm (candidate)
+    Candidate,
+    /// Backup (more than the maximum number of sources)
+    Backup,
+    /// System peer
+    SysPeer,
+    /// PPS peer (when the prefer peer is valid)
+    PpsPeer,
+}
+
+impl PeerSelection {
+    /// Return the tally code displayed by `ntpq -p` for this state
+    pub fn tally_code(&self) -> char {
+        match self {
+            PeerSelection::Reject => ' ',
+            PeerSelection::Falseticker => 'x',
+            PeerSelection::Excess => '.',
+            PeerSelection::Outlier => '-',
+            PeerSelection::Candidate => '+',
+            PeerSelection::Backup => '#',
+            PeerSelection::SysPeer => '*',
+            PeerSelection::PpsPeer => 'o',
+        }
+    }
+}
+
+/// Decoded peer status word of an association ([RFC9327] section 2.1)
+///
+/// [RFC9327]: https://tools.ietf.org/html/rfc9327
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PeerStatus {
+    /// The association is persistent (configured)
+    pub configured: bool,
+    /// Authentication is enabled
+    pub auth_enabled: bool,
+    /// Authentication succeeded
+    pub authentic: bool,
+    /// The peer is reachable
+    pub reachable: bool,
+    /// The association is a broadcast association
+    pub broadcast: bool,
+    pub selection: PeerSelection,
+    /// Number of events since the last report (4 bits)
+    pub event_count: u8,
+    /// Code of the last event (4 bits)
+    pub event_code: u8,
+}
+
+impl PeerStatus {
+    /// Decode a peer status word
+    pub fn from_word(word: u16) -> PeerStatus {
+        let selection = match (word >> 8) & 0b111 {
+            0 => PeerSelection::Reject,
+            1 => PeerSelection::Falseticker,
+            2 => PeerSelection::Excess,
+            3 => PeerSelection::Outlier,
+            4 => PeerSelection::Candidate,
+            5 => PeerSelection::Backup,
+            6 => PeerSelection::SysPeer,
+            _ => PeerSelection::PpsPeer,
+        };
+        PeerStatus {
+            configured: word & 0x8000 != 0,
+            auth_enabled: word & 0x4000 != 0,
+            authentic: word & 0x2000 != 0,
+            reachable: word & 0x1000 != 0,
+            broadcast: word & 0x0800 != 0,
+            selection,
+            event_count: ((word >> 4) & 0xf) as u8,
+            event_code: (word & 0xf) as u8,
+        }
+    }
+}
+
+/// Split a list of comma-separated `name=value` pairs
+///
+/// Commas inside double-quoted values are not separators.
+fn parse_variables(data: &[u8]) -> Option<Vec<(&str, &str)>> {
+    let data = core::str::from_utf8(data).ok()?;
+    let mut vars = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (idx, c) in data.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                push_variable(&mut vars, &data[start..idx]);
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    push_variable(&mut vars, &data[start..]);
+    Some(vars)
+}
+
+fn push_variable<'a>(vars: &mut Vec<(&'a str, &'a str)>, item: &'a str) {
+    let item = item.trim();
+    if item.is_empty() {
+        return;
+    }
+    let (name, value) = match item.find('=') {
+        Some(idx) => (&item[..idx], &item[idx + 1..]),
+        None => (item, ""),
+    };
+    let value = value.trim();
+    let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    };
+    vars.push((name.trim(), value));
+}
+
+// Fragments of a response, indexed by offset
+#[derive(Clone, Debug, Default)]
+struct Fragments {
+    pieces: BTreeMap<u16, Vec<u8>>,
+    total: Option<usize>,
+    // order of creation, to evict the oldest response first
+    id: u64,
+    // total length of the pieces
+    len: usize,
+}
+
+impl Fragments {
+    // Return the reassembled data, if all fragments were received
+    fn assemble(&self) -> Option<Vec<u8>> {
+        let total = self.total?;
+        let mut data = Vec::with_capacity(total);
+        for (&offset, piece) in &self.pieces {
+            if usize::from(offset) != data.len() {
+                return None;
+            }
+            data.extend_from_slice(piece);
+        }
+        if data.len() == total {
+            Some(data)
+        } else {
+            None
+        }
+    }
+}
+
+/// Default maximum number of incomplete responses kept by a [`ControlReassembler`]
+pub const CONTROL_MAX_PENDING_RESPONSES: usize = 64;
+
+/// Default maximum number of bytes buffered by a [`ControlReassembler`]
+pub const CONTROL_MAX_BUFFERED_BYTES: usize = 1 << 20;
+
+/// Reassembler of fragmented control responses
+///
+/// Large responses are sent in several messages, each containing a part of the data at the
+/// given `offset`. The `more` bit is set in all fragments except the last one. Fragments are
+/// grouped by association identifier and sequence number, and can be received in any order.
+///
+/// Only the offset and the count of each fragment are limited to 16 bits: the reassembled
+/// data can exceed 65535 bytes, up to the last offset plus the length of the last fragment.
+///
+/// Responses which are never completed (for ex. lost, duplicated or overlapping fragments)
+/// are kept until [`clear`](Self::clear) is called, within limits: when there are too many
+/// incomplete responses, or too many buffered bytes (see [`with_limits`](Self::with_limits)),
+/// the oldest responses are dropped.
+#[derive(Clone, Debug)]
+pub struct ControlReassembler {
+    responses: BTreeMap<(u16, u16), Fragments>,
+    max_responses: usize,
+    max_bytes: usize,
+    buffered: usize,
+    next_id: u64,
+}
+
+impl Default for ControlReassembler {
+    fn default() -> Self {
+        ControlReassembler::with_limits(CONTROL_MAX_PENDING_RESPONSES, CONTROL_MAX_BUFFERED_BYTES)
+    }
+}
+
+impl ControlReassembler {
+    /// Create an empty reassembler, with the default limits
+    ///
+    /// See [`CONTROL_MAX_PENDING_RESPONSES`] and [`CONTROL_MAX_BUFFERED_BYTES`].
+    pub fn new() -> ControlReassembler {
+        ControlReassembler::default()
+    }
+
+    /// Create an empty reassembler, keeping at most `max_responses` incomplete responses and
+    /// `max_bytes` bytes of fragments
+    pub fn with_limits(max_responses: usize, max_bytes: usize) -> ControlReassembler {
+        ControlReassembler {
+            responses: BTreeMap::new(),
+            max_responses: max_responses.max(1),
+            max_bytes,
+            buffered: 0,
+            next_id: 0,
+        }
+    }
+
+    /// Add a fragment, and return the complete data of the response if all fragments were
+    /// received
+    ///
+    /// Messages which are not responses are ignored, and so are fragments larger than the
+    /// byte limit. If the limits are exceeded, the oldest incomplete responses are dropped.
+    pub fn add(&mut self, msg: &NtpControlMessage) -> Option<Vec<u8>> {
+        if !msg.response || msg.data.len() > self.max_bytes {
+            return None;
+        }
+        let key = (msg.association_id, msg.sequence);
+        if !self.responses.contains_key(&key) {
+            while self.responses.len() >= self.max_responses {
+                self.evict_oldest();
+            }
+            let fragments = Fragments {
+                id: self.next_id,
+                ..Fragments::default()
+            };
+            self.next_id += 1;
+            self.responses.insert(key, fragments);
+        }
+        let fragments = self.responses.get_mut(&key)?;
+        if let Some(old) = fragments.pieces.insert(msg.offset, msg.data.to_vec()) {
+            fragments.len -= old.len();
+            self.buffered -= old.len();
+        }
+        fragments.len += msg.data.len();
+        self.buffered += msg.data.len();
+        if !msg.more {
+            fragments.total = Some(usize::from(msg.offset) + msg.data.len());
+        }
+        if let Some(data) = fragments.assemble() {
+            self.remove(key);
+            return Some(data);
+        }
+        while self.buffered > self.max_bytes {
+            self.evict_oldest();
+        }
+        None
+    }
+
+    /// Return the number of incomplete responses
+    pub fn pending(&self) -> usize {
+        self.responses.len()
+    }
+
+    /// Return the number of bytes of the fragments of incomplete responses
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered
+    }
+
+    /// Drop all incomplete responses
+    pub fn clear(&mut self) {
+        self.responses.clear();
+        self.buffered = 0;
+    }
+
+    // Remove the oldest incomplete response
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .responses
+            .iter()
+            .min_by_key(|(_, fragments)| fragments.id)
+            .map(|(&key, _)| key);
+        if let Some(key) = oldest {
+            self.remove(key);
+        }
+    }
+
+    fn remove(&mut self, key: (u16, u16)) {
+        if let Some(fragments) = self.responses.remove(&key) {
+            self.buffered -= fragments.len;
+        }
+    }
+}
+
+/// Parse an NTP control message (mode 6)
+#[inline]
+pub fn parse_ntp_control(i: &[u8]) -> IResult<&[u8], NtpControlMessage<'_>> {
+    NtpControlMessage::parse(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp::{parse_ntp, NtpPacket};
+
+    // read clock variables response, 46 bytes of data and 2 bytes of padding
+    static NTP_CTL_CV: &[u8] = &[
+        0x16, 0x84, 0x00, 0x02, 0x00, 0x00, 0x12, 0x34, 0x00, 0x00, 0x00, 0x2e, 0x64, 0x65, 0x76,
+        0x69, 0x63, 0x65, 0x3d, 0x22, 0x47, 0x50, 0x53, 0x2c, 0x20, 0x4e, 0x4d, 0x45, 0x41, 0x22,
+        0x2c, 0x20, 0x70, 0x6f, 0x6c, 0x6c, 0x3d, 0x31, 0x36, 0x2c, 0x0d, 0x0a, 0x6e, 0x6f, 0x72,
+        0x65, 0x70, 0x6c, 0x79, 0x3d, 0x30, 0x2c, 0x20, 0x66, 0x6c, 0x61, 0x67, 0x73, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_ntp_control_from_parse_ntp() {
+        let (rem, pkt) = parse_ntp(NTP_CTL_CV).expect("parsing failed");
+        assert!(rem.is_empty());
+        let msg = match pkt {
+            NtpPacket::Control(msg) => msg,
+            _ => panic!("unexpected packet type"),
+        };
+        assert_eq!(msg.opcode, NtpControlOpcode::ReadClockVariables);
+        assert_eq!(msg.count, 46);
+        // count larger than the remaining data
+        assert!(parse_ntp(&NTP_CTL_CV[..40]).is_err());
+    }
+
+    #[test]
+    fn test_ntp_control_clock_variables() {
+        let (rem, msg) = parse_ntp_control(NTP_CTL_CV).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(msg.version, 2);
+        assert_eq!(msg.mode, NtpMode::NtpControlMessage);
+        assert!(msg.response);
+        assert!(!msg.error);
+        assert!(!msg.is_error());
+        assert_eq!(msg.error_code(), None);
+        assert!(!msg.more);
+        assert_eq!(msg.opcode, NtpControlOpcode::ReadClockVariables);
+        assert_eq!(msg.sequence, 2);
+        assert_eq!(msg.association_id, 0x1234);
+        assert_eq!(msg.count, 46);
+        assert_eq!(msg.data, &NTP_CTL_CV[12..58]);
+        assert_eq!(msg.padding, &[0, 0]);
+        assert!(msg.auth.is_none());
+        let vars = msg
+            .parse_clock_variables()
+            .expect("decoding variables failed");
+        assert_eq!(
+            vars,
+            vec![
+                ("device", "GPS, NMEA"),
+                ("poll", "16"),
+                ("noreply", "0"),
+                ("flags", "")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ntp_control_write_variables() {
+        let data = b"leap=01, tai=37";
+        let mut buf = vec![0x16, 0x03, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        buf.extend_from_slice(data);
+        buf.push(0);
+        let (rem, msg) = parse_ntp_control(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(msg.opcode, NtpControlOpcode::WriteVariables);
+        let vars = msg
+            .parse_write_variables()
+            .expect("decoding variables failed");
+        assert_eq!(vars, vec![("leap", "01"), ("tai", "37")]);
+        assert_eq!(msg.parse_clock_variables(), None);
+        // a response to a write request
+        let mut response = buf.clone();
+        response[1] |= 0x80;
+        let (_, msg) = parse_ntp_control(&response).expect("parsing failed");
+        assert_eq!(msg.parse_write_variables(), None);
+    }
+
+    #[test]
+    fn test_ntp_control_authenticated() {
+        // read variables request with 5 bytes of data, padded to 8 bytes, and a MAC
+        let mut buf = vec![
+            0x16, 0x02, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05,
+        ];
+        buf.extend_from_slice(b"strat");
+        buf.extend_from_slice(&[0; 7]);
+        buf.extend_from_slice(&[0x00, 0x00, 0x00, 0x2a]);
+        buf.extend((0..16).map(|b| b as u8));
+        let (rem, msg) = parse_ntp_control(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(msg.data, b"strat");
+        assert_eq!(msg.padding, &[0; 7]);
+        let auth = msg.auth.expect("missing MAC");
+        assert_eq!(auth.key_id, 42);
+        assert_eq!(auth.mac, &buf[28..]);
+        // count larger than the remaining data
+        assert!(parse_ntp_control(&buf[..15]).is_err());
+        // SHA-1 key: 24-byte MAC
+        let mut buf = buf[..24].to_vec();
+        buf.extend_from_slice(&[0x00, 0x00, 0x00, 0x2b]);
+        buf.extend((0..20).map(|b| b as u8));
+        let (rem, msg) = parse_ntp_control(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(msg.padding, &[0; 7]);
+        let auth = msg.auth.expect("missing MAC");
+        assert_eq!(auth.key_id, 43);
+        assert_eq!(auth.mac, &buf[28..]);
+        // data padded to 4 bytes only, MD5 key
+        let buf = [&buf[..20], &buf[24..44]].concat();
+        let (rem, msg) = parse_ntp_control(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(msg.padding, &[0; 3]);
+        assert_eq!(msg.auth.map(|m| (m.key_id, m.mac.len())), Some((43, 16)));
+    }
+
+    // Build a fragment of a read variables response
+    fn fragment(offset: u16, data: &[u8], more: bool) -> NtpControlMessage<'_> {
+        NtpControlMessage {
+            li: 0,
+            version: 2,
+            mode: NtpMode::NtpControlMessage,
+            response: true,
+            error: false,
+            more,
+            opcode: NtpControlOpcode::ReadVariables,
+            sequence: 7,
+            status: 0,
+            association_id: 0,
+            offset,
+            count: data.len() as u16,
+            data,
+            padding: &[],
+            auth: None,
+        }
+    }
+
+    #[test]
+    fn test_ntp_control_reassembly() {
+        let mut reassembler = ControlReassembler::new();
+        // fragments received out of order
+        assert_eq!(reassembler.add(&fragment(11, b"stratum=2", false)), None);
+        assert_eq!(reassembler.pending(), 1);
+        let data = reassembler.add(&fragment(0, b"version=4, ", true));
+        assert_eq!(data.as_deref(), Some(&b"version=4, stratum=2"[..]));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn test_ntp_control_reassembly_large() {
+        let data: Vec<u8> = (0..70_000u32).map(|i| (i % 251) as u8).collect();
+        let mut reassembler = ControlReassembler::new();
+        assert_eq!(reassembler.add(&fragment(0, &data[..30_000], true)), None);
+        assert_eq!(
+            reassembler.add(&fragment(60_000, &data[60_000..], false)),
+            None
+        );
+        let res = reassembler.add(&fragment(30_000, &data[30_000..60_000], true));
+        assert_eq!(res, Some(data));
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn test_ntp_control_reassembly_limits() {
+        let mut reassembler = ControlReassembler::with_limits(2, 16);
+        // duplicate fragments are counted once
+        for _ in 0..3 {
+            assert_eq!(reassembler.add(&fragment(0, b"version=4, ", true)), None);
+        }
+        assert_eq!(
+            (reassembler.pending(), reassembler.buffered_bytes()),
+            (1, 11)
+        );
+        // too many pending responses: the oldest is dropped
+        let mut msg = fragment(0, b"abc", true);
+        for sequence in 3..6 {
+            msg.sequence = sequence;
+            assert_eq!(reassembler.add(&msg), None);
+        }
+        assert_eq!(
+            (reassembler.pending(), reassembler.buffered_bytes()),
+            (2, 6)
+        );
+        // too many bytes
+        msg.sequence = 6;
+        msg.data = b"0123456789abcdef";
+        assert_eq!(reassembler.add(&msg), None);
+        assert_eq!(
+            (reassembler.pending(), reassembler.buffered_bytes()),
+            (1, 16)
+        );
+        msg.data = b"0123456789abcdefg";
+        assert_eq!(reassembler.add(&msg), None);
+        assert_eq!(reassembler.buffered_bytes(), 16);
+        reassembler.clear();
+        assert_eq!(
+            (reassembler.pending(), reassembler.buffered_bytes()),
+            (0, 0)
+        );
+        // a response can still be completed
+        assert_eq!(reassembler.add(&fragment(11, b"stratum=2", false)), None);
+        let data = reassembler.add(&fragment(0, b"version=4, ", true));
+        assert_eq!(data.as_deref(), Some(&b"version=4, stratum=2"[..]));
+    }
+
+    #[test]
+    fn test_ntp_control_peer_status() {
+        // read status response with two associations
+        let bytes = &[
+            0x16, 0x81, 0x00, 0x01, 0x06, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x08, 0xa1, 0xb2,
+            0x96, 0x1a, 0xa1, 0xb3, 0x94, 0x14,
+        ];
+        let (_, msg) = parse_ntp_control(bytes).expect("parsing failed");
+        let statuses = msg.parse_peer_statuses().expect("decoding failed");
+        assert_eq!(statuses.len(), 2);
+        let (id, status) = statuses[0];
+        assert_eq!(id, 0xa1b2);
+        assert!(status.configured && status.reachable && !status.authentic);
+        assert_eq!(status.selection, PeerSelection::SysPeer);
+        assert_eq!(status.selection.tally_code(), '*');
+        assert_eq!(status.event_count, 1);
+        assert_eq!(status.event_code, 0xa);
+        assert_eq!(statuses[1].1.selection, PeerSelection::Candidate);
+        assert_eq!(msg.parse_clock_variables(), None);
+    }
+
+    #[test]
+    fn test_ntp_control_system_status() {
+        // read status response for the system: leap 1, NTP server (6), 2 events, last event 3
+        let mut bytes = vec![
+            0x56, 0x81, 0x00, 0x01, 0x46, 0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (_, msg) = parse_ntp_control(&bytes).expect("parsing failed");
+        let status = msg.system_status().expect("missing status");
+        assert_eq!(status.leap, LeapIndicator::LastMinute61);
+        assert_eq!(status.clock_source, 6);
+        assert_eq!(status.event_count, 2);
+        assert_eq!(status.event_code, 3);
+        assert_eq!(msg.leap_consistent(), Some(true));
+        // leap indicator 0 in the header
+        bytes[0] = 0x16;
+        let (_, msg) = parse_ntp_control(&bytes).expect("parsing failed");
+        assert_eq!(msg.leap_consistent(), Some(false));
+        // request
+        bytes[1] = 0x01;
+        let (_, msg) = parse_ntp_control(&bytes).expect("parsing failed");
+        assert_eq!(msg.system_status(), None);
+    }
+
+    #[test]
+    fn test_ntp_control_error() {
+        // error response to a read variables request: unknown association identifier
+        let bytes = &[
+            0x16, 0xc2, 0x00, 0x03, 0x04, 0x00, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let (rem, msg) = parse_ntp_control(bytes).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(msg.is_error());
+        assert_eq!(msg.opcode, NtpControlOpcode::ReadVariables);
+        assert_eq!(msg.status, 0x0400);
+        assert_eq!(msg.error_code(), Some(4));
+        assert!(msg.data.is_empty());
+    }
+}
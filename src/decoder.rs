@@ -0,0 +1,88 @@
+//! Decoding of NTP packets from a stream
+
+use crate::error::NtpError;
+use crate::ntp::parse_ntp_complete_e;
+use crate::owned::NtpPacketOwned;
+use alloc::vec::Vec;
+use nom::error::ErrorKind;
+
+/// Decoder of NTP packets received over a stream transport, in successive chunks
+///
+/// NTP packets do not carry their length, so a stream transport needs framing: each packet is
+/// expected to be preceded by its length, as a 16-bit big-endian integer (as for DNS over
+/// TCP). Incomplete frames are buffered until the next call to [`decode`](Self::decode).
+#[derive(Clone, Debug, Default)]
+pub struct NtpDecoder {
+    buffer: Vec<u8>,
+}
+
+impl NtpDecoder {
+    /// Create a decoder with an empty buffer
+    pub fn new() -> NtpDecoder {
+        NtpDecoder::default()
+    }
+
+    /// Add `chunk` to the buffered data, and decode all complete frames
+    ///
+    /// A result is returned for each complete frame, in order: the packet, or the error if
+    /// the frame is not a valid packet. Frames are consumed even if they are invalid, so an
+    /// error does not prevent the decoding of the following frames.
+    pub fn decode(&mut self, chunk: &[u8]) -> Vec<Result<NtpPacketOwned, NtpError>> {
+        self.buffer.extend_from_slice(chunk);
+        let mut results = Vec::new();
+        let mut offset = 0;
+        while let Some(header) = self.buffer.get(offset..offset + 2) {
+            let len = usize::from(u16::from_be_bytes([header[0], header[1]]));
+            let frame = match self.buffer.get(offset + 2..offset + 2 + len) {
+                Some(frame) => frame,
+                None => break,
+            };
+            let res = match parse_ntp_complete_e::<NtpError>(frame) {
+                Ok((rem, _)) if !rem.is_empty() => Err(NtpError::Nom(ErrorKind::Eof)),
+                Ok((_, pkt)) => Ok(pkt.into_owned()),
+                Err(e) => Err(NtpError::from(e)),
+            };
+            results.push(res);
+            offset += 2 + len;
+        }
+        self.buffer.drain(..offset);
+        results
+    }
+
+    /// Return the number of buffered bytes, not yet decoded
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decoder() {
+        let mut pkt = vec![0x23];
+        pkt.resize(48, 0);
+        let mut stream = Vec::new();
+        for p in [&pkt[..], &pkt[..10]] {
+            stream.extend_from_slice(&(p.len() as u16).to_be_bytes());
+            stream.extend_from_slice(p);
+        }
+        stream.extend_from_slice(&(pkt.len() as u16).to_be_bytes());
+        stream.extend_from_slice(&pkt);
+        let mut decoder = NtpDecoder::new();
+        // first packet, split across two chunks
+        assert!(decoder.decode(&stream[..1]).is_empty());
+        assert!(decoder.decode(&stream[1..40]).is_empty());
+        let res = decoder.decode(&stream[40..51]);
+        assert_eq!(res.len(), 1);
+        assert!(res[0].is_ok());
+        assert_eq!(decoder.pending(), 1);
+        // invalid packet, and third packet
+        let res = decoder.decode(&stream[51..]);
+        assert_eq!(res.len(), 2);
+        assert!(res[0].is_err());
+        assert!(res[1].is_ok());
+        assert_eq!(decoder.pending(), 0);
+    }
+}
@@ -0,0 +1,186 @@
+//! Human-readable description of NTP packets
+
+use crate::error::NtpError;
+use crate::ntp::{parse_ntp_e, NtpPacket};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+
+/// Parse an NTP packet (version 1 to 4, or a control message), and return a multi-line
+/// description
+///
+/// This is intended for command-line tools: each line describes one field (or group of
+/// fields), as `name: value`. The reference identifier is interpreted according to the
+/// stratum, and timestamps are displayed as ISO 8601 dates. The format of the description is
+/// not stable, and should not be parsed.
+pub fn describe(i: &[u8]) -> Result<String, NtpError> {
+    let (_, pkt) = parse_ntp_e::<NtpError>(i)?;
+    let mut out = String::new();
+    // writing to a String cannot fail
+    let _ = write_description(&mut out, &pkt);
+    Ok(out)
+}
+
+// Write the fields common to versions 3 and 4
+macro_rules! write_header {
+    ($out:expr, $pkt:expr) => {{
+        let pkt = $pkt;
+        writeln!($out, "version: {}", pkt.version)?;
+        writeln!($out, "mode: {}", pkt.mode)?;
+        writeln!($out, "leap indicator: {}", pkt.li)?;
+        writeln!(
+            $out,
+            "stratum: {} ({})",
+            pkt.stratum,
+            pkt.stratum_description()
+        )?;
+        writeln!($out, "poll: {}", pkt.poll)?;
+        writeln!($out, "precision: {}", pkt.precision)?;
+        writeln!($out, "root delay: {:.6} s", pkt.root_delay.to_seconds())?;
+        writeln!(
+            $out,
+            "root dispersion: {:.6} s",
+            pkt.root_dispersion.to_seconds()
+        )?;
+        let b = pkt.ref_id.to_be_bytes();
+        let ref_id = (
+            pkt.stratum,
+            pkt.kiss_code(),
+            pkt.ref_id_as_ascii(),
+            pkt.local_refclock(),
+        );
+        match ref_id {
+            (_, _, _, Some(clock)) => writeln!(
+                $out,
+                "reference id: local clock type {} unit {}",
+                clock.clock_type, clock.unit
+            )?,
+            (0, Some(code), _, _) => writeln!($out, "reference id: kiss code {:?}", code)?,
+            (0..=1, _, Some(s), _) => writeln!($out, "reference id: {}", s)?,
+            (2..=15, _, _, _) => {
+                writeln!($out, "reference id: {}.{}.{}.{}", b[0], b[1], b[2], b[3])?
+            }
+            _ => writeln!($out, "reference id: {:#010x}", pkt.ref_id)?,
+        }
+        let timestamps = [
+            ("reference", pkt.ts_ref),
+            ("origin", pkt.ts_orig),
+            ("receive", pkt.ts_recv),
+            ("transmit", pkt.ts_xmit),
+        ];
+        for (name, ts) in timestamps.iter() {
+            writeln!($out, "{} timestamp: {}", name, ts)?;
+        }
+    }};
+}
+
+fn write_description(out: &mut String, pkt: &NtpPacket) -> fmt::Result {
+    match pkt {
+        NtpPacket::V3(pkt) => {
+            write_header!(out, pkt);
+            match pkt.authenticator {
+                Some(auth) => writeln!(out, "authentication: {}-byte authenticator", auth.len()),
+                None => writeln!(out, "authentication: none"),
+            }
+        }
+        NtpPacket::V4(pkt) => {
+            write_header!(out, pkt);
+            let types: Vec<String> = pkt
+                .extensions
+                .iter()
+                .map(|e| format!("{:#06x}", e.field_type))
+                .collect();
+            writeln!(out, "extensions: {} [{}]", types.len(), types.join(", "))?;
+            match &pkt.auth {
+                Some(mac) => writeln!(
+                    out,
+                    "authentication: key {}, {}-byte digest",
+                    mac.key_id,
+                    mac.mac.len()
+                ),
+                None => writeln!(out, "authentication: none"),
+            }
+        }
+        NtpPacket::Control(msg) => {
+            writeln!(out, "version: {}", msg.version)?;
+            writeln!(out, "mode: {}", msg.mode)?;
+            writeln!(out, "leap indicator: {}", msg.li)?;
+            writeln!(
+                out,
+                "response: {}, error: {}, more: {}",
+                msg.response, msg.error, msg.more
+            )?;
+            writeln!(out, "opcode: {}", msg.opcode.0)?;
+            writeln!(out, "sequence: {}", msg.sequence)?;
+            writeln!(out, "status: {:#06x}", msg.status)?;
+            writeln!(out, "association id: {}", msg.association_id)?;
+            writeln!(out, "data: {} bytes at offset {}", msg.count, msg.offset)?;
+            match &msg.auth {
+                Some(mac) => writeln!(out, "authentication: key {}", mac.key_id),
+                None => writeln!(out, "authentication: none"),
+            }
+        }
+        #[cfg(feature = "ntpv5")]
+        NtpPacket::V5(pkt) => {
+            writeln!(out, "version: {}", pkt.version)?;
+            writeln!(out, "mode: {}", pkt.mode)?;
+            writeln!(out, "leap indicator: {}", pkt.li)?;
+            writeln!(out, "stratum: {}", pkt.stratum)?;
+            writeln!(out, "poll: {}", pkt.poll)?;
+            writeln!(out, "precision: {}", pkt.precision)?;
+            writeln!(out, "timescale: {}", pkt.timescale)?;
+            writeln!(out, "era: {}", pkt.era)?;
+            writeln!(out, "flags: {:#06x}", pkt.flags)?;
+            writeln!(out, "root delay: {:.6} s", pkt.root_delay_seconds())?;
+            writeln!(
+                out,
+                "root dispersion: {:.6} s",
+                pkt.root_dispersion_seconds()
+            )?;
+            writeln!(out, "server cookie: {:#018x}", pkt.server_cookie)?;
+            writeln!(out, "client cookie: {:#018x}", pkt.client_cookie)?;
+            writeln!(out, "receive timestamp: {}", pkt.ts_recv)?;
+            writeln!(out, "transmit timestamp: {}", pkt.ts_xmit)?;
+            let types: Vec<String> = pkt
+                .extensions
+                .iter()
+                .map(|e| format!("{:#06x}", e.field_type))
+                .collect();
+            writeln!(out, "extensions: {} [{}]", types.len(), types.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut buf = vec![0x24, 0x02, 0x06, 0xec];
+        buf.resize(48, 0);
+        buf[12..16].copy_from_slice(&[192, 168, 0, 1]);
+        buf[40..48].copy_from_slice(&(0xe98a_f870_u64 << 32).to_be_bytes());
+        let s = describe(&buf).expect("parsing failed");
+        let lines: Vec<&str> = s.lines().collect();
+        assert_eq!(lines[0], "version: 4");
+        assert_eq!(lines[1], "mode: server");
+        assert_eq!(lines[3], "stratum: 2 (secondary reference)");
+        assert_eq!(lines[8], "reference id: 192.168.0.1");
+        let mut local = buf.clone();
+        local[12..16].copy_from_slice(&[127, 127, 20, 1]);
+        let s = describe(&local).expect("parsing failed");
+        assert!(s.contains("\nreference id: local clock type 20 unit 1\n"));
+        assert_eq!(lines[9], "reference timestamp: unset");
+        assert_eq!(lines[12], "transmit timestamp: 2024-02-29T12:34:56.000000Z");
+        assert_eq!(lines[13], "extensions: 0 []");
+        assert_eq!(lines[14], "authentication: none");
+        assert!(describe(&buf[..10]).is_err());
+        let control = &[
+            0x16, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let s = describe(control).expect("parsing failed");
+        assert!(s.contains("\nopcode: 2\nsequence: 1\n"));
+    }
+}
@@ -0,0 +1,212 @@
+use nom::error::{ContextError, Error, ErrorKind, FromExternalError, ParseError};
+use nom::{Err, IResult, Needed, Offset};
+
+/// An error returned when parsing NTP data
+///
+/// Unlike the nom errors, this type does not borrow the input, so it can be stored or returned
+/// after the input has been dropped.
+///
+/// It can also be used directly as the error type of nom parsers, see [`NtpResult`]. The
+/// reasons for rejecting a packet (for ex. [`UnsupportedVersion`](Self::UnsupportedVersion))
+/// are reported by the generic parsers (for ex. [`parse_ntp_e`](crate::parse_ntp_e)) at the
+/// point of failure, other failures are [`Nom`](Self::Nom) errors. With the `thiserror`
+/// feature, it implements `std::error::Error`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+pub enum NtpError {
+    /// The input is too short, more data is needed
+    #[cfg_attr(feature = "thiserror", error("incomplete input ({0:?})"))]
+    Incomplete(Needed),
+    /// The version of the packet is not supported (0, or greater than 4; greater than 5 with
+    /// the `ntpv5` feature), see [`supported_versions`](crate::supported_versions)
+    #[cfg_attr(feature = "thiserror", error("unsupported version {0}"))]
+    UnsupportedVersion(u8),
+    /// An extension field is shorter than its header, or exceeds the packet, so the MAC cannot
+    /// be located
+    #[cfg_attr(feature = "thiserror", error("invalid extension field length"))]
+    InvalidExtensionLength,
+    /// The MAC is truncated (between 5 and 19 bytes), or the authenticator of a version 3
+    /// packet is neither 12 nor 20 bytes long
+    #[cfg_attr(feature = "thiserror", error("truncated MAC"))]
+    TruncatedMac,
+    /// A parser failed with the given error kind
+    #[cfg_attr(feature = "thiserror", error("parsing failed: {}", .0.description()))]
+    Nom(ErrorKind),
+}
+
+impl<I> ParseError<I> for NtpError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        NtpError::Nom(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> ContextError<I> for NtpError {}
+
+impl<I> FromExternalError<I, NtpError> for NtpError {
+    fn from_external_error(_input: I, _kind: ErrorKind, e: NtpError) -> Self {
+        e
+    }
+}
+
+/// The result of a nom parser returning an [`NtpError`]
+///
+/// The generic parsers (for ex. [`parse_ntp_e`](crate::parse_ntp_e)) return this type, with
+/// the error type as parameter: the default is [`NtpError`].
+pub type NtpResult<'a, T, E = NtpError> = IResult<&'a [u8], T, E>;
+
+impl From<Err<NtpError>> for NtpError {
+    fn from(e: Err<NtpError>) -> Self {
+        match e {
+            Err::Incomplete(n) => NtpError::Incomplete(n),
+            Err::Error(e) | Err::Failure(e) => e,
+        }
+    }
+}
+
+impl<I> From<Err<Error<I>>> for NtpError {
+    fn from(e: Err<Error<I>>) -> Self {
+        match e {
+            Err::Incomplete(n) => NtpError::Incomplete(n),
+            Err::Error(e) | Err::Failure(e) => NtpError::Nom(e.code),
+        }
+    }
+}
+
+/// An error returned when serializing a packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+pub enum SerializeError {
+    /// The output buffer is too small, `needed` bytes are required
+    #[cfg_attr(
+        feature = "thiserror",
+        error("buffer too small ({needed} bytes needed)")
+    )]
+    BufferTooSmall { needed: usize },
+    /// The value of an extension field is too long to be written with a 16-bit length, once
+    /// padded (more than 65528 bytes)
+    #[cfg_attr(
+        feature = "thiserror",
+        error("extension field too large ({len} bytes of value)")
+    )]
+    ExtensionTooLarge { len: usize },
+}
+
+/// An [`NtpError`], with the position in the input where it was detected
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "thiserror", derive(thiserror::Error))]
+#[cfg_attr(feature = "thiserror", error("{error} at offset {offset}"))]
+pub struct NtpErrorAt {
+    /// Offset of the failure, in bytes from the start of the input
+    ///
+    /// For incomplete input, this is the length of the input.
+    pub offset: usize,
+    pub error: NtpError,
+}
+
+/// An [`NtpError`], with the input at the position where it was detected
+///
+/// This is an error type for the generic parsers (for ex. [`parse_ntp_e`](crate::parse_ntp_e)),
+/// which keeps the position of the failure: see [`NtpErrorAt::from_located`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NtpLocatedError<I> {
+    pub input: I,
+    pub error: NtpError,
+}
+
+impl<I> ParseError<I> for NtpLocatedError<I> {
+    fn from_error_kind(input: I, kind: ErrorKind) -> Self {
+        NtpLocatedError {
+            input,
+            error: NtpError::Nom(kind),
+        }
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<I> ContextError<I> for NtpLocatedError<I> {}
+
+impl<I> FromExternalError<I, NtpError> for NtpLocatedError<I> {
+    fn from_external_error(input: I, _kind: ErrorKind, error: NtpError) -> Self {
+        NtpLocatedError { input, error }
+    }
+}
+
+impl NtpErrorAt {
+    /// Convert an error returned when parsing `input` with a generic parser, computing the
+    /// failure offset
+    ///
+    /// The error must have been produced by parsing `input` (or a subslice of it).
+    pub fn from_located(input: &[u8], e: Err<NtpLocatedError<&[u8]>>) -> Self {
+        match e {
+            Err::Incomplete(n) => NtpErrorAt {
+                offset: input.len(),
+                error: NtpError::Incomplete(n),
+            },
+            Err::Error(e) | Err::Failure(e) => NtpErrorAt {
+                offset: input.offset(e.input),
+                error: e.error,
+            },
+        }
+    }
+
+    /// Convert a nom error returned when parsing `input`, computing the failure offset
+    ///
+    /// The error must have been produced by parsing `input` (or a subslice of it).
+    pub fn from_nom(input: &[u8], e: Err<Error<&[u8]>>) -> Self {
+        let offset = match &e {
+            Err::Incomplete(_) => input.len(),
+            Err::Error(e) | Err::Failure(e) => input.offset(e.input),
+        };
+        NtpErrorAt {
+            offset,
+            error: NtpError::from(e),
+        }
+    }
+}
+
+/// An issue detected by the tolerant parser, which did not prevent parsing
+///
+/// Offsets are in bytes from the start of the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtpWarning {
+    /// The input is shorter than the 48-byte header
+    TruncatedHeader { len: usize },
+    /// The length of the extension field at `offset` is not a multiple of 4, and the field is
+    /// not followed by padding: the following fields are not aligned
+    MisalignedExtension { offset: usize },
+    /// The MAC at `offset` does not have the standard length (20 bytes)
+    UnexpectedMacLength { offset: usize, len: usize },
+    /// `len` bytes at `offset` are neither extension fields nor a MAC, and were ignored
+    TrailingBytes { offset: usize, len: usize },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nom::number::complete::be_u16;
+
+    #[test]
+    fn test_ntp_error_as_parse_error() {
+        let res = be_u16::<_, NtpError>(&[0u8][..]);
+        assert_eq!(res, Err(Err::Error(NtpError::Nom(ErrorKind::Eof))));
+    }
+
+    #[cfg(feature = "thiserror")]
+    #[test]
+    fn test_ntp_error_display() {
+        let e = NtpErrorAt {
+            offset: 48,
+            error: NtpError::Nom(ErrorKind::Eof),
+        };
+        assert_eq!(e.to_string(), "parsing failed: End of file at offset 48");
+        let e: &dyn std::error::Error = &e;
+        assert!(e.source().is_none());
+    }
+}
@@ -0,0 +1,410 @@
+//! Decoding of the content of NTP extension fields
+
+use crate::ntp::{extension_padding_len, parse_ntp_extension, NtpExtension, NtpV4Packet};
+use alloc::collections::BTreeSet;
+use nom::bytes::streaming::take;
+use nom::combinator::verify;
+use nom::number::streaming::be_u16;
+use nom::IResult;
+
+/// Extension field type of the Unique Identifier field ([RFC8915])
+///
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+pub const NTP_EXT_UNIQUE_IDENTIFIER: u16 = 0x0104;
+
+/// Extension field type of the NTS Cookie field ([RFC8915])
+///
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+pub const NTP_EXT_NTS_COOKIE: u16 = 0x0204;
+
+/// Extension field type of the NTS Cookie Placeholder field ([RFC8915])
+///
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+pub const NTP_EXT_NTS_COOKIE_PLACEHOLDER: u16 = 0x0304;
+
+/// Extension field type of the NTS Authenticator and Encrypted Extension Fields field
+/// ([RFC8915])
+///
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+pub const NTP_EXT_NTS_AUTHENTICATOR: u16 = 0x0404;
+
+/// Extension field type of the Checksum Complement field ([RFC7821])
+///
+/// [RFC7821]: https://tools.ietf.org/html/rfc7821
+pub const NTP_EXT_CHECKSUM_COMPLEMENT: u16 = 0x2005;
+
+/// Extension field type used by some servers to report their name
+///
+/// This is **not standard**: this type is not registered by IANA, it is taken from the range
+/// reserved for experimental use. The value is the server name, in UTF-8, possibly padded
+/// with NUL bytes.
+pub const NTP_EXT_SERVER_NAME: u16 = 0xf001;
+
+/// Decoded content of an NTP extension field
+#[derive(Clone, Debug, PartialEq)]
+pub enum NtpExtensionContent<'a> {
+    /// Checksum Complement ([RFC7821]), used to keep the UDP checksum unchanged when the packet
+    /// is modified by hardware timestamping
+    ///
+    /// [RFC7821]: https://tools.ietf.org/html/rfc7821
+    ChecksumComplement {
+        /// Padding preceding the checksum complement (must be zero)
+        mbz: &'a [u8],
+        checksum_complement: u16,
+    },
+    /// Server name, as reported by the server (non-standard, see [`NTP_EXT_SERVER_NAME`])
+    ServerName(&'a str),
+    /// Unique Identifier ([RFC8915]), at least 32 random bytes
+    ///
+    /// [RFC8915]: https://tools.ietf.org/html/rfc8915
+    UniqueIdentifier(&'a [u8]),
+    /// NTS Cookie, opaque to the client
+    NtsCookie(&'a [u8]),
+    /// NTS Cookie Placeholder, with the same length as a cookie (the content is ignored)
+    NtsCookiePlaceholder(&'a [u8]),
+    /// NTS Authenticator and Encrypted Extension Fields
+    ///
+    /// The nonce and the ciphertext are returned without their padding: their lengths are the
+    /// lengths encoded in the field.
+    NtsAuthenticator {
+        nonce: &'a [u8],
+        ciphertext: &'a [u8],
+    },
+    /// Unknown or malformed extension field, value is not decoded
+    Raw(&'a [u8]),
+}
+
+impl<'a> NtpExtension<'a> {
+    /// Return true if this is an empty field of type 0, used as padding
+    ///
+    /// Some implementations send such fields (type 0 and length 4), which carry no information.
+    /// They are parsed as other fields, and do not end the list of extensions.
+    #[inline]
+    pub fn is_padding(&self) -> bool {
+        self.field_type == 0 && self.value.is_empty()
+    }
+
+    /// Decode the value of the extension field, according to its type
+    ///
+    /// If the field type is unknown, or if the value cannot be decoded, the raw value is
+    /// returned.
+    pub fn parse_typed(&self) -> NtpExtensionContent<'a> {
+        let value = self.value;
+        match self.field_type {
+            NTP_EXT_CHECKSUM_COMPLEMENT if value.len() >= 2 => {
+                let (mbz, cc) = value.split_at(value.len() - 2);
+                NtpExtensionContent::ChecksumComplement {
+                    mbz,
+                    checksum_complement: u16::from_be_bytes([cc[0], cc[1]]),
+                }
+            }
+            NTP_EXT_UNIQUE_IDENTIFIER => NtpExtensionContent::UniqueIdentifier(value),
+            NTP_EXT_NTS_COOKIE => NtpExtensionContent::NtsCookie(value),
+            NTP_EXT_NTS_COOKIE_PLACEHOLDER => NtpExtensionContent::NtsCookiePlaceholder(value),
+            NTP_EXT_NTS_AUTHENTICATOR => match split_nts_authenticator(value) {
+                Some((nonce, ciphertext)) => {
+                    NtpExtensionContent::NtsAuthenticator { nonce, ciphertext }
+                }
+                None => NtpExtensionContent::Raw(value),
+            },
+            NTP_EXT_SERVER_NAME => {
+                let len = value.iter().rposition(|&b| b != 0).map_or(0, |p| p + 1);
+                match core::str::from_utf8(&value[..len]) {
+                    Ok(name) => NtpExtensionContent::ServerName(name),
+                    Err(_) => NtpExtensionContent::Raw(value),
+                }
+            }
+            _ => NtpExtensionContent::Raw(value),
+        }
+    }
+}
+
+// Split the value of an NTS Authenticator field into nonce and ciphertext
+//
+// The value starts with the lengths of the nonce and the ciphertext (16 bits each), then
+// contains the nonce and the ciphertext, each padded to a multiple of 4 bytes.
+pub(crate) fn split_nts_authenticator(value: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (lengths, data) = (value.get(..4)?, &value[4..]);
+    let nonce_len = usize::from(u16::from_be_bytes([lengths[0], lengths[1]]));
+    let ciphertext_len = usize::from(u16::from_be_bytes([lengths[2], lengths[3]]));
+    let padded_nonce_len = (nonce_len + 3) & !3;
+    let nonce = data.get(..nonce_len)?;
+    let ciphertext = data.get(padded_nonce_len..padded_nonce_len + ciphertext_len)?;
+    Some((nonce, ciphertext))
+}
+
+/// Interpretation of the length of an extension field, chosen by the tolerant parser
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExtensionPadding {
+    /// The length is a multiple of 4: it covers the padding, if any
+    Included,
+    /// The length excludes the padding, which follows the value
+    Excluded,
+    /// The length is not a multiple of 4, and the value is not followed by padding
+    Missing,
+}
+
+// Parse the header and the value of an extension field, without skipping the padding
+fn parse_ntp_extension_unpadded(i: &[u8]) -> IResult<&[u8], NtpExtension<'_>> {
+    let (i, field_type) = be_u16(i)?;
+    let (i, length) = verify(be_u16, |&length| length >= 4)(i)?;
+    let (i, value) = take(length - 4)(i)?;
+    let ext = NtpExtension {
+        field_type,
+        length,
+        value,
+    };
+    Ok((i, ext))
+}
+
+/// Parse an extension field, accepting either interpretation of the length for padding
+///
+/// Implementations disagree on whether the length includes padding. If the length is not a
+/// multiple of 4, the value is followed by padding only if the padding bytes are zero and the
+/// next field (if any) can be parsed after them, so that the following fields stay aligned to
+/// 4 bytes. Otherwise, the next field is assumed to start immediately after the value.
+///
+/// The interpretation that was used is returned with the extension.
+pub fn parse_ntp_extension_tolerant(
+    i: &[u8],
+) -> IResult<&[u8], (NtpExtension<'_>, ExtensionPadding)> {
+    let (rem, ext) = parse_ntp_extension_unpadded(i)?;
+    let pad = extension_padding_len(ext.length);
+    if pad == 0 {
+        return Ok((rem, (ext, ExtensionPadding::Included)));
+    }
+    if rem.len() >= pad && rem[..pad].iter().all(|&b| b == 0) {
+        let next = &rem[pad..];
+        if next.is_empty() || parse_ntp_extension(next).is_ok() {
+            return Ok((next, (ext, ExtensionPadding::Excluded)));
+        }
+    }
+    Ok((rem, (ext, ExtensionPadding::Missing)))
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Return the value of the first NTS Unique Identifier extension field, if present
+    ///
+    /// A response to an NTS request must contain the same unique identifier as the request,
+    /// so this value can be used to match requests and responses.
+    pub fn nts_unique_id(&self) -> Option<&'a [u8]> {
+        self.extensions
+            .iter()
+            .find(|ext| ext.field_type == NTP_EXT_UNIQUE_IDENTIFIER)
+            .map(|ext| ext.value)
+    }
+
+    /// Return the number of NTS Unique Identifier extension fields
+    ///
+    /// [RFC8915] allows only one such field in a packet: all fields are parsed and kept in
+    /// [`extensions`](Self::extensions), so callers can reject packets where this is greater
+    /// than 1.
+    ///
+    /// [RFC8915]: https://tools.ietf.org/html/rfc8915
+    pub fn nts_unique_id_count(&self) -> usize {
+        self.extensions
+            .iter()
+            .filter(|ext| ext.field_type == NTP_EXT_UNIQUE_IDENTIFIER)
+            .count()
+    }
+
+    /// Return the name reported by the server in the first server name extension field
+    ///
+    /// Returns `None` if the field is absent, or if the name is not valid UTF-8. See
+    /// [`NTP_EXT_SERVER_NAME`].
+    pub fn server_name(&self) -> Option<&'a str> {
+        self.extensions
+            .iter()
+            .find(|ext| ext.field_type == NTP_EXT_SERVER_NAME)
+            .and_then(|ext| match ext.parse_typed() {
+                NtpExtensionContent::ServerName(name) => Some(name),
+                _ => None,
+            })
+    }
+
+    /// Iterate over the extension fields, with a flag set for the last field before the MAC
+    ///
+    /// [RFC7822] requires the last extension field to be at least 28 bytes long when a MAC
+    /// follows (instead of 16 bytes for the other fields), so that it cannot be confused with
+    /// a MAC. The flag is false for all fields if the packet has no MAC.
+    ///
+    /// [RFC7822]: https://tools.ietf.org/html/rfc7822
+    pub fn extensions_with_last_flag(&self) -> impl Iterator<Item = (&NtpExtension<'a>, bool)> {
+        let last = match self.auth {
+            Some(_) => self.extensions.len().checked_sub(1),
+            None => None,
+        };
+        self.extensions
+            .iter()
+            .enumerate()
+            .map(move |(idx, ext)| (ext, Some(idx) == last))
+    }
+
+    /// Return the set of the types of the extension fields of the packet
+    pub fn extension_type_set(&self) -> BTreeSet<u16> {
+        self.extensions.iter().map(|ext| ext.field_type).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp::parse_ntpv4;
+
+    #[test]
+    fn test_nts_unique_id() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        // checksum complement, then unique identifier
+        buf.extend_from_slice(&[0x20, 0x05, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00]);
+        buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x0c]);
+        buf.extend((0..8).map(|b| b as u8));
+        buf.extend_from_slice(&[0; 20]);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), 2);
+        assert_eq!(pkt.nts_unique_id(), Some(&buf[60..68]));
+        let flags: Vec<bool> = pkt.extensions_with_last_flag().map(|(_, l)| l).collect();
+        assert_eq!(flags, [false, true]);
+        let types: Vec<u16> = pkt.extension_type_set().into_iter().collect();
+        assert_eq!(
+            types,
+            [NTP_EXT_UNIQUE_IDENTIFIER, NTP_EXT_CHECKSUM_COMPLEMENT]
+        );
+        assert_eq!(pkt.nts_unique_id_count(), 1);
+        let (_, pkt) = parse_ntpv4(&buf[..48]).expect("parsing failed");
+        assert_eq!(pkt.nts_unique_id(), None);
+        assert_eq!(pkt.nts_unique_id_count(), 0);
+    }
+
+    #[test]
+    fn test_nts_duplicate_unique_id() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        for b in 0..2 {
+            buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x24]);
+            buf.extend_from_slice(&[b; 32]);
+        }
+        buf.extend_from_slice(&[0; 20]);
+        let (rem, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 2);
+        assert_eq!(pkt.nts_unique_id_count(), 2);
+        // the first identifier is returned
+        assert_eq!(pkt.nts_unique_id(), Some(&[0; 32][..]));
+    }
+
+    #[test]
+    fn test_server_name() {
+        let mut buf = vec![0x24];
+        buf.resize(48, 0);
+        buf.extend_from_slice(&[0xf0, 0x01, 0x00, 0x14]);
+        buf.extend_from_slice(b"ntp1.example\0\0\0\0");
+        buf.extend_from_slice(&[0; 20]);
+        let mut invalid = buf.clone();
+        invalid[52] = 0xff;
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert_eq!(
+            pkt.extensions[0].parse_typed(),
+            NtpExtensionContent::ServerName("ntp1.example")
+        );
+        assert_eq!(pkt.server_name(), Some("ntp1.example"));
+        // invalid UTF-8
+        let (_, pkt) = parse_ntpv4(&invalid).expect("parsing failed");
+        assert_eq!(pkt.server_name(), None);
+        assert_eq!(
+            pkt.extensions[0].parse_typed(),
+            NtpExtensionContent::Raw(&invalid[52..68])
+        );
+    }
+
+    #[test]
+    fn test_ext_tolerant_padding() {
+        // length excludes padding
+        let data = &[
+            0x00, 0x01, 0x00, 0x06, 0xaa, 0xbb, 0x00, 0x00, 0x00, 0x02, 0x00, 0x04,
+        ];
+        let (rem, (ext, padding)) = parse_ntp_extension_tolerant(data).expect("parsing failed");
+        assert_eq!(ext.value, &[0xaa, 0xbb]);
+        assert_eq!(padding, ExtensionPadding::Excluded);
+        let (rem, (ext, padding)) = parse_ntp_extension_tolerant(rem).expect("parsing failed");
+        assert_eq!(ext.field_type, 2);
+        assert_eq!(padding, ExtensionPadding::Included);
+        assert!(rem.is_empty());
+        // no padding, next field immediately after the value
+        let data = &[0x00, 0x01, 0x00, 0x06, 0xaa, 0xbb, 0x00, 0x02, 0x00, 0x04];
+        let (rem, (_, padding)) = parse_ntp_extension_tolerant(data).expect("parsing failed");
+        assert_eq!(padding, ExtensionPadding::Missing);
+        assert_eq!(rem, &data[6..]);
+    }
+
+    #[test]
+    fn test_ext_padding() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        buf.extend_from_slice(&[0x00, 0x00, 0x00, 0x04]);
+        buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0; 20]);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), 2);
+        assert!(pkt.extensions[0].is_padding());
+        assert!(!pkt.extensions[1].is_padding());
+        assert_eq!(pkt.nts_unique_id(), Some(&buf[56..60]));
+    }
+
+    #[test]
+    fn test_ext_checksum_complement() {
+        let value = &[0, 0, 0, 0, 0x12, 0x34];
+        let ext = NtpExtension {
+            field_type: NTP_EXT_CHECKSUM_COMPLEMENT,
+            length: 10,
+            value,
+        };
+        let expected = NtpExtensionContent::ChecksumComplement {
+            mbz: &value[..4],
+            checksum_complement: 0x1234,
+        };
+        assert_eq!(ext.parse_typed(), expected);
+
+        let ext = NtpExtension {
+            field_type: NTP_EXT_CHECKSUM_COMPLEMENT,
+            length: 5,
+            value: &value[..1],
+        };
+        assert_eq!(ext.parse_typed(), NtpExtensionContent::Raw(&value[..1]));
+    }
+
+    #[test]
+    fn test_ext_nts_typed() {
+        let ext = |field_type, value| NtpExtension {
+            field_type,
+            length: 0,
+            value,
+        };
+        let cookie = &[0xc0; 8];
+        assert_eq!(
+            ext(NTP_EXT_NTS_COOKIE, cookie).parse_typed(),
+            NtpExtensionContent::NtsCookie(cookie)
+        );
+        assert_eq!(
+            ext(NTP_EXT_NTS_COOKIE_PLACEHOLDER, &[0; 8]).parse_typed(),
+            NtpExtensionContent::NtsCookiePlaceholder(&[0; 8])
+        );
+        assert_eq!(
+            ext(NTP_EXT_UNIQUE_IDENTIFIER, &[1; 32]).parse_typed(),
+            NtpExtensionContent::UniqueIdentifier(&[1; 32])
+        );
+        // 6-byte nonce padded to 8 bytes, 4-byte ciphertext
+        let value = &[0, 6, 0, 4, 1, 2, 3, 4, 5, 6, 0, 0, 0xa, 0xb, 0xc, 0xd];
+        assert_eq!(
+            ext(NTP_EXT_NTS_AUTHENTICATOR, value).parse_typed(),
+            NtpExtensionContent::NtsAuthenticator {
+                nonce: &value[4..10],
+                ciphertext: &value[12..],
+            }
+        );
+        assert_eq!(
+            ext(NTP_EXT_NTS_AUTHENTICATOR, &value[..14]).parse_typed(),
+            NtpExtensionContent::Raw(&value[..14])
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Flat representation of NTP packets, for columnar export
+
+use crate::ntp::NtpPacket;
+use alloc::format;
+use alloc::string::String;
+
+/// A version-agnostic NTP packet, with primitive-typed fields
+///
+/// This is intended for export to columnar formats (CSV, Arrow, Parquet, etc.): each field is
+/// a column, and version 3 and 4 packets have the same columns. Timestamps are kept in the NTP
+/// format (seconds since 1900 in the high 32 bits, fraction in the low 32 bits).
+///
+/// Control messages only have the version, mode, leap indicator and MAC columns: the other
+/// columns are zero or empty. Version 5 packets (with the `ntpv5` feature) have no reference
+/// identifier, reference or origin timestamps, or MAC.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpFlatRecord {
+    pub version: u8,
+    pub mode: u8,
+    pub li: u8,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: i8,
+    pub root_delay_secs: f64,
+    pub root_dispersion_secs: f64,
+    pub ref_id: u32,
+    /// Reference identifier, interpreted according to the stratum
+    ///
+    /// This is the kiss code or the reference clock name for strata 0 and 1 (if printable),
+    /// an IPv4 address for strata 2 to 15, and the hexadecimal value otherwise.
+    pub refid_str: String,
+    pub ts_ref: u64,
+    pub ts_orig: u64,
+    pub ts_recv: u64,
+    pub ts_xmit: u64,
+    /// Number of extension fields (always 0 for version 3)
+    pub extensions: usize,
+    /// True if the packet has a MAC (version 4) or an authenticator (version 3)
+    pub authenticated: bool,
+    /// Key identifier of the MAC, for version 4
+    pub key_id: Option<u32>,
+}
+
+// Build a record from the fields common to versions 3 and 4
+macro_rules! flat_header {
+    ($pkt:expr, $extensions:expr, $authenticated:expr, $key_id:expr) => {{
+        let pkt = $pkt;
+        let b = pkt.ref_id.to_be_bytes();
+        let refid_str = match (pkt.stratum, pkt.ref_id_as_ascii()) {
+            (0..=1, Some(s)) => s,
+            (2..=15, _) => format!("{}.{}.{}.{}", b[0], b[1], b[2], b[3]),
+            _ => format!("{:#010x}", pkt.ref_id),
+        };
+        NtpFlatRecord {
+            version: pkt.version,
+            mode: pkt.mode.0,
+            li: pkt.li,
+            stratum: pkt.stratum,
+            poll: pkt.poll,
+            precision: pkt.precision.0,
+            root_delay_secs: pkt.root_delay.to_seconds(),
+            root_dispersion_secs: pkt.root_dispersion.to_seconds(),
+            ref_id: pkt.ref_id,
+            refid_str,
+            ts_ref: pkt.ts_ref.0,
+            ts_orig: pkt.ts_orig.0,
+            ts_recv: pkt.ts_recv.0,
+            ts_xmit: pkt.ts_xmit.0,
+            extensions: $extensions,
+            authenticated: $authenticated,
+            key_id: $key_id,
+        }
+    }};
+}
+
+impl<'a> NtpPacket<'a> {
+    /// Return a flat record of the packet, see [`NtpFlatRecord`]
+    pub fn to_flat_record(&self) -> NtpFlatRecord {
+        match self {
+            NtpPacket::V3(pkt) => flat_header!(pkt, 0, pkt.authenticator.is_some(), None),
+            NtpPacket::V4(pkt) => flat_header!(
+                pkt,
+                pkt.extensions.len(),
+                pkt.auth.is_some(),
+                pkt.auth.as_ref().map(|mac| mac.key_id)
+            ),
+            NtpPacket::Control(msg) => NtpFlatRecord {
+                version: msg.version,
+                mode: msg.mode.0,
+                li: msg.li,
+                stratum: 0,
+                poll: 0,
+                precision: 0,
+                root_delay_secs: 0.0,
+                root_dispersion_secs: 0.0,
+                ref_id: 0,
+                refid_str: String::new(),
+                ts_ref: 0,
+                ts_orig: 0,
+                ts_recv: 0,
+                ts_xmit: 0,
+                extensions: 0,
+                authenticated: msg.auth.is_some(),
+                key_id: msg.auth.as_ref().map(|mac| mac.key_id),
+            },
+            #[cfg(feature = "ntpv5")]
+            NtpPacket::V5(pkt) => NtpFlatRecord {
+                version: pkt.version,
+                mode: pkt.mode.0,
+                li: pkt.li,
+                stratum: pkt.stratum,
+                poll: pkt.poll,
+                precision: pkt.precision.0,
+                root_delay_secs: pkt.root_delay_seconds(),
+                root_dispersion_secs: pkt.root_dispersion_seconds(),
+                ref_id: 0,
+                refid_str: String::new(),
+                ts_ref: 0,
+                ts_orig: 0,
+                ts_recv: pkt.ts_recv.0,
+                ts_xmit: pkt.ts_xmit.0,
+                extensions: pkt.extensions.len(),
+                authenticated: false,
+                key_id: None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ntp::parse_ntp;
+
+    #[test]
+    fn test_flat_record() {
+        let mut buf = vec![0x1c, 0x02, 0x06, 0xec, 0x00, 0x00, 0x80, 0x00];
+        buf.resize(48, 0);
+        buf[12..16].copy_from_slice(&[192, 168, 0, 1]);
+        buf.extend_from_slice(&[0, 0, 0, 5]);
+        buf.extend_from_slice(&[0xaa; 16]);
+        let (_, pkt) = parse_ntp(&buf).expect("parsing failed");
+        let record = pkt.to_flat_record();
+        assert_eq!((record.version, record.mode, record.stratum), (3, 4, 2));
+        assert_eq!((record.poll, record.precision), (6, -20));
+        assert_eq!(record.root_delay_secs, 0.5);
+        assert_eq!(record.refid_str, "192.168.0.1");
+        assert!(record.authenticated);
+        assert_eq!(record.key_id, None);
+        // version 4, primary server
+        let mut v4 = buf.clone();
+        v4[0] = 0x24;
+        v4[1] = 1;
+        v4[12..16].copy_from_slice(b"GPS\0");
+        let (_, pkt) = parse_ntp(&v4).expect("parsing failed");
+        let record = pkt.to_flat_record();
+        assert_eq!(record.version, 4);
+        assert_eq!(record.refid_str, "GPS");
+        assert_eq!(record.extensions, 0);
+        assert_eq!(record.key_id, Some(5));
+    }
+}
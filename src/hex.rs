@@ -0,0 +1,67 @@
+//! Decoding of packets from hexadecimal strings
+//!
+//! This is useful to reproduce a captured packet, for ex. in a bug report or a test. This
+//! module is only available with the `hex` feature.
+
+use crate::error::NtpError;
+use crate::ntp::parse_ntp_complete_e;
+use crate::owned::NtpPacketOwned;
+use alloc::vec::Vec;
+use nom::error::ErrorKind;
+
+/// Parse an NTP packet from the hexadecimal string copied from Wireshark
+///
+/// The expected format is "Copy as Hex Stream" (pairs of hexadecimal digits, without
+/// separators). Whitespace, including line breaks, is ignored so a stream split on several
+/// lines is accepted.
+///
+/// Invalid hexadecimal digits return `NtpError::Nom(ErrorKind::HexDigit)`, and data remaining
+/// after the packet returns `NtpError::Nom(ErrorKind::Eof)`.
+pub fn ntp_from_wireshark_hex(s: &str) -> Result<NtpPacketOwned, NtpError> {
+    let data = decode_hex(s).ok_or(NtpError::Nom(ErrorKind::HexDigit))?;
+    let (rem, pkt) = parse_ntp_complete_e::<NtpError>(&data)?;
+    if !rem.is_empty() {
+        return Err(NtpError::Nom(ErrorKind::Eof));
+    }
+    Ok(pkt.into_owned())
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(digits.chunks(2).map(|p| (p[0] << 4) | p[1]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::NtpTimestamp;
+
+    #[test]
+    fn test_wireshark_hex() {
+        let s = "e30006ec00000000000000000000000000000000000000000000000000000000\n\
+                 0000000000000000c50204ecec42ee92\n";
+        let pkt = ntp_from_wireshark_hex(s).expect("decoding failed");
+        let pkt = match pkt {
+            NtpPacketOwned::V4(pkt) => pkt,
+            _ => panic!("unexpected version"),
+        };
+        assert_eq!(pkt.poll, 6);
+        assert_eq!(pkt.ts_xmit, NtpTimestamp(0xc502_04ec_ec42_ee92));
+        assert_eq!(
+            ntp_from_wireshark_hex("e3 0g"),
+            Err(NtpError::Nom(ErrorKind::HexDigit))
+        );
+        assert_eq!(
+            ntp_from_wireshark_hex("e30"),
+            Err(NtpError::Nom(ErrorKind::HexDigit))
+        );
+        assert!(ntp_from_wireshark_hex("e300").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+//! Parsers for the individual fields of NTP packets
+//!
+//! These functions are building blocks to compose the NTP parsers with other nom combinators,
+//! for ex. to parse NTP data embedded in another protocol. They are only available with the
+//! `internals` feature, and are not covered by the stability guarantees of the rest of the API.
+
+use crate::ntp::NtpMode;
+use crate::timestamp::{NtpShortFormat, NtpTimestamp};
+use nom::combinator::map;
+use nom::number::streaming::{be_u32, be_u64, be_u8};
+use nom::IResult;
+
+/// Parse a 64-bit NTP timestamp
+#[inline]
+pub fn parse_timestamp(i: &[u8]) -> IResult<&[u8], NtpTimestamp> {
+    map(be_u64, NtpTimestamp)(i)
+}
+
+/// Parse a 32-bit NTP short format value (root delay or root dispersion)
+#[inline]
+pub fn parse_short_format(i: &[u8]) -> IResult<&[u8], NtpShortFormat> {
+    map(be_u32, NtpShortFormat)(i)
+}
+
+/// Parse the first byte of a packet, returning the leap indicator, version and mode
+#[inline]
+pub fn parse_flags_byte(i: &[u8]) -> IResult<&[u8], (u8, u8, NtpMode)> {
+    map(be_u8, |b| (b >> 6, (b >> 3) & 0b111, NtpMode(b & 0b111)))(i)
+}
+
+/// Split the data following an NTP version 4 header into the extensions region and the MAC
+///
+/// The extensions region is returned, and the remaining data is the MAC (if any).
+#[inline]
+pub fn parse_extensions_region(i: &[u8]) -> IResult<&[u8], &[u8]> {
+    crate::ntp::take_extensions_region(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_parsers() {
+        let data = [0xe4, 0x00, 0x01, 0x80, 0x00, 0, 0, 0, 0];
+        let (rem, flags) = parse_flags_byte(&data).expect("parsing failed");
+        assert_eq!(flags, (3, 4, NtpMode::Server));
+        let (rem, short) = parse_short_format(rem).expect("parsing failed");
+        assert_eq!(short, NtpShortFormat(0x0001_8000));
+        assert!(parse_timestamp(rem).is_err());
+        let (_, ts) = parse_timestamp(&data[1..]).expect("parsing failed");
+        assert_eq!(ts.seconds(), 0x0001_8000);
+    }
+}
@@ -0,0 +1,140 @@
+//! Helpers for the analysis of captured packets
+
+use crate::timestamp::NtpTimestamp;
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+// Add `data` to the one's complement sum, as 16-bit big-endian words
+fn checksum_add(mut sum: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for c in &mut chunks {
+        sum += u32::from(u16::from_be_bytes([c[0], c[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    sum
+}
+
+/// Check the UDP checksum of a datagram, using the IP pseudo-header
+///
+/// `udp_payload` is the payload of the IP packet, i.e. the UDP header (8 bytes) followed by
+/// the UDP data. Returns false if the data is too short, if the length in the UDP header does
+/// not match, or if the addresses are not of the same family.
+///
+/// A zero checksum means that the checksum was not computed, which is valid for IPv4 only.
+pub fn validate_udp_checksum(ip_src: IpAddr, ip_dst: IpAddr, udp_payload: &[u8]) -> bool {
+    if udp_payload.len() < 8 || udp_payload.len() > usize::from(u16::MAX) {
+        return false;
+    }
+    let udp_len = u16::from_be_bytes([udp_payload[4], udp_payload[5]]);
+    if usize::from(udp_len) != udp_payload.len() {
+        return false;
+    }
+    let zero_checksum = udp_payload[6] == 0 && udp_payload[7] == 0;
+    let mut sum = match (ip_src, ip_dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            if zero_checksum {
+                return true;
+            }
+            let sum = checksum_add(0, &src.octets());
+            checksum_add(sum, &dst.octets())
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            if zero_checksum {
+                return false;
+            }
+            let sum = checksum_add(0, &src.octets());
+            checksum_add(sum, &dst.octets())
+        }
+        _ => return false,
+    };
+    // protocol (UDP) and length
+    sum += 17 + u32::from(udp_len);
+    sum = checksum_add(sum, udp_payload);
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum == 0xffff
+}
+
+/// Transport metadata of a request sent by a client
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Exchange {
+    /// UDP source port of the request
+    pub src_port: u16,
+    /// Transmit timestamp of the request
+    pub ts_xmit: NtpTimestamp,
+}
+
+/// Check if a client randomizes the source port of its requests ([RFC9109])
+///
+/// `exchanges` are the requests of a single client to a server. Returns true if there are
+/// at least two requests, each using a different source port, and none using the NTP port
+/// (123). A client reusing a port (or using the NTP port) is not conforming.
+///
+/// [RFC9109]: https://tools.ietf.org/html/rfc9109
+pub fn suggests_port_randomization(exchanges: &[Exchange]) -> bool {
+    if exchanges.len() < 2 {
+        return false;
+    }
+    let mut ports = HashSet::with_capacity(exchanges.len());
+    exchanges
+        .iter()
+        .all(|ex| ex.src_port != 123 && ports.insert(ex.src_port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    // UDP header (port 123 to port 123, length 12, checksum) and 4 bytes of data
+    static UDP_V4: &[u8] = &[
+        0x00, 0x7b, 0x00, 0x7b, 0x00, 0x0c, 0xdf, 0xee, 0xde, 0xad, 0xbe, 0xef,
+    ];
+
+    #[test]
+    fn test_udp_checksum_v4() {
+        let src = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+        let dst = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 2));
+        assert!(validate_udp_checksum(src, dst, UDP_V4));
+        let mut bad = UDP_V4.to_vec();
+        bad[8] = 0;
+        assert!(!validate_udp_checksum(src, dst, &bad));
+        bad[6] = 0;
+        bad[7] = 0;
+        assert!(validate_udp_checksum(src, dst, &bad));
+        let v6 = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        assert!(!validate_udp_checksum(src, v6, UDP_V4));
+        assert!(!validate_udp_checksum(src, dst, &UDP_V4[..6]));
+    }
+
+    #[test]
+    fn test_port_randomization() {
+        let ex = |src_port, t| Exchange {
+            src_port,
+            ts_xmit: NtpTimestamp(t),
+        };
+        assert!(suggests_port_randomization(&[
+            ex(40123, 1),
+            ex(51234, 2),
+            ex(33001, 3)
+        ]));
+        assert!(!suggests_port_randomization(&[ex(40123, 1), ex(40123, 2)]));
+        assert!(!suggests_port_randomization(&[ex(123, 1), ex(40123, 2)]));
+        assert!(!suggests_port_randomization(&[ex(40123, 1)]));
+    }
+
+    #[test]
+    fn test_udp_checksum_v6() {
+        let lo = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        let mut data = UDP_V4.to_vec();
+        data[6] = 0x61;
+        data[7] = 0x41;
+        assert!(validate_udp_checksum(lo, lo, &data));
+        data[6] = 0;
+        data[7] = 0;
+        assert!(!validate_udp_checksum(lo, lo, &data));
+    }
+}
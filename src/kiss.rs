@@ -0,0 +1,166 @@
+//! Kiss-o'-Death codes ([RFC5905] section 7.4)
+//!
+//! [RFC5905]: https://tools.ietf.org/html/rfc5905
+
+/// Kiss code, an ASCII string stored in the reference identifier
+///
+/// Kiss codes are sent in Kiss-o'-Death packets (stratum 0). The reference implementation
+/// also uses some codes (for ex. `INIT` and `STEP`) in the reference identifier of
+/// unsynchronized packets (stratum 16), as a status report.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KissCode {
+    /// The association belongs to a unicast server
+    Acst,
+    /// Server authentication failed
+    Auth,
+    /// Autokey sequence failed
+    Auto,
+    /// The association belongs to a broadcast server
+    Bcst,
+    /// Cryptographic authentication or identification failed
+    Cryp,
+    /// Access denied by remote server
+    Deny,
+    /// Lost peer in symmetric mode
+    Drop,
+    /// Access denied due to local policy
+    Rstr,
+    /// The association has not yet synchronized for the first time
+    Init,
+    /// The association belongs to a dynamically discovered server
+    Mcst,
+    /// No key found
+    Nkey,
+    /// NTS negative acknowledgment: the server cannot decrypt the NTS cookie ([RFC8915])
+    ///
+    /// [RFC8915]: https://tools.ietf.org/html/rfc8915
+    Ntsn,
+    /// Rate exceeded, the server has temporarily denied access
+    Rate,
+    /// Alteration of association from a remote host running ntpdc
+    Rmot,
+    /// A step change in system time has occurred, but the association has not yet
+    /// resynchronized
+    Step,
+    /// Other printable ASCII code
+    Unknown([u8; 4]),
+}
+
+impl KissCode {
+    /// Decode a reference identifier as a kiss code
+    ///
+    /// Returns `None` if the bytes are not printable ASCII. Trailing NUL bytes are allowed,
+    /// for codes shorter than 4 characters.
+    pub fn from_ref_id(ref_id: u32) -> Option<KissCode> {
+        let b = ref_id.to_be_bytes();
+        let len = b.iter().position(|&c| c == 0).unwrap_or(4);
+        if len == 0
+            || !b[..len].iter().all(|c| c.is_ascii_graphic())
+            || b[len..].iter().any(|&c| c != 0)
+        {
+            return None;
+        }
+        let code = match &b {
+            b"ACST" => KissCode::Acst,
+            b"AUTH" => KissCode::Auth,
+            b"AUTO" => KissCode::Auto,
+            b"BCST" => KissCode::Bcst,
+            b"CRYP" => KissCode::Cryp,
+            b"DENY" => KissCode::Deny,
+            b"DROP" => KissCode::Drop,
+            b"RSTR" => KissCode::Rstr,
+            b"INIT" => KissCode::Init,
+            b"MCST" => KissCode::Mcst,
+            b"NKEY" => KissCode::Nkey,
+            b"NTSN" => KissCode::Ntsn,
+            b"RATE" => KissCode::Rate,
+            b"RMOT" => KissCode::Rmot,
+            b"STEP" => KissCode::Step,
+            _ => KissCode::Unknown(b),
+        };
+        Some(code)
+    }
+}
+
+static KISS_CODES: &[(KissCode, &str)] = &[
+    (
+        KissCode::Acst,
+        "ACST: the association belongs to a unicast server",
+    ),
+    (KissCode::Auth, "AUTH: server authentication failed"),
+    (KissCode::Auto, "AUTO: autokey sequence failed"),
+    (
+        KissCode::Bcst,
+        "BCST: the association belongs to a broadcast server",
+    ),
+    (
+        KissCode::Cryp,
+        "CRYP: cryptographic authentication or identification failed",
+    ),
+    (KissCode::Deny, "DENY: access denied by remote server"),
+    (KissCode::Drop, "DROP: lost peer in symmetric mode"),
+    (KissCode::Rstr, "RSTR: access denied due to local policy"),
+    (
+        KissCode::Init,
+        "INIT: the association has not yet synchronized for the first time",
+    ),
+    (
+        KissCode::Mcst,
+        "MCST: the association belongs to a dynamically discovered server",
+    ),
+    (KissCode::Nkey, "NKEY: no key found"),
+    (
+        KissCode::Ntsn,
+        "NTSN: NTS negative acknowledgment, the server cannot decrypt the cookie",
+    ),
+    (KissCode::Rate, "RATE: rate exceeded, reduce polling"),
+    (
+        KissCode::Rmot,
+        "RMOT: alteration of association from a remote host running ntpdc",
+    ),
+    (
+        KissCode::Step,
+        "STEP: a step change in system time has occurred",
+    ),
+];
+
+/// Return all the known kiss codes, with a description
+///
+/// The table contains the codes listed in [RFC5905] section 7.4, and `NTSN` from [RFC8915],
+/// in the order of the variants of [`KissCode`]. Each description starts with the code, and is
+/// suitable for help texts.
+///
+/// [RFC5905]: https://tools.ietf.org/html/rfc5905
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+pub fn all_kiss_codes() -> &'static [(KissCode, &'static str)] {
+    KISS_CODES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kiss_code() {
+        assert_eq!(KissCode::from_ref_id(0x494e_4954), Some(KissCode::Init));
+        assert_eq!(KissCode::from_ref_id(0x5354_4550), Some(KissCode::Step));
+        assert_eq!(KissCode::from_ref_id(0x5241_5445), Some(KissCode::Rate));
+        assert_eq!(
+            KissCode::from_ref_id(0x4142_4300),
+            Some(KissCode::Unknown(*b"ABC\0"))
+        );
+        // IPv4 address 192.168.0.1
+        assert_eq!(KissCode::from_ref_id(0xc0a8_0001), None);
+        assert_eq!(KissCode::from_ref_id(0), None);
+    }
+
+    #[test]
+    fn test_all_kiss_codes() {
+        assert_eq!(all_kiss_codes().len(), 15);
+        for (code, description) in all_kiss_codes() {
+            let b = description.as_bytes();
+            let ref_id = u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+            assert_eq!(KissCode::from_ref_id(ref_id), Some(*code));
+        }
+    }
+}
@@ -10,7 +10,15 @@
 //! ntp-parser is a parser for the NTP protocol.
 //!
 //! This crate mostly serves as a demo/example crate for network protocol parsers written using nom, and nom-derive.
+//!
+//! Without the default `std` feature, the crate itself is `no_std` and only uses `core` and
+//! `alloc` (the helpers requiring `std`, such as `reference_id`, are not available). However,
+//! this is not enough to build for targets without `std` yet: `nom-derive` requires `std`, and
+//! enables the `std` feature of `nom`.
+//!
+//! The `ntpv5` feature adds a parser for the NTP version 5 draft, see the `ntpv5` module.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 // add missing_docs
 #![deny(
     unsafe_code,
@@ -19,5 +27,60 @@
     unused_qualifications
 )]
 
+extern crate alloc;
+
+pub use anomaly::*;
+pub use auth::*;
+pub use autokey::*;
+pub use broadcast::*;
+pub use burst::*;
+pub use control::*;
+pub use decoder::*;
+pub use describe::*;
+pub use error::*;
+pub use extension::*;
+pub use flat::*;
+pub use kiss::*;
+pub use mssntp::*;
 pub use ntp::*;
+#[cfg(feature = "ntpv5")]
+pub use ntpv5::*;
+pub use owned::*;
+pub use private::*;
+pub use probe::*;
+pub use refclock::*;
+pub use replay::*;
+pub use template::*;
+pub use timestamp::*;
+pub mod anomaly;
+pub mod auth;
+pub mod autokey;
+pub mod broadcast;
+pub mod burst;
+pub mod control;
+pub mod decoder;
+pub mod describe;
+pub mod error;
+pub mod extension;
+pub mod flat;
+#[cfg(feature = "hex")]
+pub mod hex;
+#[cfg(feature = "internals")]
+pub mod internals;
+#[cfg(feature = "std")]
+pub mod interop;
+pub mod kiss;
+pub mod mssntp;
 pub mod ntp;
+#[cfg(feature = "ntpv5")]
+pub mod ntpv5;
+pub mod owned;
+pub mod private;
+pub mod probe;
+pub mod refclock;
+pub mod replay;
+#[cfg(feature = "serde")]
+mod serde_util;
+mod serialize;
+pub mod template;
+pub mod timestamp;
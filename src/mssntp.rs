@@ -0,0 +1,145 @@
+//! Microsoft authentication extensions to NTP (MS-SNTP)
+//!
+//! Windows domain controllers authenticate NTP responses using the computer account of the
+//! client, as described in the [MS-SNTP] specification. This is a Microsoft extension, **not**
+//! an RFC standard.
+//!
+//! MS-SNTP does not use extension fields: the authenticator takes the place of the MAC, after
+//! the NTP header. It is either 20 bytes long (same size as a MD5 MAC), or 72 bytes long
+//! (extended authenticator).
+//!
+//! [MS-SNTP]: https://docs.microsoft.com/en-us/openspecs/windows_protocols/ms-sntp
+
+use crate::ntp::NtpV4Packet;
+use nom::bytes::streaming::take;
+use nom::error::{make_error, ErrorKind};
+use nom::number::streaming::{be_u32, be_u8};
+use nom::{Err, IResult};
+use nom_derive::Parse;
+
+/// Length of the MS-SNTP authenticator (key identifier and 16-byte checksum)
+pub const MS_SNTP_AUTHENTICATOR_LEN: usize = 20;
+
+/// Length of the MS-SNTP extended authenticator (key identifier, flags and 64-byte checksum)
+pub const MS_SNTP_EXTENDED_AUTHENTICATOR_LEN: usize = 72;
+
+/// An MS-SNTP authenticator
+#[derive(Clone, Debug, PartialEq)]
+pub enum MsSntpAuthenticator<'a> {
+    /// Authenticator, with a MD5-based checksum
+    Authenticator { key_id: u32, checksum: &'a [u8] },
+    /// Extended authenticator, with a HMAC-SHA512-based checksum
+    Extended {
+        key_id: u32,
+        reserved: u8,
+        flags: u8,
+        client_hash_id_hints: u8,
+        signature_hash_id: u8,
+        checksum: &'a [u8],
+    },
+}
+
+impl<'a> MsSntpAuthenticator<'a> {
+    /// Return the raw key identifier
+    pub fn key_id(&self) -> u32 {
+        match self {
+            MsSntpAuthenticator::Authenticator { key_id, .. } => *key_id,
+            MsSntpAuthenticator::Extended { key_id, .. } => *key_id,
+        }
+    }
+
+    /// Return the relative identifier (RID) of the computer account of the client
+    #[inline]
+    pub fn rid(&self) -> u32 {
+        self.key_id() & 0x7fff_ffff
+    }
+
+    /// Return true if the checksum was computed using the previous password of the account
+    #[inline]
+    pub fn uses_previous_key(&self) -> bool {
+        self.key_id() & 0x8000_0000 != 0
+    }
+
+    /// Return the cryptographic checksum
+    pub fn checksum(&self) -> &'a [u8] {
+        match self {
+            MsSntpAuthenticator::Authenticator { checksum, .. } => checksum,
+            MsSntpAuthenticator::Extended { checksum, .. } => checksum,
+        }
+    }
+}
+
+/// Parse an MS-SNTP authenticator
+///
+/// The authenticator must be the complete input, the type is selected using its length.
+pub fn parse_ms_sntp_authenticator(i: &[u8]) -> IResult<&[u8], MsSntpAuthenticator<'_>> {
+    match i.len() {
+        MS_SNTP_AUTHENTICATOR_LEN => {
+            let (i, key_id) = be_u32(i)?;
+            let (i, checksum) = take(16usize)(i)?;
+            Ok((i, MsSntpAuthenticator::Authenticator { key_id, checksum }))
+        }
+        MS_SNTP_EXTENDED_AUTHENTICATOR_LEN => {
+            let (i, key_id) = be_u32(i)?;
+            let (i, reserved) = be_u8(i)?;
+            let (i, flags) = be_u8(i)?;
+            let (i, client_hash_id_hints) = be_u8(i)?;
+            let (i, signature_hash_id) = be_u8(i)?;
+            let (i, checksum) = take(64usize)(i)?;
+            let auth = MsSntpAuthenticator::Extended {
+                key_id,
+                reserved,
+                flags,
+                client_hash_id_hints,
+                signature_hash_id,
+                checksum,
+            };
+            Ok((i, auth))
+        }
+        _ => Err(Err::Error(make_error(i, ErrorKind::LengthValue))),
+    }
+}
+
+/// Parse an NTP version 4 packet authenticated using MS-SNTP
+///
+/// The bytes following the header are parsed as an MS-SNTP authenticator, so the packet has
+/// no extensions and no MAC.
+pub fn parse_ntpv4_ms_sntp(i: &[u8]) -> IResult<&[u8], (NtpV4Packet<'_>, MsSntpAuthenticator<'_>)> {
+    let (rem, header) = take(48usize)(i)?;
+    let (_, pkt) = NtpV4Packet::parse(header)?;
+    let (rem, auth) = parse_ms_sntp_authenticator(rem)?;
+    Ok((rem, (pkt, auth)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ms_sntp_extended_authenticator() {
+        let mut buf = vec![0x1c, 0x02, 0x11, 0xe9];
+        buf.resize(48, 0);
+        // key identifier: previous key, RID 0x0452
+        buf.extend_from_slice(&[0x80, 0x00, 0x04, 0x52, 0x00, 0x01, 0x00, 0x01]);
+        buf.extend((0..64).map(|b| b as u8));
+        let (rem, (pkt, auth)) = parse_ntpv4_ms_sntp(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.stratum, 2);
+        assert!(pkt.auth.is_none());
+        assert_eq!(auth.rid(), 0x0452);
+        assert!(auth.uses_previous_key());
+        assert_eq!(auth.checksum(), &buf[56..]);
+        assert!(matches!(
+            auth,
+            MsSntpAuthenticator::Extended {
+                flags: 1,
+                signature_hash_id: 1,
+                ..
+            }
+        ));
+        // 20-byte authenticator
+        let (_, auth) = parse_ms_sntp_authenticator(&buf[52..72]).expect("parsing failed");
+        assert!(matches!(auth, MsSntpAuthenticator::Authenticator { .. }));
+        assert!(parse_ms_sntp_authenticator(&buf[52..]).is_err());
+    }
+}
@@ -1,18 +1,52 @@
+use crate::auth::MacTail;
+use crate::control::{parse_ntp_control, NtpControlMessage};
+use crate::error::{NtpError, NtpErrorAt, NtpLocatedError, NtpResult, NtpWarning};
+use crate::extension::{parse_ntp_extension_tolerant, ExtensionPadding};
+use crate::kiss::KissCode;
+#[cfg(feature = "ntpv5")]
+use crate::ntpv5::NtpV5Packet;
+use crate::refclock::{ClockCategory, LocalRefClock};
+use crate::timestamp::{
+    duration_to_fixed64, fixed64_to_duration, fixed64_to_seconds, pow2, NtpShortFormat,
+    NtpTimestamp, Precision,
+};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::Range;
+use core::time::Duration;
 use nom::bytes::streaming::take;
-use nom::combinator::{complete, map, map_parser, opt};
-use nom::error::{make_error, ErrorKind};
-use nom::multi::many1;
-use nom::number::streaming::be_u8;
-pub use nom::{Err, IResult};
+use nom::combinator::{complete, map, map_res};
+use nom::error::{
+    context, make_error, ContextError, Error, ErrorKind, FromExternalError, ParseError,
+};
+use nom::multi::fold_many1;
+use nom::number::streaming::{be_i8, be_u32, be_u64, be_u8};
+pub use nom::{Err, IResult, Needed};
 use nom_derive::*;
+#[cfg(feature = "std")]
+use std::net::Ipv4Addr;
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NtpPacket<'a> {
+    #[cfg_attr(feature = "serde", serde(borrow))]
     V3(NtpV3Packet<'a>),
+    #[cfg_attr(feature = "serde", serde(borrow))]
     V4(NtpV4Packet<'a>),
+    /// Control message (mode 6), see [`parse_ntp_control`]
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    Control(NtpControlMessage<'a>),
+    /// Version 5 packet (draft), see [`parse_ntpv5`](crate::ntpv5::parse_ntpv5)
+    #[cfg(feature = "ntpv5")]
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    V5(NtpV5Packet<'a>),
 }
 
-#[derive(Clone, Copy, Debug, Eq, PartialEq, NomBE)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NtpMode(pub u8);
 
 #[allow(non_upper_case_globals)]
@@ -27,34 +61,2174 @@ impl NtpMode {
     pub const Private: NtpMode = NtpMode(7);
 }
 
+impl NtpMode {
+    /// Return the role of the sender of a packet with this mode
+    pub fn role(&self) -> NtpRole {
+        match *self {
+            NtpMode::SymmetricActive | NtpMode::SymmetricPassive => NtpRole::Peer,
+            NtpMode::Client => NtpRole::Client,
+            NtpMode::Server => NtpRole::Server,
+            NtpMode::Broadcast => NtpRole::Broadcast,
+            NtpMode::NtpControlMessage => NtpRole::Control,
+            NtpMode::Private => NtpRole::Private,
+            _ => NtpRole::Reserved,
+        }
+    }
+}
+
+impl fmt::Display for NtpMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            NtpMode::Reserved => "reserved",
+            NtpMode::SymmetricActive => "symmetric active",
+            NtpMode::SymmetricPassive => "symmetric passive",
+            NtpMode::Client => "client",
+            NtpMode::Server => "server",
+            NtpMode::Broadcast => "broadcast",
+            NtpMode::NtpControlMessage => "control message",
+            NtpMode::Private => "private",
+            _ => return write!(f, "unknown ({})", self.0),
+        };
+        f.write_str(s)
+    }
+}
+
+/// Role of the sender of an NTP packet, grouping the modes by function
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtpRole {
+    /// Client requesting time (mode 3)
+    Client,
+    /// Server answering a client (mode 4)
+    Server,
+    /// Symmetric peer (modes 1 and 2)
+    Peer,
+    /// Broadcast or multicast server (mode 5)
+    Broadcast,
+    /// Control message, used for monitoring (mode 6)
+    Control,
+    /// Private, implementation-specific message (mode 7)
+    Private,
+    /// Reserved or invalid mode
+    Reserved,
+}
+
+/// Leap indicator, as encoded in the first byte of the packet (2 bits)
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct LeapIndicator(pub u8);
+
+#[allow(non_upper_case_globals)]
+impl LeapIndicator {
+    pub const NoWarning: LeapIndicator = LeapIndicator(0);
+    /// The last minute of the day has 61 seconds
+    pub const LastMinute61: LeapIndicator = LeapIndicator(1);
+    /// The last minute of the day has 59 seconds
+    pub const LastMinute59: LeapIndicator = LeapIndicator(2);
+    /// Alarm condition: the clock is not synchronized
+    pub const Unsynchronized: LeapIndicator = LeapIndicator(3);
+}
+
+/// Decoded leap indicator, see `leap_indicator`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NtpLeapIndicator {
+    /// No leap second warning
+    NoWarning,
+    /// The last minute of the day has 61 seconds (a leap second is inserted)
+    AddSecond,
+    /// The last minute of the day has 59 seconds (a leap second is deleted)
+    SubSecond,
+    /// Alarm condition: the clock is not synchronized, the leap second state is unknown
+    Unknown,
+}
+
+impl From<LeapIndicator> for NtpLeapIndicator {
+    /// Only the 2 low bits of the value are used
+    fn from(li: LeapIndicator) -> NtpLeapIndicator {
+        match LeapIndicator(li.0 & 0b11) {
+            LeapIndicator::NoWarning => NtpLeapIndicator::NoWarning,
+            LeapIndicator::LastMinute61 => NtpLeapIndicator::AddSecond,
+            LeapIndicator::LastMinute59 => NtpLeapIndicator::SubSecond,
+            _ => NtpLeapIndicator::Unknown,
+        }
+    }
+}
+
+/// Band of the stratum of the sender of a packet
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum StratumKind {
+    /// Stratum 0: unspecified or invalid, used by Kiss-o'-Death packets
+    Unspecified,
+    /// Stratum 1: primary server, synchronized to a reference clock
+    Primary,
+    /// Strata 2 to 15: secondary server, synchronized to another server
+    Secondary,
+    /// Stratum 16: unsynchronized
+    Unsynchronized,
+    /// Strata 17 to 255: reserved
+    Reserved,
+}
+
+impl From<u8> for StratumKind {
+    fn from(stratum: u8) -> StratumKind {
+        match stratum {
+            0 => StratumKind::Unspecified,
+            1 => StratumKind::Primary,
+            2..=15 => StratumKind::Secondary,
+            16 => StratumKind::Unsynchronized,
+            _ => StratumKind::Reserved,
+        }
+    }
+}
+
+/// NTP protocol version, as encoded in the first byte of the packet
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NtpVersion(pub u8);
+
+impl NtpVersion {
+    pub const V1: NtpVersion = NtpVersion(1);
+    pub const V2: NtpVersion = NtpVersion(2);
+    pub const V3: NtpVersion = NtpVersion(3);
+    pub const V4: NtpVersion = NtpVersion(4);
+    pub const V5: NtpVersion = NtpVersion(5);
+}
+
 /// An NTP version 3 packet
-#[derive(Debug, PartialEq, NomBE)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NtpV3Packet<'a> {
-    #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
-    #[nom(Value(b0 >> 6))]
     pub li: u8,
-    #[nom(Value((b0 >> 3) & 0b111))]
     pub version: u8,
-    #[nom(Value(NtpMode(b0 & 0b111)))]
     pub mode: NtpMode,
     pub stratum: u8,
     pub poll: i8,
-    pub precision: i8,
-    pub root_delay: u32,
-    pub root_dispersion: u32,
+    pub precision: Precision,
+    pub root_delay: NtpShortFormat,
+    pub root_dispersion: NtpShortFormat,
     pub ref_id: u32,
-    pub ts_ref: u64,
-    pub ts_orig: u64,
-    pub ts_recv: u64,
-    pub ts_xmit: u64,
+    pub ts_ref: NtpTimestamp,
+    pub ts_orig: NtpTimestamp,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
 
-    #[nom(Parse = "opt(complete(take(12usize)))")]
+    /// Authenticator, 12 bytes (DES) or 20 bytes (MD5), see [`NtpV3AuthLayout`]
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, serialize_with = "crate::serde_util::serialize_option_bytes")
+    )]
     pub authenticator: Option<&'a [u8]>,
 }
 
+/// Layout of the authenticator of an NTP version 3 packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtpV3AuthLayout {
+    /// 4-byte key identifier and 8-byte DES-CBC checksum (RFC 1305, 12 bytes)
+    Des,
+    /// 4-byte key identifier and 16-byte MD5 digest (as in version 4, 20 bytes)
+    Md5,
+}
+
+// Parse the authenticator of a version 3 packet: the data following the header must be
+// empty, or exactly a DES (12 bytes) or MD5 (20 bytes) authenticator. Other lengths are a
+// malformed trailer, and are rejected.
+fn parse_v3_authenticator<'a, E>(i: &'a [u8]) -> NtpResult<'a, Option<&'a [u8]>, E>
+where
+    E: ParseError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    match i.len() {
+        0 => Ok((i, None)),
+        12 | 20 => map(take(i.len()), Some)(i),
+        _ => {
+            let e = E::from_external_error(i, ErrorKind::LengthValue, NtpError::TruncatedMac);
+            Err(Err::Error(e))
+        }
+    }
+}
+
+// Header shared by versions 1 to 4 (48 bytes), parsed before the authenticator or the
+// extension fields and MAC
+#[derive(NomBE)]
+#[nom(GenericErrors)]
+struct NtpHeader {
+    #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
+    #[nom(Value(b0 >> 6))]
+    li: u8,
+    #[nom(Value((b0 >> 3) & 0b111))]
+    version: u8,
+    #[nom(Value(NtpMode(b0 & 0b111)))]
+    mode: NtpMode,
+    stratum: u8,
+    poll: i8,
+    #[nom(Parse = "map(be_i8, Precision)")]
+    precision: Precision,
+    #[nom(Parse = "map(be_u32, NtpShortFormat)")]
+    root_delay: NtpShortFormat,
+    #[nom(Parse = "map(be_u32, NtpShortFormat)")]
+    root_dispersion: NtpShortFormat,
+    ref_id: u32,
+    #[nom(Parse = "map(be_u64, NtpTimestamp)")]
+    ts_ref: NtpTimestamp,
+    #[nom(Parse = "map(be_u64, NtpTimestamp)")]
+    ts_orig: NtpTimestamp,
+    #[nom(Parse = "map(be_u64, NtpTimestamp)")]
+    ts_recv: NtpTimestamp,
+    #[nom(Parse = "map(be_u64, NtpTimestamp)")]
+    ts_xmit: NtpTimestamp,
+}
+
 /// An NTP version 4 packet
-#[derive(Debug, PartialEq, NomBE)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NtpV4Packet<'a> {
+    pub li: u8,
+    pub version: u8,
+    pub mode: NtpMode,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: Precision,
+    pub root_delay: NtpShortFormat,
+    pub root_dispersion: NtpShortFormat,
+    pub ref_id: u32,
+    pub ts_ref: NtpTimestamp,
+    pub ts_orig: NtpTimestamp,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            borrow,
+            serialize_with = "crate::serde_util::serialize_slice",
+            deserialize_with = "crate::serde_util::deserialize_extensions"
+        )
+    )]
+    pub extensions: NtpExtensions<'a>,
+    /// Bytes covered by the MAC (header and extension fields), with the `header-bytes` feature
+    ///
+    /// This is empty if the packet was not parsed (for ex. built by
+    /// [`server_reply`](Self::server_reply)).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub header_bytes: HeaderBytes<'a>,
+    /// MAC, if present
+    ///
+    /// A 4-byte MAC (key identifier without digest) is parsed with an empty digest, see
+    /// [`MacTail`](crate::MacTail).
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    pub auth: Option<NtpMac<'a>>,
+    /// Bytes of the MAC (key identifier and digest), with the `header-bytes` feature
+    ///
+    /// This is empty if the packet has no MAC, or was not parsed. See
+    /// [`raw_auth_bytes`](Self::raw_auth_bytes).
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub auth_bytes: HeaderBytes<'a>,
+}
+
+/// Raw bytes of a part of a packet (the data covered by the MAC, or the MAC)
+///
+/// With the `header-bytes` feature, this is a slice of the input. Otherwise, this is a
+/// zero-sized placeholder, so the packet size does not change.
+#[cfg(feature = "header-bytes")]
+pub type HeaderBytes<'a> = &'a [u8];
+
+/// Raw bytes of a part of a packet (the data covered by the MAC, or the MAC)
+///
+/// With the `header-bytes` feature, this is a slice of the input. Otherwise, this is a
+/// zero-sized placeholder, so the packet size does not change.
+#[cfg(not(feature = "header-bytes"))]
+pub type HeaderBytes<'a> = core::marker::PhantomData<&'a [u8]>;
+
+// Return the bytes from `start` to `rem`, for ex. the bytes covered by the MAC, from the start
+// of the packet to the data following the extension fields
+#[cfg(feature = "header-bytes")]
+#[inline]
+fn raw_bytes<'a>(start: &'a [u8], rem: &[u8]) -> HeaderBytes<'a> {
+    &start[..start.len() - rem.len()]
+}
+
+#[cfg(not(feature = "header-bytes"))]
+#[inline]
+fn raw_bytes<'a>(_start: &'a [u8], _rem: &[u8]) -> HeaderBytes<'a> {
+    core::marker::PhantomData
+}
+
+impl<'a> NtpV3Packet<'a> {
+    /// Return the length of the packet on the wire, in bytes (header and authenticator)
+    pub fn wire_len(&self) -> usize {
+        48 + self.authenticator.map_or(0, <[u8]>::len)
+    }
+
+    /// Return the layout of the authenticator, or `None` if there is no authenticator
+    ///
+    /// The layout is identified by the length of the authenticator. Other lengths (for a
+    /// packet which was not parsed) return `None`.
+    pub fn authenticator_layout(&self) -> Option<NtpV3AuthLayout> {
+        match self.authenticator?.len() {
+            12 => Some(NtpV3AuthLayout::Des),
+            20 => Some(NtpV3AuthLayout::Md5),
+            _ => None,
+        }
+    }
+}
+
+impl NtpV4Packet<'static> {
+    /// Build a plausible server reply (mode 4), for ex. for tests
+    ///
+    /// The packet has no leap warning, version 4, a poll interval of 64s, a precision of
+    /// about 1 µs and zero root delay and dispersion. The reference timestamp is set to
+    /// `recv`. The origin timestamp is 0, and should be set to the transmit timestamp of the
+    /// request.
+    pub fn server_reply(
+        stratum: u8,
+        ref_id: u32,
+        recv: NtpTimestamp,
+        xmit: NtpTimestamp,
+    ) -> NtpV4Packet<'static> {
+        NtpV4Packet {
+            li: 0,
+            version: 4,
+            mode: NtpMode::Server,
+            stratum,
+            poll: 6,
+            precision: Precision(-20),
+            root_delay: NtpShortFormat(0),
+            root_dispersion: NtpShortFormat(0),
+            ref_id,
+            ts_ref: recv,
+            ts_orig: NtpTimestamp(0),
+            ts_recv: recv,
+            ts_xmit: xmit,
+            extensions: NtpExtensions::new(),
+            header_bytes: HeaderBytes::default(),
+            auth: None,
+            auth_bytes: HeaderBytes::default(),
+        }
+    }
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Return the length of the packet on the wire, in bytes
+    ///
+    /// This is the size of the header, the extension fields (padded to a multiple of 4 bytes)
+    /// and the MAC, computed from the parsed fields.
+    pub fn wire_len(&self) -> usize {
+        let ext_len: usize = self
+            .extensions
+            .iter()
+            .map(|e| 4 + ((e.value.len() + 3) & !3))
+            .sum();
+        let mac_len = self.auth.as_ref().map_or(0, |m| 4 + m.mac.len());
+        48 + ext_len + mac_len
+    }
+
+    /// Return true if the header, extension fields and MAC exactly cover `wire_len` bytes
+    ///
+    /// `wire_len` is the length of the packet on the wire (for ex. the UDP payload length).
+    /// Extension fields are counted with their 4-byte header and padding, so a mismatch
+    /// indicates either a malformed packet or a different interpretation of the length of
+    /// extension fields.
+    pub fn check_extension_alignment(&self, wire_len: usize) -> bool {
+        self.wire_len() == wire_len
+    }
+
+    pub fn get_precision(&self) -> f32 {
+        self.precision.to_seconds() as f32
+    }
+
+    /// Return the extension fields of the packet
+    ///
+    /// Prefer this accessor to the `extensions` field, whose type may change in the future.
+    #[inline]
+    pub fn extensions(&self) -> &[NtpExtension<'a>] {
+        &self.extensions
+    }
+
+    /// Return true if the packet has extension fields, but no MAC
+    ///
+    /// This is typical of NTS, where authentication is done by an extension field. Such
+    /// packets can only be parsed by [`parse_ntpv4_lenient_mac`].
+    pub fn has_extensions_without_mac(&self) -> bool {
+        !self.extensions.is_empty() && self.auth.is_none()
+    }
+
+    /// Return the raw bytes of the MAC (key identifier and digest), as seen on the wire
+    ///
+    /// This allows forwarding the MAC without rebuilding it. Returns `None` if the packet has
+    /// no MAC, or was not parsed.
+    #[cfg(feature = "header-bytes")]
+    pub fn raw_auth_bytes(&self) -> Option<&'a [u8]> {
+        match self.auth {
+            Some(_) if !self.auth_bytes.is_empty() => Some(self.auth_bytes),
+            _ => None,
+        }
+    }
+}
+
+/// Build the first byte of a packet from the leap indicator, version and mode
+///
+/// This is the inverse of the decoding done by the parsers. Values are truncated to the size
+/// of their bit field (2 bits for `li`, 3 bits for `version` and `mode`).
+#[inline]
+pub fn compose_flags_byte(li: u8, version: u8, mode: NtpMode) -> u8 {
+    ((li & 0b11) << 6) | ((version & 0b111) << 3) | (mode.0 & 0b111)
+}
+
+/// Direction of a packet, as guessed from its content
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Query,
+    Response,
+    Unknown,
+}
+
+/// Quality tier of the clock of a server, see `clock_quality`
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum ClockQuality {
+    Low,
+    Medium,
+    High,
+}
+
+/// Reference identifier, decoded according to the stratum, see `reference_id` (requires the
+/// `std` feature, for `std::net::Ipv4Addr`)
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RefId {
+    /// Kiss code (stratum 0), as ASCII bytes, for ex. `*b"DENY"`
+    KissCode([u8; 4]),
+    /// Reference clock identifier (stratum 1), as ASCII bytes, for ex. `*b"GPS\0"`
+    ClockSource([u8; 4]),
+    /// IPv4 address of the upstream server (strata 2 to 15)
+    ///
+    /// For an IPv6 upstream server, this is the first 4 bytes of the MD5 hash of the address,
+    /// which cannot be distinguished from an IPv4 address.
+    IpAddr(Ipv4Addr),
+    /// Other strata (unsynchronized or reserved)
+    Other(u32),
+}
+
+/// Order of magnitude of the expected accuracy of a server, see `ReferenceAccuracy`
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum AccuracyClass {
+    /// The clock is not synchronized, and should not be used
+    Unsynchronized,
+    /// Worse than 100 ms, or a local undisciplined clock
+    Coarse,
+    /// Between 1 ms and 100 ms
+    Millisecond,
+    /// Between 1 µs and 1 ms
+    Microsecond,
+    /// Better than 1 µs
+    SubMicrosecond,
+}
+
+impl fmt::Display for AccuracyClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            AccuracyClass::Unsynchronized => "unsynchronized",
+            AccuracyClass::Coarse => "coarse",
+            AccuracyClass::Millisecond => "millisecond",
+            AccuracyClass::Microsecond => "microsecond",
+            AccuracyClass::SubMicrosecond => "sub-microsecond",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Expected accuracy of the clock of a server, see `reference_accuracy`
+///
+/// The `Display` implementation returns a short label, for ex. `sub-microsecond GNSS primary`
+/// or `millisecond secondary`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReferenceAccuracy {
+    pub class: AccuracyClass,
+    /// True for a primary server (stratum 1)
+    pub primary: bool,
+    /// Category of the reference clock, for primary servers
+    pub source: Option<ClockCategory>,
+    /// Estimated bound of the error, in seconds (precision and root distance)
+    pub error_bound: f64,
+}
+
+impl fmt::Display for ReferenceAccuracy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.class)?;
+        if self.class == AccuracyClass::Unsynchronized {
+            return Ok(());
+        }
+        let source = match self.source {
+            Some(ClockCategory::Gnss) => " GNSS",
+            Some(ClockCategory::Radio) => " radio",
+            Some(ClockCategory::Pps) => " PPS",
+            Some(ClockCategory::Atomic) => " atomic",
+            Some(ClockCategory::Local) => " local",
+            None => "",
+        };
+        let stratum = if self.primary { "primary" } else { "secondary" };
+        write!(f, "{} {}", source, stratum)
+    }
+}
+
+/// The four timestamps of a packet, see `timestamps`
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct NtpTimestamps {
+    /// Time the system clock of the sender was last set
+    pub reference: NtpTimestamp,
+    /// Time the request was sent by the client (T1)
+    pub origin: NtpTimestamp,
+    /// Time the request was received by the server (T2)
+    pub receive: NtpTimestamp,
+    /// Time the packet was sent (T3)
+    pub transmit: NtpTimestamp,
+}
+
+/// Errors returned when checking the consistency of a parsed packet
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NtpValidationError {
+    /// The mode is reserved (0) and cannot be used
+    InvalidMode,
+    /// The packet carries time from a clock which is not synchronized (leap indicator 3)
+    Unsynchronized,
+}
+
+/// Methods shared by the NTP version 3 and version 4 packets, which have the same header
+macro_rules! impl_ntp_header_methods {
+    ($t:ident) => {
+        impl<'a> $t<'a> {
+            /// Return the first byte of the packet (leap indicator, version and mode)
+            ///
+            /// All the bits of this byte are stored in the `li`, `version` and `mode` fields,
+            /// so this returns the exact byte of a parsed packet.
+            #[inline]
+            pub fn flags_byte(&self) -> u8 {
+                compose_flags_byte(self.li, self.version, self.mode)
+            }
+
+            /// Return the reference timestamp (time the system clock was last set)
+            #[inline]
+            pub fn reference_timestamp(&self) -> NtpTimestamp {
+                self.ts_ref
+            }
+
+            /// Return the origin timestamp (time the request was sent by the client)
+            #[inline]
+            pub fn origin_timestamp(&self) -> NtpTimestamp {
+                self.ts_orig
+            }
+
+            /// Return the receive timestamp (time the request was received by the server)
+            #[inline]
+            pub fn receive_timestamp(&self) -> NtpTimestamp {
+                self.ts_recv
+            }
+
+            /// Return the transmit timestamp (time the packet was sent)
+            #[inline]
+            pub fn transmit_timestamp(&self) -> NtpTimestamp {
+                self.ts_xmit
+            }
+
+            /// Return the four timestamps of the packet
+            pub fn timestamps(&self) -> NtpTimestamps {
+                NtpTimestamps {
+                    reference: self.ts_ref,
+                    origin: self.ts_orig,
+                    receive: self.ts_recv,
+                    transmit: self.ts_xmit,
+                }
+            }
+
+            /// Guess the direction of the packet, without transport-layer information
+            ///
+            /// Client packets are queries, server and broadcast packets are responses. For
+            /// symmetric modes, a packet with a zero origin timestamp is a fresh query, and
+            /// other packets are responses to the previous packet of the peer. The direction
+            /// of other modes is unknown.
+            pub fn direction(&self) -> Direction {
+                match self.mode {
+                    NtpMode::Client => Direction::Query,
+                    NtpMode::Server | NtpMode::Broadcast => Direction::Response,
+                    NtpMode::SymmetricActive | NtpMode::SymmetricPassive => {
+                        if self.ts_orig.is_unset() {
+                            Direction::Query
+                        } else {
+                            Direction::Response
+                        }
+                    }
+                    _ => Direction::Unknown,
+                }
+            }
+
+            /// Return the key identifying the exchange of this packet, to match requests and
+            /// responses
+            ///
+            /// A server copies the transmit timestamp of the request into the origin timestamp
+            /// of the response, so the key is the transmit timestamp for requests, and the
+            /// origin timestamp for responses, as guessed by [`direction`](Self::direction).
+            /// Broadcast packets are not responses to a request, and packets of unknown
+            /// direction are treated as requests: their key is the transmit timestamp.
+            pub fn exchange_key(&self) -> NtpTimestamp {
+                match (self.direction(), self.mode) {
+                    (_, NtpMode::Broadcast) => self.ts_xmit,
+                    (Direction::Response, _) => self.ts_orig,
+                    _ => self.ts_xmit,
+                }
+            }
+
+            /// Return the poll interval, or `None` if the exponent is out of range
+            ///
+            /// The poll field is the base-2 logarithm of the interval in seconds. RFC 5905
+            /// limits it from 4 (16 s) to 17 (36 h), but some implementations poll faster, down
+            /// to 2^-6 s, so values from -6 to 17 are accepted.
+            pub fn poll_interval_checked(&self) -> Option<Duration> {
+                match self.poll {
+                    0..=17 => Some(Duration::from_secs(1 << self.poll)),
+                    -6..=-1 => Some(Duration::from_nanos(1_000_000_000 >> -self.poll)),
+                    _ => None,
+                }
+            }
+
+            /// Return the poll interval in seconds
+            ///
+            /// Unlike [`poll_interval_checked`](Self::poll_interval_checked), the exponent is
+            /// not range-checked: negative values give sub-second intervals.
+            #[inline]
+            pub fn poll_interval(&self) -> f64 {
+                pow2(i32::from(self.poll))
+            }
+
+            /// Return the precision of the system clock in seconds
+            #[inline]
+            pub fn precision_seconds(&self) -> f64 {
+                self.precision.to_seconds()
+            }
+
+            /// Return the precision of the system clock as a duration
+            ///
+            /// Returns `None` if the exponent is too large to be represented as a duration.
+            pub fn precision_duration(&self) -> Option<Duration> {
+                Duration::try_from_secs_f64(self.precision.to_seconds()).ok()
+            }
+
+            /// Return the root delay in seconds
+            #[inline]
+            pub fn root_delay_seconds(&self) -> f64 {
+                self.root_delay.to_seconds()
+            }
+
+            /// Return the root delay as a duration, rounded to the nearest nanosecond
+            #[inline]
+            pub fn root_delay_duration(&self) -> Duration {
+                self.root_delay.to_duration()
+            }
+
+            /// Return the root dispersion in seconds
+            #[inline]
+            pub fn root_dispersion_seconds(&self) -> f64 {
+                self.root_dispersion.to_seconds()
+            }
+
+            /// Return the root dispersion as a duration, rounded to the nearest nanosecond
+            #[inline]
+            pub fn root_dispersion_duration(&self) -> Duration {
+                self.root_dispersion.to_duration()
+            }
+
+            /// Return a description of the stratum of the sender
+            ///
+            /// Stratum 0 is unspecified (or a Kiss-o'-Death packet), 1 is a primary server,
+            /// 2 to 15 a secondary server, 16 an unsynchronized server, and greater values
+            /// are reserved.
+            pub fn stratum_description(&self) -> &'static str {
+                match self.stratum_kind() {
+                    StratumKind::Unspecified => "unspecified/kiss",
+                    StratumKind::Primary => "primary reference",
+                    StratumKind::Secondary => "secondary reference",
+                    StratumKind::Unsynchronized => "unsynchronized",
+                    StratumKind::Reserved => "reserved",
+                }
+            }
+
+            /// Return the band of the stratum of the sender, see [`StratumKind`]
+            #[inline]
+            pub fn stratum_kind(&self) -> StratumKind {
+                StratumKind::from(self.stratum)
+            }
+
+            /// Return the leap indicator
+            ///
+            /// [`NtpLeapIndicator::Unknown`] (3) is the alarm condition, sent by servers whose
+            /// clock is not synchronized.
+            #[inline]
+            pub fn leap_indicator(&self) -> NtpLeapIndicator {
+                NtpLeapIndicator::from(LeapIndicator(self.li))
+            }
+
+            /// Return the reference identifier as an ASCII string, regardless of the stratum
+            ///
+            /// Returns `None` unless all bytes are printable ASCII characters. Identifiers
+            /// shorter than 4 characters are padded with zeroes, which are removed. This is
+            /// useful for vendor-specific identifiers (for ex. PTP grandmasters).
+            pub fn ref_id_as_ascii(&self) -> Option<String> {
+                let b = self.ref_id.to_be_bytes();
+                let len = b.iter().position(|&c| c == 0).unwrap_or(4);
+                if len == 0
+                    || !b[..len].iter().all(|&c| c == b' ' || c.is_ascii_graphic())
+                    || b[len..].iter().any(|&c| c != 0)
+                {
+                    return None;
+                }
+                Some(b[..len].iter().map(|&c| char::from(c)).collect())
+            }
+
+            /// Decode the reference identifier according to the stratum
+            ///
+            /// The bytes of kiss codes and clock identifiers are returned as is (see
+            /// [`kiss_code`](Self::kiss_code) to decode kiss codes). This requires the `std`
+            /// feature; without it, use the raw `ref_id` field.
+            #[cfg(feature = "std")]
+            pub fn reference_id(&self) -> RefId {
+                let b = self.ref_id.to_be_bytes();
+                match self.stratum {
+                    0 => RefId::KissCode(b),
+                    1 => RefId::ClockSource(b),
+                    2..=15 => RefId::IpAddr(Ipv4Addr::from(self.ref_id)),
+                    _ => RefId::Other(self.ref_id),
+                }
+            }
+
+            /// Guess the category of the reference clock of a primary server (stratum 1)
+            ///
+            /// See [`ClockCategory::from_ref_id`]. Returns `None` for other strata.
+            pub fn clock_source_category(&self) -> Option<ClockCategory> {
+                if self.stratum == 1 {
+                    ClockCategory::from_ref_id(self.ref_id)
+                } else {
+                    None
+                }
+            }
+
+            /// Decode a reference identifier of the form `127.127.t.u` (local reference clock)
+            ///
+            /// See [`LocalRefClock::from_ref_id`]. This form does not conflict with the
+            /// ASCII identifiers of primary servers, so it is decoded for all strata.
+            pub fn local_refclock(&self) -> Option<LocalRefClock> {
+                LocalRefClock::from_ref_id(self.ref_id)
+            }
+
+            /// Return the kiss code of a Kiss-o'-Death packet (stratum 0)
+            pub fn kiss_code(&self) -> Option<KissCode> {
+                if self.stratum == 0 {
+                    KissCode::from_ref_id(self.ref_id)
+                } else {
+                    None
+                }
+            }
+
+            /// Decode the reference identifier as a kiss code, regardless of the stratum
+            ///
+            /// Returns `None` if the reference identifier is not printable ASCII. This is
+            /// useful to show the status codes (for ex. `INIT`) sent by the reference
+            /// implementation when it is not synchronized (stratum 16).
+            pub fn ref_id_kiss_code(&self) -> Option<KissCode> {
+                KissCode::from_ref_id(self.ref_id)
+            }
+
+            /// Return true if the packet has the shape of an SNTP client request ([RFC4330])
+            ///
+            /// The mode must be client (3), the version 3 or 4, the stratum 0 (unspecified), and
+            /// the reference, origin and receive timestamps must be zero. The transmit timestamp
+            /// (which should be set by the client, and is copied by the server in the origin
+            /// timestamp of the reply), the leap indicator, poll, precision, root delay, root
+            /// dispersion and reference identifier are not checked.
+            ///
+            /// [RFC4330]: https://tools.ietf.org/html/rfc4330
+            pub fn is_valid_sntp_request(&self) -> bool {
+                self.mode == NtpMode::Client
+                    && (self.version == 3 || self.version == 4)
+                    && self.stratum == 0
+                    && self.ts_ref.is_unset()
+                    && self.ts_orig.is_unset()
+                    && self.ts_recv.is_unset()
+            }
+
+            /// Return true if the clock of the sender is synchronized
+            ///
+            /// This only checks the leap indicator, which is 3 (alarm condition) if the clock
+            /// is not synchronized. Clients commonly send requests with this value, so a false
+            /// result is only significant for replies (see [`validate`](Self::validate)).
+            pub fn is_synchronized(&self) -> bool {
+                self.li != 3
+            }
+
+            /// Classify the clock of the sender into a quality tier
+            ///
+            /// This is a coarse heuristic, combining the stratum, the precision and the root
+            /// dispersion:
+            ///
+            /// - `High`: stratum 1 or 2, precision of 2^-18 s (about 4 µs) or better, and root
+            ///   dispersion below 10 ms
+            /// - `Medium`: stratum 1 to 4, precision of 2^-10 s (about 1 ms) or better, and
+            ///   root dispersion below 100 ms
+            /// - `Low`: all other packets, including packets from an unsynchronized clock
+            ///   (leap indicator 3, stratum 0 or 16 and above)
+            pub fn clock_quality(&self) -> ClockQuality {
+                if !self.is_synchronized() {
+                    return ClockQuality::Low;
+                }
+                let dispersion = self.root_dispersion.to_seconds();
+                match (self.stratum, self.precision.0) {
+                    (1..=2, p) if p <= -18 && dispersion < 0.01 => ClockQuality::High,
+                    (1..=4, p) if p <= -10 && dispersion < 0.1 => ClockQuality::Medium,
+                    _ => ClockQuality::Low,
+                }
+            }
+
+            /// Estimate the accuracy of the clock of the sender
+            ///
+            /// The error bound is the precision plus the root distance, and is mapped to an
+            /// order of magnitude. The reference clock of a primary server is guessed from
+            /// the reference identifier, and a local clock is always `Coarse`. Packets from an
+            /// unsynchronized clock (leap indicator 3, stratum 0 or 16 and above) are
+            /// `Unsynchronized`.
+            pub fn reference_accuracy(&self) -> ReferenceAccuracy {
+                let source = self.clock_source_category();
+                let error_bound = self.precision.to_seconds() + self.root_distance();
+                let class = if !self.is_synchronized() || !(1..=15).contains(&self.stratum) {
+                    AccuracyClass::Unsynchronized
+                } else if source == Some(ClockCategory::Local) || error_bound >= 0.1 {
+                    AccuracyClass::Coarse
+                } else if error_bound >= 1e-3 {
+                    AccuracyClass::Millisecond
+                } else if error_bound >= 1e-6 {
+                    AccuracyClass::Microsecond
+                } else {
+                    AccuracyClass::SubMicrosecond
+                };
+                ReferenceAccuracy {
+                    class,
+                    primary: self.stratum == 1,
+                    source,
+                    error_bound,
+                }
+            }
+
+            /// Check the consistency of the packet, taking its direction into account
+            ///
+            /// The leap indicator is only checked for packets carrying time that a receiver
+            /// would use (symmetric passive, server and broadcast modes): a server replying
+            /// with an unsynchronized clock must not be trusted, while the same value is
+            /// normal in a client request.
+            pub fn validate(&self) -> Result<(), NtpValidationError> {
+                match self.mode {
+                    NtpMode::Reserved => Err(NtpValidationError::InvalidMode),
+                    NtpMode::SymmetricPassive | NtpMode::Server | NtpMode::Broadcast
+                        if !self.is_synchronized() =>
+                    {
+                        Err(NtpValidationError::Unsynchronized)
+                    }
+                    _ => Ok(()),
+                }
+            }
+
+            /// Check that the timestamps of the packet are plausible, compared to `now`
+            ///
+            /// Returns false if the transmit timestamp is unset (all zeros), all ones, or not
+            /// within `max_skew` of `now`, or if the reference timestamp is later than
+            /// `now + max_skew`. Timestamps are compared using wrapping arithmetic, so this
+            /// works across an era boundary as long as the values are less than 68 years apart.
+            pub fn timestamps_plausible(&self, now: NtpTimestamp, max_skew: Duration) -> bool {
+                if self.ts_xmit.is_unset() || self.ts_xmit.0 == u64::MAX {
+                    return false;
+                }
+                let max_skew = duration_to_fixed64(max_skew);
+                let xmit_delta = self.ts_xmit.wrapping_diff(now);
+                if xmit_delta.unsigned_abs() > max_skew {
+                    return false;
+                }
+                let ref_delta = self.ts_ref.wrapping_diff(now);
+                self.ts_ref.is_unset() || ref_delta <= 0 || ref_delta.unsigned_abs() <= max_skew
+            }
+
+            /// Return the time elapsed between the last synchronization of the sender and the
+            /// transmission of the packet
+            ///
+            /// This is the difference between the transmit and reference timestamps. Returns
+            /// `None` if the reference timestamp is unset (the sender was never synchronized),
+            /// or later than the transmit timestamp.
+            pub fn time_since_sync(&self) -> Option<Duration> {
+                if self.ts_ref.is_unset() {
+                    return None;
+                }
+                let delta = self.ts_xmit.wrapping_diff(self.ts_ref);
+                if delta < 0 {
+                    return None;
+                }
+                Some(fixed64_to_duration(delta as u64))
+            }
+
+            /// Compute the offset of the local clock, in seconds, from a reply to a request
+            ///
+            /// `destination` is the local time when this reply was received. The packet
+            /// timestamps are T1 (origin), T2 (receive), and T3 (transmit), and the offset is
+            /// `((T2 - T1) + (T3 - T4)) / 2` (RFC 5905 section 8). Differences are computed
+            /// using wrapping arithmetic, so the result stays correct across an era boundary.
+            pub fn clock_offset(&self, destination: NtpTimestamp) -> f64 {
+                let d1 = self.ts_recv.wrapping_diff(self.ts_orig);
+                let d2 = self.ts_xmit.wrapping_diff(destination);
+                // do not add the differences as integers, which could overflow
+                (fixed64_to_seconds(d1) + fixed64_to_seconds(d2)) / 2.0
+            }
+
+            /// Compute the round-trip delay, in seconds, from a reply to a request
+            ///
+            /// `destination` is the local time when this reply was received (T4). The delay is
+            /// `(T4 - T1) - (T3 - T2)` (RFC 5905 section 8), see
+            /// [`clock_offset`](Self::clock_offset).
+            pub fn round_trip_delay(&self, destination: NtpTimestamp) -> f64 {
+                let d1 = destination.wrapping_diff(self.ts_orig);
+                let d2 = self.ts_xmit.wrapping_diff(self.ts_recv);
+                fixed64_to_seconds(d1) - fixed64_to_seconds(d2)
+            }
+
+            /// Estimate the dispersion, in seconds, of the time provided by the sender after
+            /// `since` has elapsed since the reception of this packet
+            ///
+            /// Following RFC 5905 (section 10), the dispersion is the root dispersion of the
+            /// packet, increased by the precision of the sender and by the frequency
+            /// tolerance `phi` (in seconds per second, 15e-6 in RFC 5905) during the poll
+            /// interval and the elapsed time. This can be used to model the degradation of time
+            /// quality along a hierarchy of servers.
+            pub fn effective_dispersion(&self, since: Duration, phi: f64) -> f64 {
+                let poll_interval = pow2(i32::from(self.poll));
+                self.root_dispersion.to_seconds()
+                    + self.precision.to_seconds()
+                    + phi * (poll_interval + since.as_secs_f64())
+            }
+
+            /// Return the root distance (lambda), in seconds
+            ///
+            /// This is `root_delay / 2 + root_dispersion`, the maximum error of the time of
+            /// the sender relative to the primary reference (RFC 5905 section 11.2), used to
+            /// select among servers.
+            pub fn root_distance(&self) -> f64 {
+                self.root_delay.to_seconds() / 2.0 + self.root_dispersion.to_seconds()
+            }
+
+            /// Return true if the low-order bits of the transmit timestamp look crafted
+            ///
+            /// This is a **non-normative** heuristic, to help detecting spoofed packets: the
+            /// low 16 bits of the fraction (about 15 µs) of a timestamp read from a system
+            /// clock are practically random, and implementations commonly fill the bits below
+            /// the precision with random data. They are considered suspicious if they are all
+            /// zeros, all ones, or the same byte repeated. About 1 legitimate packet in 256
+            /// is flagged, so this should only be combined with other indicators.
+            pub fn transmit_timestamp_entropy_suspicious(&self) -> bool {
+                let low = (self.ts_xmit.0 & 0xffff) as u16;
+                let [hi, lo] = low.to_be_bytes();
+                hi == lo
+            }
+        }
+    };
+}
+
+impl_ntp_header_methods!(NtpV3Packet);
+impl_ntp_header_methods!(NtpV4Packet);
+
+/// The collection of extension fields of an NTP version 4 packet
+///
+/// With the `smallvec` feature, up to 2 extensions are stored inline, avoiding a heap
+/// allocation for most packets.
+#[cfg(not(feature = "smallvec"))]
+pub type NtpExtensions<'a> = Vec<NtpExtension<'a>>;
+
+/// The collection of extension fields of an NTP version 4 packet
+///
+/// With the `smallvec` feature, up to 2 extensions are stored inline, avoiding a heap
+/// allocation for most packets.
+#[cfg(feature = "smallvec")]
+pub type NtpExtensions<'a> = smallvec::SmallVec<[NtpExtension<'a>; 2]>;
+
+/// An NTP version 4 extension field ([RFC7822])
+///
+/// The length is the length of the entire field, including the 4-byte type and length header
+/// and the padding: it must be at least 4, and should be a multiple of 4. The value is the
+/// `length - 4` following bytes. If the length is not a multiple of 4, the padding up to the
+/// next 4-byte boundary is skipped by the parser, and is not part of the value.
+///
+/// The length is a 16-bit field, so the value of a single field is limited to 65531 bytes.
+/// Larger data (for ex. certificate chains) must be split across several fields. The value
+/// is borrowed from the input, so large fields are not copied.
+///
+/// [RFC7822]: https://tools.ietf.org/html/rfc7822
+#[derive(Clone, Debug, Eq, Hash, PartialEq, NomBE)]
+#[nom(GenericErrors)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpExtension<'a> {
+    pub field_type: u16,
+    #[nom(Verify = "*length >= 4")]
+    pub length: u16,
+    #[nom(Parse = "take(length - 4)")]
+    #[nom(PostExec = "let (i, _) = take(extension_padding_len(length))(i)?;")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, serialize_with = "crate::serde_util::serialize_bytes")
+    )]
+    pub value: &'a [u8],
+}
+
+// Return the number of padding bytes following an extension field of `length` bytes
+#[inline]
+pub(crate) fn extension_padding_len(length: u16) -> usize {
+    (4 - usize::from(length) % 4) % 4
+}
+
+/// A message authentication code: key identifier and digest
+///
+/// The digest is 16 bytes long for MD5 and AES-CMAC keys, and 20 bytes long for SHA-1 keys.
+/// It is empty for a crypto-NAK (key identifier alone). [`NtpMac::parse`] parses a 16-byte
+/// digest, but the MAC of a version 4 packet is parsed with all the data following the
+/// extension fields as digest.
+#[derive(Clone, Debug, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpMac<'a> {
+    pub key_id: u32,
+    #[nom(Parse = "take(16usize)")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(borrow, serialize_with = "crate::serde_util::serialize_bytes")
+    )]
+    pub mac: &'a [u8],
+}
+
+// Parse the MAC following the extensions: the digest is all the data following the key
+// identifier, and is empty for a key identifier alone (crypto-NAK, or malformed MAC)
+fn parse_ntpv4_mac<'a, E: ParseError<&'a [u8]>>(
+    i: &'a [u8],
+) -> NtpResult<'a, Option<NtpMac<'a>>, E> {
+    if i.is_empty() {
+        return Ok((i, None));
+    }
+    let (mac, key_id) = be_u32(i)?;
+    Ok((&mac[mac.len()..], Some(NtpMac { key_id, mac })))
+}
+
+#[inline]
+pub fn parse_ntp_extension(i: &[u8]) -> IResult<&[u8], NtpExtension<'_>> {
+    NtpExtension::parse(i)
+}
+
+// Split the data following the header into the extensions region and the MAC.
+//
+// See section 7.5 of [RFC5905] and [RFC7822]:
+// In NTPv4, one or more extension fields can be inserted after the
+//    header and before the MAC, which is always present when an extension
+//    field is present.
+//
+// The MAC is a key identifier followed by a 16-byte (MD5, AES-CMAC) or 20-byte (SHA-1)
+// digest, or a key identifier alone (crypto-NAK). The extension fields are skipped (using
+// their length only) until the remaining data has one of these sizes:
+//  if == 0,  nothing (only if there are no extensions)
+//  if == 4,  only key identifier (crypto-NAK)
+//  if == 20, only MAC (MD5)
+//  if == 24, only MAC (SHA-1), unless the data starts with a 4-byte extension field
+//  else      ext + MAC, or error
+//
+// The error is `TruncatedMac` if the data which is not an extension field is shorter than a
+// MAC, and `InvalidExtensionLength` otherwise.
+pub(crate) fn take_extensions_region<'a, E>(i: &'a [u8]) -> NtpResult<'a, &'a [u8], E>
+where
+    E: ParseError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    let mut rem = i;
+    while !i.is_empty() && !is_mac_len(rem) {
+        match extension_field_len(rem) {
+            Some(len) => rem = &rem[len..],
+            None => {
+                let cause = match MacTail::classify(rem) {
+                    MacTail::Truncated { .. } => NtpError::TruncatedMac,
+                    _ => NtpError::InvalidExtensionLength,
+                };
+                return Err(Err::Error(E::from_external_error(
+                    rem,
+                    ErrorKind::Eof,
+                    cause,
+                )));
+            }
+        }
+    }
+    Ok((rem, &i[..i.len() - rem.len()]))
+}
+
+// Return the length of the extension field at the start of `i`, including the padding, or
+// `None` if the length is invalid or exceeds the data
+fn extension_field_len(i: &[u8]) -> Option<usize> {
+    let length = u16::from_be_bytes([*i.get(2)?, *i.get(3)?]);
+    let len = usize::from(length) + extension_padding_len(length);
+    if length < 4 || len > i.len() {
+        return None;
+    }
+    Some(len)
+}
+
+// Return true if the data following the extension fields has the size of a MAC
+//
+// A 24-byte MAC is ambiguous with a 4-byte extension field (for ex. padding) followed by a
+// 20-byte MAC: the latter is assumed if the key identifier would be a valid field header.
+fn is_mac_len(i: &[u8]) -> bool {
+    match i.len() {
+        4 | 20 => true,
+        24 => i[2..4] != [0, 4],
+        _ => false,
+    }
+}
+
+// Split the data following the header, assuming a MAC of `mac_len` bytes (see above)
+fn take_extensions_region_mac_len<'a, E>(i: &'a [u8], mac_len: usize) -> NtpResult<'a, &'a [u8], E>
+where
+    E: ParseError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    if i.is_empty() || i.len() == 4 || i.len() == mac_len {
+        // if empty, or if remaining length is exactly the MAC length or the key identifier
+        // length (4), assume we do not have extensions
+        return Ok((i, &i[..0]));
+    }
+    if i.len() < mac_len {
+        let e = E::from_external_error(i, ErrorKind::Eof, NtpError::TruncatedMac);
+        return Err(Err::Error(e));
+    }
+    take(i.len() - mac_len)(i)
+}
+
+/// Maximum number of extension fields in a packet
+///
+/// Parsing a packet with more extension fields fails, which prevents allocating large
+/// vectors for malicious packets made of many small fields.
+pub const MAX_EXTENSIONS: usize = 64;
+
+// Parse a list of one or more extension fields, stopping at the first error.
+//
+// Fails if there are more than `MAX_EXTENSIONS` fields.
+pub(crate) fn parse_extension_list<'a, 'i: 'a, E: ParseError<&'i [u8]>>(
+    i: &'i [u8],
+) -> NtpResult<'i, NtpExtensions<'a>, E> {
+    let mut extensions = NtpExtensions::new();
+    let mut i = i;
+    loop {
+        match complete(NtpExtension::parse)(i) {
+            Ok((rem, ext)) => {
+                if extensions.len() == MAX_EXTENSIONS {
+                    return Err(Err::Error(E::from_error_kind(i, ErrorKind::TooLarge)));
+                }
+                extensions.push(ext);
+                i = rem;
+            }
+            Err(Err::Error(_)) if !extensions.is_empty() => return Ok((i, extensions)),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Attempt to parse extensions, assuming a MAC of `mac_len` bytes.
+//
+// The lifetime of the result is not tied to the input, because `SmallVec` is invariant.
+fn parse_extensions_mac_len<'a, 'i: 'a>(
+    i: &'i [u8],
+    mac_len: usize,
+) -> IResult<&'i [u8], NtpExtensions<'a>> {
+    let (rem, region) = take_extensions_region_mac_len(i, mac_len)?;
+    if region.is_empty() {
+        return Ok((rem, NtpExtensions::new()));
+    }
+    let (_, extensions) = parse_extension_list(region)?;
+    Ok((rem, extensions))
+}
+
+// Attempt to parse extensions, locating the MAC from its length (see `take_extensions_region`)
+fn try_parse_extensions<'a, 'i: 'a, E>(i: &'i [u8]) -> NtpResult<'i, NtpExtensions<'a>, E>
+where
+    E: ParseError<&'i [u8]> + FromExternalError<&'i [u8], NtpError>,
+{
+    let (rem, region) = take_extensions_region(i)?;
+    if region.is_empty() {
+        return Ok((rem, NtpExtensions::new()));
+    }
+    let (_, extensions) = parse_extension_list(region)?;
+    Ok((rem, extensions))
+}
+
+/// Parse the extension fields preceding a MAC of `mac_len` bytes
+///
+/// The input is the data following the header (extension fields and MAC), and the remaining
+/// data (the MAC) is returned. The boundary follows [RFC7822]: if the data is empty, or exactly
+/// 4 bytes (a key identifier alone) or `mac_len` bytes, there are no extension fields;
+/// otherwise the last `mac_len` bytes are the MAC. Parsing fails if the data is shorter than
+/// the MAC, or if the extension fields are invalid.
+///
+/// [RFC7822]: https://tools.ietf.org/html/rfc7822
+pub fn parse_extensions_region(i: &[u8], mac_len: usize) -> IResult<&[u8], Vec<NtpExtension<'_>>> {
+    let (rem, extensions) = parse_extensions_mac_len(i, mac_len)?;
+    Ok((rem, extensions.into_iter().collect()))
+}
+
+/// Call `f` for each extension field, without storing them
+///
+/// The input is the data following the NTP version 4 header (extensions and MAC). Extensions
+/// are located and parsed exactly as when parsing a packet, and the remaining data (the MAC)
+/// is returned.
+pub fn for_each_extension<F>(i: &[u8], f: F) -> IResult<&[u8], ()>
+where
+    F: FnMut(&NtpExtension),
+{
+    let mut f = f;
+    let (rem, region) = take_extensions_region(i)?;
+    if !region.is_empty() {
+        fold_many1(complete(parse_ntp_extension), || (), |_, ext| f(&ext))(region)?;
+    }
+    Ok((rem, ()))
+}
+
+/// Iterator over the extension fields of an NTP version 4 packet, see [`iter_extensions`]
+#[derive(Clone, Debug)]
+pub struct NtpExtensionIter<'a> {
+    region: Result<&'a [u8], NtpError>,
+    count: usize,
+}
+
+/// Return an iterator over the extension fields, without storing them
+///
+/// The input is the data following the NTP version 4 header (extensions and MAC). Extensions
+/// are located and parsed exactly as when parsing a packet, so the iterator yields the same
+/// fields as the `extensions` of the packet, and stops before the MAC. Nothing is parsed
+/// before the first call to `next`, and iteration can stop early, for ex. with `find`.
+///
+/// If the extensions cannot be located, or if there are more than [`MAX_EXTENSIONS`] fields,
+/// an error is returned as the last item.
+pub fn iter_extensions(i: &[u8]) -> NtpExtensionIter<'_> {
+    let region = take_extensions_region::<NtpError>(i)
+        .map(|(_, region)| region)
+        .map_err(NtpError::from);
+    NtpExtensionIter { region, count: 0 }
+}
+
+impl<'a> Iterator for NtpExtensionIter<'a> {
+    type Item = Result<NtpExtension<'a>, NtpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let region = match self.region {
+            Ok([]) => return None,
+            Ok(region) => region,
+            Err(e) => {
+                self.region = Ok(&[]);
+                return Some(Err(e));
+            }
+        };
+        if self.count == MAX_EXTENSIONS {
+            self.region = Ok(&[]);
+            return Some(Err(NtpError::Nom(ErrorKind::TooLarge)));
+        }
+        match complete(parse_ntp_extension)(region) {
+            Ok((rem, ext)) => {
+                self.region = Ok(rem);
+                self.count += 1;
+                Some(Ok(ext))
+            }
+            Err(e) => {
+                self.region = Ok(&[]);
+                Some(Err(NtpError::from(e)))
+            }
+        }
+    }
+}
+
+/// Size of the largest mode 6 response fragment sent by ntpd (12-byte header, 468 bytes of data)
+const CONTROL_MAX_RESPONSE_LEN: usize = 12 + 468;
+
+/// Size of a full mode 7 `monlist` response from ntpd (100 packets of 440 bytes)
+const PRIVATE_MONLIST_RESPONSE_LEN: usize = 100 * 440;
+
+/// Return the typical size, in bytes, of the response(s) elicited by `request`
+///
+/// - client and symmetric active requests get a response of the same size as the request
+///   (a server copies the extensions and adds a MAC if the request is authenticated)
+/// - control (mode 6) requests get up to one full fragment (480 bytes), and possibly more
+/// - private (mode 7) requests get up to the size of a full `monlist` response (44000 bytes)
+/// - other modes are not requests, and do not elicit a response (0)
+///
+/// This is an estimation, useful to evaluate the amplification factor of requests.
+pub fn expected_response_len(request: &NtpPacket) -> usize {
+    let (mode, len) = match request {
+        NtpPacket::V3(pkt) => (pkt.mode, pkt.wire_len()),
+        NtpPacket::V4(pkt) => (pkt.mode, pkt.wire_len()),
+        NtpPacket::Control(msg) => (msg.mode, 0),
+        #[cfg(feature = "ntpv5")]
+        NtpPacket::V5(pkt) => (pkt.mode, pkt.wire_len()),
+    };
+    match mode {
+        NtpMode::Client | NtpMode::SymmetricActive => len,
+        NtpMode::NtpControlMessage => CONTROL_MAX_RESPONSE_LEN,
+        NtpMode::Private => PRIVATE_MONLIST_RESPONSE_LEN,
+        _ => 0,
+    }
+}
+
+/// Return the amplification factor of an exchange: the ratio of the response size to the
+/// request size
+///
+/// Sizes are in bytes, and can be measured at any layer as long as both use the same (for ex.
+/// UDP payloads). `response_len` is the total size of all the responses to the request.
+/// Returns 0 if `request_len` is 0.
+///
+/// With [`expected_response_len`], this estimates the amplification of a request, for ex. the
+/// classic `monlist` attack, where a small private (mode 7) request elicits 100 response
+/// packets:
+///
+/// ```rust
+/// use ntp_parser::{amplification_factor, expected_response_len, parse_ntp};
+///
+/// let mut request = vec![0x27]; // version 4, mode 7
+/// request.resize(48, 0);
+/// let (_, pkt) = parse_ntp(&request).unwrap();
+/// let factor = amplification_factor(request.len(), expected_response_len(&pkt));
+/// assert!(factor > 900.0); // 44000 / 48
+/// ```
+pub fn amplification_factor(request_len: usize, response_len: usize) -> f64 {
+    if request_len == 0 {
+        return 0.0;
+    }
+    response_len as f64 / request_len as f64
+}
+
+/// Return true if `response` is a reply in interleaved mode to `request`
+///
+/// Version 5 packets (with the `ntpv5` feature) signal the interleaved mode explicitly, see
+/// `NtpV5Packet::is_interleaved`: the flag of the response is used, and `request` is
+/// ignored.
+///
+/// Versions 3 and 4 have no such flag, and the mode is guessed from the timestamps: in basic
+/// mode, the origin timestamp of the response is the transmit timestamp of the request, while
+/// in interleaved mode it is the receive timestamp of the request (the receive time of the
+/// previous response). This heuristic needs the request, and returns false if the timestamps
+/// are ambiguous (equal or zero). Control messages and packets of different versions return
+/// false.
+pub fn is_interleaved_response(request: &NtpPacket, response: &NtpPacket) -> bool {
+    let (orig, recv, xmit) = match (request, response) {
+        (_, NtpPacket::Control(_)) => return false,
+        #[cfg(feature = "ntpv5")]
+        (_, NtpPacket::V5(resp)) => return resp.is_interleaved(),
+        (NtpPacket::V3(req), NtpPacket::V3(resp)) => (resp.ts_orig, req.ts_recv, req.ts_xmit),
+        (NtpPacket::V4(req), NtpPacket::V4(resp)) => (resp.ts_orig, req.ts_recv, req.ts_xmit),
+        _ => return false,
+    };
+    !orig.is_unset() && orig == recv && orig != xmit
+}
+
+/// Parse an NTP version 3 packet (RFC 1305)
+///
+/// The data following the header must be empty, or an authenticator of 12 (DES) or 20 (MD5)
+/// bytes: other trailers are rejected.
+#[inline]
+pub fn parse_ntpv3(i: &[u8]) -> IResult<&[u8], NtpV3Packet<'_>> {
+    parse_ntpv3_e(i)
+}
+
+/// Parse an NTP version 3 packet (RFC 1305), with a generic error type
+///
+/// This is the same as [`parse_ntpv3`], but the error type is `E`. A malformed authenticator
+/// is reported as [`NtpError::TruncatedMac`], see [`parse_ntp_e`].
+pub fn parse_ntpv3_e<'a, E>(i: &'a [u8]) -> NtpResult<'a, NtpV3Packet<'a>, E>
+where
+    E: ParseError<&'a [u8]> + ContextError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    let (i, header) = context("header", NtpHeader::parse)(i)?;
+    let (i, authenticator) = context("authenticator", parse_v3_authenticator)(i)?;
+    let NtpHeader {
+        li,
+        version,
+        mode,
+        stratum,
+        poll,
+        precision,
+        root_delay,
+        root_dispersion,
+        ref_id,
+        ts_ref,
+        ts_orig,
+        ts_recv,
+        ts_xmit,
+    } = header;
+    let pkt = NtpV3Packet {
+        li,
+        version,
+        mode,
+        stratum,
+        poll,
+        precision,
+        root_delay,
+        root_dispersion,
+        ref_id,
+        ts_ref,
+        ts_orig,
+        ts_recv,
+        ts_xmit,
+        authenticator,
+    };
+    Ok((i, pkt))
+}
+
+impl<'a> Parse<&'a [u8]> for NtpV3Packet<'a> {
+    fn parse(i: &'a [u8]) -> IResult<&'a [u8], Self> {
+        parse_ntpv3_e(i)
+    }
+}
+
+/// Parse an NTP version 4 packet (RFC 1305)
+///
+/// Packets do not carry the length of the MAC: the extension fields are skipped until the
+/// remaining data is 4 bytes (a key identifier alone, for ex. a crypto-NAK), 20 bytes (MD5 or
+/// AES-CMAC digest) or 24 bytes (SHA-1 digest), and this data is the MAC. A 24-byte MAC whose
+/// key identifier is also the header of a 4-byte extension field (for ex. padding) is parsed
+/// as this field followed by a 20-byte MAC.
+#[inline]
+pub fn parse_ntpv4(i: &[u8]) -> IResult<&[u8], NtpV4Packet<'_>> {
+    parse_ntpv4_e(i)
+}
+
+/// Parse an NTP version 4 packet (RFC 1305), with a generic error type
+///
+/// This is the same as [`parse_ntpv4`], but the error type is `E`. If the MAC cannot be
+/// located, the error is [`NtpError::TruncatedMac`] or [`NtpError::InvalidExtensionLength`],
+/// see [`parse_ntp_e`].
+pub fn parse_ntpv4_e<'a, E>(i: &'a [u8]) -> NtpResult<'a, NtpV4Packet<'a>, E>
+where
+    E: ParseError<&'a [u8]> + ContextError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    let start = i;
+    let (i, header) = context("header", NtpHeader::parse)(i)?;
+    let (i, extensions) = context("extension fields and MAC", try_parse_extensions)(i)?;
+    let header_bytes = raw_bytes(start, i);
+    let auth_start = i;
+    let (i, auth) = context("extension fields and MAC", parse_ntpv4_mac)(i)?;
+    let auth_bytes = raw_bytes(auth_start, i);
+    let NtpHeader {
+        li,
+        version,
+        mode,
+        stratum,
+        poll,
+        precision,
+        root_delay,
+        root_dispersion,
+        ref_id,
+        ts_ref,
+        ts_orig,
+        ts_recv,
+        ts_xmit,
+    } = header;
+    let pkt = NtpV4Packet {
+        li,
+        version,
+        mode,
+        stratum,
+        poll,
+        precision,
+        root_delay,
+        root_dispersion,
+        ref_id,
+        ts_ref,
+        ts_orig,
+        ts_recv,
+        ts_xmit,
+        extensions,
+        header_bytes,
+        auth,
+        auth_bytes,
+    };
+    Ok((i, pkt))
+}
+
+impl<'a> Parse<&'a [u8]> for NtpV4Packet<'a> {
+    fn parse(i: &'a [u8]) -> IResult<&'a [u8], Self> {
+        parse_ntpv4_e(i)
+    }
+}
+
+/// Parse an NTP version 4 packet followed by one or more MACs
+///
+/// This is **not standard**: RFC 5905 allows only one MAC, but some deployments append several
+/// MACs (for ex. during key rollover). All bytes following the header must be MAC-shaped
+/// fields of the same length: 20 bytes (key identifier and 16-byte MD5 or AES-CMAC digest) or
+/// 24 bytes (key identifier and 20-byte SHA-1 digest). If the length of the data is a multiple
+/// of both, 20-byte MACs are assumed. Extensions are not supported in this mode.
+///
+/// The returned vector contains all MACs, and the `auth` field of the packet is set to the
+/// first one.
+pub fn parse_ntpv4_multi_mac(i: &[u8]) -> IResult<&[u8], (NtpV4Packet<'_>, Vec<NtpMac<'_>>)> {
+    let (rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    let mac_len = match rem.len() {
+        n if n % 20 == 0 => 20,
+        n if n % 24 == 0 => 24,
+        _ => return Err(Err::Error(make_error(rem, ErrorKind::LengthValue))),
+    };
+    let mut macs = Vec::with_capacity(rem.len() / mac_len);
+    for field in rem.chunks(mac_len) {
+        let (mac, key_id) = be_u32(field)?;
+        macs.push(NtpMac { key_id, mac });
+    }
+    pkt.auth = macs.first().cloned();
+    if pkt.auth.is_some() {
+        pkt.auth_bytes = raw_bytes(rem, &rem[mac_len..]);
+    }
+    Ok((&rem[rem.len()..], (pkt, macs)))
+}
+
+/// Options for [`parse_ntpv4_with_options`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NtpParseOptions {
+    /// Length of the MAC (key identifier and digest), in bytes, at least 4
+    ///
+    /// The default is 20 (MD5 digest). Use 24 for SHA-1 digests.
+    pub mac_len: usize,
+}
+
+impl Default for NtpParseOptions {
+    fn default() -> Self {
+        NtpParseOptions { mac_len: 20 }
+    }
+}
+
+/// Parse an NTP version 4 packet, using `options`
+///
+/// Packets do not carry the length of the MAC, so the data following the header is split
+/// using the assumed MAC length `options.mac_len`: if the data is exactly this length (or 4
+/// bytes, a key identifier alone), it is the MAC and there are no extension fields, otherwise
+/// the last `mac_len` bytes are the MAC and the preceding bytes are parsed as extension
+/// fields. With the default options, this is the same as [`parse_ntpv4`] for packets with a
+/// 20-byte MAC or without MAC.
+///
+/// If the MAC length is wrong, the packet is misparsed or rejected: for ex. with the default
+/// length, the first 4 bytes of a 24-byte SHA-1 MAC are parsed as an extension field.
+pub fn parse_ntpv4_with_options(
+    i: &[u8],
+    options: NtpParseOptions,
+) -> IResult<&[u8], NtpV4Packet<'_>> {
+    if options.mac_len < 4 {
+        return Err(Err::Error(make_error(i, ErrorKind::LengthValue)));
+    }
+    let (rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    let (rem, extensions) = parse_extensions_mac_len(rem, options.mac_len)?;
+    pkt.extensions = extensions;
+    pkt.header_bytes = raw_bytes(i, rem);
+    if let [a, b, c, d, digest @ ..] = rem {
+        pkt.auth = Some(NtpMac {
+            key_id: u32::from_be_bytes([*a, *b, *c, *d]),
+            mac: digest,
+        });
+        pkt.auth_bytes = raw_bytes(rem, &rem[rem.len()..]);
+    }
+    Ok((&rem[rem.len()..], pkt))
+}
+
+/// Layout of a non-standard MAC
+///
+/// The standard MAC (RFC 5905) has a 4-byte key identifier, followed by the digest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MacLayout {
+    /// Length of the key identifier, in bytes (at most 8)
+    pub key_id_len: usize,
+    /// Length of the digest, in bytes
+    pub digest_len: usize,
+}
+
+impl MacLayout {
+    /// Layout of the standard MAC, with a 4-byte key identifier and a 16-byte digest
+    pub const STANDARD: MacLayout = MacLayout {
+        key_id_len: 4,
+        digest_len: 16,
+    };
+}
+
+/// A MAC parsed using a custom [`MacLayout`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct NtpCustomMac<'a> {
+    pub key_id: u64,
+    pub digest: &'a [u8],
+}
+
+/// Parse an NTP version 4 packet, with a MAC using a custom layout
+///
+/// This is **not standard**: it is intended to decode experimental or proprietary
+/// deployments using a MAC with a different key identifier or digest length. As with
+/// [`parse_ntpv4`], the MAC is expected at the end of the packet if the data following the
+/// header is longer than the MAC, and preceding data is parsed as extension fields.
+///
+/// The MAC is returned separately, and the `auth` field of the packet is `None`. A key
+/// identifier longer than 8 bytes, or a MAC length overflowing `usize`, is an error.
+pub fn parse_ntpv4_custom_mac(
+    i: &[u8],
+    layout: MacLayout,
+) -> IResult<&[u8], (NtpV4Packet<'_>, Option<NtpCustomMac<'_>>)> {
+    if layout.key_id_len > 8 {
+        return Err(Err::Error(make_error(i, ErrorKind::TooLarge)));
+    }
+    let (rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    let mac_len = match layout.key_id_len.checked_add(layout.digest_len) {
+        Some(len) => len,
+        None => return Err(Err::Error(make_error(i, ErrorKind::TooLarge))),
+    };
+    if rem.is_empty() {
+        return Ok((rem, (pkt, None)));
+    }
+    if rem.len() < mac_len {
+        return Err(Err::Error(make_error(rem, ErrorKind::Eof)));
+    }
+    let (region, mac) = rem.split_at(rem.len() - mac_len);
+    if !region.is_empty() {
+        let (_, extensions) = parse_extension_list(region)?;
+        pkt.extensions = extensions;
+        pkt.header_bytes = raw_bytes(i, mac);
+    }
+    let (key_id, digest) = mac.split_at(layout.key_id_len);
+    let key_id = key_id
+        .iter()
+        .fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+    let mac = NtpCustomMac { key_id, digest };
+    Ok((&rem[rem.len()..], (pkt, Some(mac))))
+}
+
+/// Parse an NTP version 4 packet, accepting extension fields without a MAC
+///
+/// RFC 5905 requires a MAC when extension fields are present, but some implementations omit
+/// it. The packet is first parsed as by [`parse_ntpv4`]: if extension fields are found, the
+/// data following them is a MAC, and this packet is returned. Otherwise, if all the data
+/// following the header can be parsed as extension fields, the packet is returned with these
+/// extensions and no MAC (`auth` is `None`).
+///
+/// A MAC alone whose key identifier is also a valid field header (for ex. key identifier 20,
+/// which reads as a field of type 0 and length 20) is ambiguous, and is parsed as an extension
+/// field. A MAC following extension fields is never parsed as a field.
+pub fn parse_ntpv4_lenient_mac(i: &[u8]) -> IResult<&[u8], NtpV4Packet<'_>> {
+    let res = parse_ntpv4(i);
+    if let Ok((_, pkt)) = &res {
+        if !pkt.extensions.is_empty() {
+            return res;
+        }
+    }
+    let (rem, header) = take(48usize)(i)?;
+    if !rem.is_empty() {
+        if let Ok((r, extensions)) = parse_extension_list::<Error<_>>(rem) {
+            if r.is_empty() {
+                let (_, mut pkt) = NtpV4Packet::parse(header)?;
+                pkt.extensions = extensions;
+                pkt.header_bytes = raw_bytes(i, r);
+                return Ok((r, pkt));
+            }
+        }
+    }
+    res
+}
+
+/// Parse an NTP version 4 packet, keeping the extensions parsed before an error
+///
+/// This best-effort mode is useful to analyze partially corrupt packets: the header must be
+/// valid, but if an extension field (or the MAC) is malformed, the fields parsed before it are
+/// returned with the packet, and the first error is returned with its position in `i`. The MAC
+/// is still parsed after a malformed extension field.
+pub fn parse_ntpv4_best_effort(i: &[u8]) -> IResult<&[u8], (NtpV4Packet<'_>, Option<NtpErrorAt>)> {
+    let (rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    // if the extensions cannot be skipped to locate the MAC, assume a 20-byte MAC
+    let region = take_extensions_region::<NtpLocatedError<_>>(rem)
+        .or_else(|_| take_extensions_region_mac_len(rem, 20));
+    let (rem, mut region) = match region {
+        Ok(r) => r,
+        Err(e) => return Ok((rem, (pkt, Some(NtpErrorAt::from_located(i, e))))),
+    };
+    pkt.header_bytes = raw_bytes(i, rem);
+    let mut error = None;
+    while !region.is_empty() {
+        if pkt.extensions.len() == MAX_EXTENSIONS {
+            let e = Err::Error(make_error(region, ErrorKind::TooLarge));
+            error = Some(NtpErrorAt::from_nom(i, e));
+            break;
+        }
+        match complete(parse_ntp_extension)(region) {
+            Ok((r, ext)) => {
+                pkt.extensions.push(ext);
+                region = r;
+            }
+            Err(e) => {
+                error = Some(NtpErrorAt::from_nom(i, e));
+                break;
+            }
+        }
+    }
+    if rem.is_empty() {
+        return Ok((rem, (pkt, error)));
+    }
+    match parse_ntpv4_mac(rem) {
+        Ok((r, mac)) => {
+            pkt.auth = mac;
+            pkt.auth_bytes = raw_bytes(rem, r);
+            Ok((r, (pkt, error)))
+        }
+        Err(e) => Ok((
+            rem,
+            (pkt, error.or_else(|| Some(NtpErrorAt::from_located(i, e)))),
+        )),
+    }
+}
+
+/// Maximum length of a MAC accepted by [`parse_ntpv4_tolerant`] (key identifier and a 64-byte
+/// digest)
+const TOLERANT_MAX_MAC_LEN: usize = 4 + 64;
+
+/// Parse an NTP version 4 packet, recording issues as warnings instead of failing
+///
+/// This is intended for the analysis of malformed or adversarial packets. The header is
+/// required, and `None` is returned if it is truncated. Extension fields are then parsed with
+/// [`parse_ntp_extension_tolerant`], as long as they leave room for a MAC. The remaining data
+/// is the MAC if it is 20 bytes long, or if it is 4-byte aligned and at most 68 bytes long
+/// (with a warning). Other data is ignored, with a warning.
+pub fn parse_ntpv4_tolerant(i: &[u8]) -> (Option<NtpV4Packet<'_>>, Vec<NtpWarning>) {
+    let truncated = NtpWarning::TruncatedHeader { len: i.len() };
+    if i.len() < 48 {
+        return (None, vec![truncated]);
+    }
+    let (header, mut rem) = i.split_at(48);
+    let mut pkt = match NtpV4Packet::parse(header) {
+        Ok((_, pkt)) => pkt,
+        Err(_) => return (None, vec![truncated]),
+    };
+    let mut warnings = Vec::new();
+    while rem.len() > 20 && pkt.extensions.len() < MAX_EXTENSIONS {
+        match complete(parse_ntp_extension_tolerant)(rem) {
+            Ok((r, (ext, padding))) if r.is_empty() || r.len() >= 20 => {
+                if padding == ExtensionPadding::Missing && !r.is_empty() {
+                    let offset = i.len() - rem.len();
+                    warnings.push(NtpWarning::MisalignedExtension { offset });
+                }
+                pkt.extensions.push(ext);
+                rem = r;
+            }
+            _ => break,
+        }
+    }
+    pkt.header_bytes = raw_bytes(i, rem);
+    let offset = i.len() - rem.len();
+    match rem.len() {
+        0 => (),
+        20 => {
+            pkt.auth = NtpMac::parse(rem).ok().map(|(_, mac)| mac);
+            pkt.auth_bytes = raw_bytes(rem, &rem[20..]);
+        }
+        len if len > 4 && len <= TOLERANT_MAX_MAC_LEN && len % 4 == 0 => {
+            let (key_id, mac) = rem.split_at(4);
+            let key_id = u32::from_be_bytes([key_id[0], key_id[1], key_id[2], key_id[3]]);
+            pkt.auth = Some(NtpMac { key_id, mac });
+            pkt.auth_bytes = raw_bytes(rem, &rem[len..]);
+            warnings.push(NtpWarning::UnexpectedMacLength { offset, len });
+        }
+        len => warnings.push(NtpWarning::TrailingBytes { offset, len }),
+    }
+    (Some(pkt), warnings)
+}
+
+/// Parse an NTP version 4 packet, followed by a non-standard trailer
+///
+/// This is **not standard**: some deployments append proprietary data (for ex. a vendor
+/// signature) after the MAC. Extension fields are parsed as long as they are valid, have a
+/// length multiple of 4, and leave room for the MAC. The next 20 bytes are parsed as the MAC,
+/// and all following bytes are returned as the trailer, which must be 4-byte aligned.
+///
+/// The position of the MAC is a heuristic: a MAC whose key identifier is also a valid extension
+/// field header (type 0 and a length multiple of 4) can be mistaken for an extension field.
+pub fn parse_ntpv4_with_trailer(i: &[u8]) -> IResult<&[u8], (NtpV4Packet<'_>, &[u8])> {
+    let (mut rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    while rem.len() > 20 && pkt.extensions.len() < MAX_EXTENSIONS {
+        match complete(parse_ntp_extension)(rem) {
+            Ok((r, ext)) if ext.length % 4 == 0 && r.len() >= 20 => {
+                pkt.extensions.push(ext);
+                rem = r;
+            }
+            _ => break,
+        }
+    }
+    pkt.header_bytes = raw_bytes(i, rem);
+    if rem.is_empty() {
+        return Ok((rem, (pkt, rem)));
+    }
+    let (trailer, mac) = complete(NtpMac::parse)(rem)?;
+    if trailer.len() % 4 != 0 {
+        return Err(Err::Error(make_error(trailer, ErrorKind::LengthValue)));
+    }
+    pkt.auth = Some(mac);
+    pkt.auth_bytes = raw_bytes(rem, trailer);
+    Ok((&trailer[trailer.len()..], (pkt, trailer)))
+}
+
+/// Parse an NTP version 4 packet, returning the extension fields region unparsed
+///
+/// The region is located as in [`parse_ntpv4`] (see [`parse_extensions_region`]), but the
+/// extension fields are neither decoded nor validated: the `extensions` field of the returned
+/// packet is empty, and the whole region is returned, for ex. to be relayed verbatim by a
+/// proxy. The MAC is parsed as usual.
+pub fn parse_ntpv4_extensions_raw(i: &[u8]) -> IResult<&[u8], (NtpV4Packet<'_>, &[u8])> {
+    let (rem, header) = take(48usize)(i)?;
+    let (_, mut pkt) = NtpV4Packet::parse(header)?;
+    // if the extensions cannot be skipped to locate the MAC, assume a 20-byte MAC
+    let region = take_extensions_region::<Error<_>>(rem)
+        .or_else(|_| take_extensions_region_mac_len(rem, 20));
+    let (rem, region) = region?;
+    pkt.header_bytes = raw_bytes(i, rem);
+    let (end, auth) = parse_ntpv4_mac(rem)?;
+    pkt.auth = auth;
+    pkt.auth_bytes = raw_bytes(rem, end);
+    Ok((end, (pkt, region)))
+}
+
+/// Parse an NTP packet, version 1 to 4, or a control message
+///
+/// Control messages (mode 6) have a different layout, and are returned as
+/// [`NtpPacket::Control`] whatever their version (`ntpq` sends version 2 messages).
+///
+/// Versions 1 (RFC 1059) and 2 (RFC 1119) have the same header as version 3, and are returned
+/// as [`NtpPacket::V3`], with the `version` field set to the actual version.
+///
+/// Some implementations send version 4 packets followed by a version 3 authenticator (12
+/// bytes), which is not a valid extension field or MAC. These packets are returned as
+/// [`NtpPacket::V3`], with the `version` field set to 4. A 12-byte trailer made of valid
+/// extension fields and MAC (for ex. an 8-byte field and a crypto-NAK) is parsed as version 4.
+///
+/// With the `ntpv5` feature, version 5 packets are returned as `NtpPacket::V5`.
+#[inline]
+pub fn parse_ntp(i: &[u8]) -> IResult<&[u8], NtpPacket<'_>> {
+    parse_ntp_e(i)
+}
+
+/// Return true if `i` looks like an NTP packet (version 3 or 4), without parsing it
+///
+/// This is a heuristic classifier, for protocol detection when the UDP port is not reliable:
+/// the data must be at least 48 bytes long, the version must be 3 or 4, the mode must be a
+/// time-transfer mode (1 to 5, control and private messages are not recognized), and the
+/// stratum must be at most 16. Other data can match, so the packet should still be parsed.
+pub fn looks_like_ntp(i: &[u8]) -> bool {
+    if i.len() < 48 {
+        return false;
+    }
+    let version = (i[0] >> 3) & 0b111;
+    let mode = i[0] & 0b111;
+    let stratum = i[1];
+    (3..=4).contains(&version) && (1..=5).contains(&mode) && stratum <= 16
+}
+
+/// Parse an NTP packet (version 1 to 4), with a generic error type
+///
+/// This is the same as [`parse_ntp`], but the error type is `E`. The error is threaded
+/// through the parsers of the packet, with context labels describing the part of the packet
+/// (the version and the header, or the extension fields and MAC) being parsed. This is
+/// intended to debug parsing failures, for ex. with `nom::error::VerboseError`:
+///
+/// ```rust
+/// use nom::error::VerboseError;
+/// use ntp_parser::parse_ntp_e;
+///
+/// let mut data = vec![0x23];
+/// data.resize(48 + 10, 0);
+/// let err = parse_ntp_e::<VerboseError<&[u8]>>(&data).unwrap_err();
+/// println!("{:?}", err);
+/// ```
+///
+/// The reason for rejecting the packet is passed to `E` as an external error, at the point of
+/// failure: with [`NtpError`] (see [`NtpResult`]), the error is for ex.
+/// [`NtpError::UnsupportedVersion`] or [`NtpError::TruncatedMac`] instead of a nom error kind.
+///
+/// Control messages and version 5 packets are parsed with the nom error type, and only the
+/// final failure (its position and error kind) is converted to `E`.
+pub fn parse_ntp_e<'a, E>(i: &'a [u8]) -> NtpResult<'a, NtpPacket<'a>, E>
+where
+    E: ParseError<&'a [u8]> + ContextError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    let (_, b0) = be_u8(i)?;
+    if NtpMode(b0 & 0b111) == NtpMode::NtpControlMessage {
+        return map(parse_ntp_control, NtpPacket::Control)(i).map_err(from_nom_error);
+    }
+    let version = NtpVersion((b0 >> 3) & 0b111);
+    let label = match version {
+        NtpVersion::V3 => "NTPv3 packet",
+        NtpVersion::V4 => "NTPv4 packet",
+        _ => "NTP packet",
+    };
+    if version == NtpVersion::V4
+        && i.len() == 48 + 12
+        && take_extensions_region::<()>(&i[48..]).is_err()
+    {
+        return context(label, |i| parse_ntp_as_e(i, NtpVersion::V3))(i);
+    }
+    context(label, move |i| parse_ntp_as_e(i, version))(i)
+}
+
+// Convert an error returned by a parser using the nom error type to `E`
+fn from_nom_error<'a, E: ParseError<&'a [u8]>>(e: Err<Error<&'a [u8]>>) -> Err<E> {
+    e.map(|e| E::from_error_kind(e.input, e.code))
+}
+
+/// Parse an NTP packet, version 1 to 4, after skipping the first `n` bytes of `i`
+///
+/// This is intended for captures where the packet is wrapped in a fixed-size encapsulation,
+/// for ex. mirrored traffic in ERSPAN/GRE: the lower layers are not decoded, so `n` must be the
+/// total size of the headers preceding the NTP packet. See `examples/erspan.rs`.
+pub fn parse_ntp_skipping(i: &[u8], n: usize) -> IResult<&[u8], NtpPacket<'_>> {
+    let (i, _) = take(n)(i)?;
+    parse_ntp(i)
+}
+
+/// Byte ranges of the parts of an NTP packet, see [`byte_layout`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NtpLayout {
+    /// Fixed 48-byte header
+    ///
+    /// For a control message, this is the 12-byte header followed by the data and the padding.
+    pub header: Range<usize>,
+    /// Each extension field, including its type and length (version 4 only)
+    pub extensions: Vec<Range<usize>>,
+    /// MAC (version 4) or authenticator (version 3), including the key identifier
+    pub mac: Option<Range<usize>>,
+}
+
+/// Parse an NTP packet (version 1 to 4), and return the byte ranges of its parts
+///
+/// This gives a map of the packet, for ex. to highlight regions in a hex view, or to compare
+/// the segmentation with another dissector. Ranges are offsets in `i`, and follow each other
+/// without gaps; data after the packet is not covered.
+pub fn byte_layout(i: &[u8]) -> Result<NtpLayout, NtpError> {
+    let (_, pkt) = parse_ntp(i)?;
+    let (extensions, mac_len) = match &pkt {
+        NtpPacket::V3(pkt) => (Vec::new(), pkt.authenticator.map(<[u8]>::len)),
+        NtpPacket::V4(pkt) => {
+            let mut offset = 48;
+            let extensions = pkt
+                .extensions
+                .iter()
+                .map(|ext| {
+                    let start = offset;
+                    offset += 4 + ext.value.len() + extension_padding_len(ext.length);
+                    start..offset
+                })
+                .collect();
+            (extensions, pkt.auth.as_ref().map(|m| 4 + m.mac.len()))
+        }
+        NtpPacket::Control(msg) => (Vec::new(), msg.auth.as_ref().map(|m| 4 + m.mac.len())),
+        #[cfg(feature = "ntpv5")]
+        NtpPacket::V5(pkt) => {
+            let mut offset = 48;
+            let extensions = pkt
+                .extensions
+                .iter()
+                .map(|ext| {
+                    let start = offset;
+                    offset += 4 + ext.value.len() + extension_padding_len(ext.length);
+                    start..offset
+                })
+                .collect();
+            (extensions, None)
+        }
+    };
+    let header_len = match &pkt {
+        NtpPacket::Control(msg) => 12 + msg.data.len() + msg.padding.len(),
+        _ => 48,
+    };
+    let mac_start = extensions
+        .last()
+        .map_or(header_len, |r: &Range<usize>| r.end);
+    Ok(NtpLayout {
+        header: 0..header_len,
+        extensions,
+        mac: mac_len.map(|len| mac_start..mac_start + len),
+    })
+}
+
+/// Parse an NTP packet received with `std::net::UdpSocket::recv_from`
+///
+/// `buf` is the receive buffer, and `len` the length of the datagram returned by `recv_from`:
+/// only the first `len` bytes are parsed, so stale data in the rest of the buffer is ignored.
+/// The datagram must contain exactly one packet, trailing data returns
+/// `NtpError::Nom(ErrorKind::Eof)`.
+#[cfg(feature = "std")]
+pub fn parse_ntp_datagram(buf: &[u8], len: usize) -> Result<NtpPacket<'_>, NtpError> {
+    let data = buf
+        .get(..len)
+        .ok_or_else(|| NtpError::Incomplete(Needed::new(len - buf.len())))?;
+    match parse_ntp_complete_e::<NtpError>(data)? {
+        ([], pkt) => Ok(pkt),
+        _ => Err(NtpError::Nom(ErrorKind::Eof)),
+    }
+}
+
+/// Parse an NTP packet, version 1 to 4, and return the number of bytes consumed
+pub fn parse_ntp_consumed(i: &[u8]) -> IResult<&[u8], (NtpPacket<'_>, usize)> {
+    let (rem, pkt) = parse_ntp(i)?;
+    Ok((rem, (pkt, i.len() - rem.len())))
+}
+
+/// Parse an NTP packet, version 1 to 4, reporting the offset of the failure on error
+///
+/// This is the same as [`parse_ntp`], but the returned error contains the position in `i`
+/// where parsing failed, which helps analyzing malformed packets.
+pub fn parse_ntp_located(i: &[u8]) -> Result<(&[u8], NtpPacket<'_>), NtpErrorAt> {
+    parse_ntp_e::<NtpLocatedError<_>>(i).map_err(|e| NtpErrorAt::from_located(i, e))
+}
+
+/// Parse a batch of NTP packets, stored one after the other in the input buffer
+///
+/// `lengths` gives the length of each packet (for ex. the UDP payload length of each datagram
+/// in a capture). Each segment is parsed independently, so a malformed packet does not prevent
+/// the following ones from being parsed. Bytes remaining in a segment after the packet are
+/// ignored.
+///
+/// A length which makes the segment end overflow returns `NtpError::Nom(ErrorKind::TooLarge)`,
+/// and the following segments cannot be located: they return `NtpError::Incomplete`.
+pub fn parse_ntp_batch<'a>(i: &'a [u8], lengths: &[usize]) -> Vec<Result<NtpPacket<'a>, NtpError>> {
+    // `None` once a length has overflowed: the following segments cannot be located
+    let mut offset = Some(0usize);
+    lengths
+        .iter()
+        .map(|&len| {
+            let start = offset.ok_or(NtpError::Incomplete(Needed::Unknown))?;
+            offset = start.checked_add(len);
+            let end = offset.ok_or(NtpError::Nom(ErrorKind::TooLarge))?;
+            match i.get(start..end) {
+                Some(segment) => parse_ntp_e::<NtpError>(segment)
+                    .map(|(_, pkt)| pkt)
+                    .map_err(NtpError::from),
+                None => {
+                    let missing = end - start.max(i.len());
+                    Err(NtpError::Incomplete(Needed::new(missing)))
+                }
+            }
+        })
+        .collect()
+}
+
+/// Parse each record (for ex. the UDP payloads read from a capture file) as an NTP packet
+///
+/// Records are parsed lazily and independently, as in [`parse_ntp_batch`]. Bytes remaining in
+/// a record after the packet are ignored.
+pub fn parse_ntp_from_records<'a, I>(
+    records: I,
+) -> impl Iterator<Item = Result<NtpPacket<'a>, NtpError>>
+where
+    I: Iterator<Item = &'a [u8]>,
+{
+    records.map(|record| {
+        parse_ntp_e::<NtpError>(record)
+            .map(|(_, pkt)| pkt)
+            .map_err(NtpError::from)
+    })
+}
+
+/// Return the NTP versions supported by the parsers of this crate
+///
+/// Version 5 is included with the `ntpv5` feature.
+pub fn supported_versions() -> &'static [u8] {
+    if cfg!(feature = "ntpv5") {
+        &[1, 2, 3, 4, 5]
+    } else {
+        &[1, 2, 3, 4]
+    }
+}
+
+/// Parse an NTP packet using the layout of the specified version
+///
+/// The version field of the packet is ignored, which is useful when the version is known
+/// out-of-band, or to decode packets with a corrupted or deliberately wrong version. Versions
+/// 1 and 2 use the layout of version 3.
+#[inline]
+pub fn parse_ntp_as(i: &[u8], version: NtpVersion) -> IResult<&[u8], NtpPacket<'_>> {
+    parse_ntp_as_e(i, version)
+}
+
+/// Parse an NTP packet using the layout of the specified version, with a generic error type
+///
+/// This is the same as [`parse_ntp_as`], but the error type is `E`. An unknown version is
+/// reported as [`NtpError::UnsupportedVersion`].
+pub fn parse_ntp_as_e<'a, E>(i: &'a [u8], version: NtpVersion) -> NtpResult<'a, NtpPacket<'a>, E>
+where
+    E: ParseError<&'a [u8]> + ContextError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    match version {
+        NtpVersion::V1 | NtpVersion::V2 | NtpVersion::V3 => map(parse_ntpv3_e, NtpPacket::V3)(i),
+        NtpVersion::V4 => map(parse_ntpv4_e, NtpPacket::V4)(i),
+        #[cfg(feature = "ntpv5")]
+        NtpVersion::V5 => map(NtpV5Packet::parse, NtpPacket::V5)(i).map_err(from_nom_error),
+        NtpVersion(v) => {
+            let e = E::from_external_error(i, ErrorKind::Tag, NtpError::UnsupportedVersion(v));
+            Err(Err::Error(e))
+        }
+    }
+}
+
+/// Header fields of a possibly truncated NTP packet
+///
+/// Fields which are not entirely present in the input are `None`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PartialNtpHeader {
+    pub li: Option<u8>,
+    pub version: Option<u8>,
+    pub mode: Option<NtpMode>,
+    pub stratum: Option<u8>,
+    pub poll: Option<i8>,
+    pub precision: Option<Precision>,
+    pub root_delay: Option<NtpShortFormat>,
+    pub root_dispersion: Option<NtpShortFormat>,
+    pub ref_id: Option<u32>,
+    pub ts_ref: Option<NtpTimestamp>,
+    pub ts_orig: Option<NtpTimestamp>,
+    pub ts_recv: Option<NtpTimestamp>,
+    pub ts_xmit: Option<NtpTimestamp>,
+}
+
+/// Extract as many header fields as possible from a possibly truncated packet
+///
+/// This never fails: truncated captures (for ex. because of a small snapshot length) return
+/// the fields present in the input, and the missing fields are `None`. Data after the header
+/// is ignored.
+pub fn parse_ntp_partial(i: &[u8]) -> PartialNtpHeader {
+    let be_u32_at = |off: usize| {
+        i.get(off..off + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+    let be_u64_at = |off: usize| {
+        let hi = be_u32_at(off)?;
+        let lo = be_u32_at(off + 4)?;
+        Some((u64::from(hi) << 32) | u64::from(lo))
+    };
+    let b0 = i.first().copied();
+    PartialNtpHeader {
+        li: b0.map(|b| b >> 6),
+        version: b0.map(|b| (b >> 3) & 0b111),
+        mode: b0.map(|b| NtpMode(b & 0b111)),
+        stratum: i.get(1).copied(),
+        poll: i.get(2).map(|&b| b as i8),
+        precision: i.get(3).map(|&b| Precision(b as i8)),
+        root_delay: be_u32_at(4).map(NtpShortFormat),
+        root_dispersion: be_u32_at(8).map(NtpShortFormat),
+        ref_id: be_u32_at(12),
+        ts_ref: be_u64_at(16).map(NtpTimestamp),
+        ts_orig: be_u64_at(24).map(NtpTimestamp),
+        ts_recv: be_u64_at(32).map(NtpTimestamp),
+        ts_xmit: be_u64_at(40).map(NtpTimestamp),
+    }
+}
+
+/// Header of an NTP packet (version 3 or 4), with undecoded timestamps
+///
+/// This is returned by [`parse_ntp_header_lazy`], for callers which do not need the
+/// timestamps: the 32 bytes are kept as is, and can be decoded on demand with
+/// [`timestamps`](Self::timestamps).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, NomBE)]
+pub struct NtpLazyHeader<'a> {
     #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
     #[nom(Value(b0 >> 6))]
     pub li: u8,
@@ -64,99 +2238,136 @@ pub struct NtpV4Packet<'a> {
     pub mode: NtpMode,
     pub stratum: u8,
     pub poll: i8,
-    pub precision: i8,
-    pub root_delay: u32,
-    pub root_dispersion: u32,
+    pub precision: Precision,
+    pub root_delay: NtpShortFormat,
+    pub root_dispersion: NtpShortFormat,
     pub ref_id: u32,
-    pub ts_ref: u64,
-    pub ts_orig: u64,
-    pub ts_recv: u64,
-    pub ts_xmit: u64,
-
-    #[nom(Parse = "try_parse_extensions")]
-    pub extensions: Vec<NtpExtension<'a>>,
-    #[nom(Cond(!i.is_empty()))]
-    pub auth: Option<NtpMac<'a>>,
+    /// Reference, origin, receive and transmit timestamps, in network byte order
+    #[nom(Parse = "take_raw_timestamps")]
+    pub raw_timestamps: &'a [u8; 32],
 }
 
-impl<'a> NtpV4Packet<'a> {
-    pub fn get_precision(&self) -> f32 {
-        2.0_f32.powf(self.precision as f32)
+fn take_raw_timestamps(i: &[u8]) -> IResult<&[u8], &[u8; 32]> {
+    map_res(take(32usize), <&[u8; 32]>::try_from)(i)
+}
+
+impl<'a> NtpLazyHeader<'a> {
+    /// Decode the four timestamps
+    pub fn timestamps(&self) -> NtpTimestamps {
+        let b = self.raw_timestamps;
+        let ts = |off: usize| {
+            let mut v = 0u64;
+            for &byte in &b[off..off + 8] {
+                v = (v << 8) | u64::from(byte);
+            }
+            NtpTimestamp(v)
+        };
+        NtpTimestamps {
+            reference: ts(0),
+            origin: ts(8),
+            receive: ts(16),
+            transmit: ts(24),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, NomBE)]
-pub struct NtpExtension<'a> {
-    pub field_type: u16,
-    pub length: u16,
-    #[nom(Parse = "take(length)")]
-    pub value: &'a [u8],
-    /*padding*/
+/// Parse the 48-byte header of an NTP packet, without decoding the timestamps
+///
+/// This is faster than a complete parsing for scanners which only look at the first fields
+/// (mode, stratum, reference identifier, etc.). The version is not checked, and the data
+/// following the header (extension fields, MAC or authenticator) is returned unparsed.
+pub fn parse_ntp_header_lazy(i: &[u8]) -> IResult<&[u8], NtpLazyHeader<'_>> {
+    NtpLazyHeader::parse(i)
+}
+
+/// Read the version from the first byte of a packet, without parsing it
+///
+/// Returns `None` if the input is empty. Like the other `peek_` functions, this reads a fixed
+/// offset of a version 3 or 4 header, and does not validate the packet.
+#[inline]
+pub fn peek_version(i: &[u8]) -> Option<u8> {
+    i.first().map(|&b| (b >> 3) & 0b111)
+}
+
+/// Read the mode from the first byte of a packet, without parsing it
+#[inline]
+pub fn peek_mode(i: &[u8]) -> Option<NtpMode> {
+    i.first().map(|&b| NtpMode(b & 0b111))
+}
+
+/// Read the version and the mode from the first byte of a packet, without parsing it
+///
+/// This is the cheapest classification of a packet, for ex. to count packets by version and
+/// mode. Returns `None` if the input is empty. The version is not checked.
+#[inline]
+pub fn classify(i: &[u8]) -> Option<(NtpVersion, NtpMode)> {
+    i.first()
+        .map(|&b| (NtpVersion((b >> 3) & 0b111), NtpMode(b & 0b111)))
 }
 
-#[derive(Debug, PartialEq, NomBE)]
-pub struct NtpMac<'a> {
-    pub key_id: u32,
-    #[nom(Parse = "take(16usize)")]
-    pub mac: &'a [u8],
+/// Read the stratum of a packet, without parsing it
+#[inline]
+pub fn peek_stratum(i: &[u8]) -> Option<u8> {
+    i.get(1).copied()
 }
 
+/// Read the reference identifier of a packet, without parsing it
 #[inline]
-pub fn parse_ntp_extension(i: &[u8]) -> IResult<&[u8], NtpExtension> {
-    NtpExtension::parse(i)
+pub fn peek_ref_id(i: &[u8]) -> Option<u32> {
+    i.get(12..16)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
 }
 
-// Attempt to parse extensions.
-//
-// See section 7.5 of [RFC5905] and [RFC7822]:
-// In NTPv4, one or more extension fields can be inserted after the
-//    header and before the MAC, which is always present when an extension
-//    field is present.
-//
-// So:
-//  if == 20, only MAC
-//  if >  20, ext + MAC
-//  if ==  0, nothing
-//  else      error
-fn try_parse_extensions(i: &[u8]) -> IResult<&[u8], Vec<NtpExtension>> {
-    if i.is_empty() || i.len() == 20 {
-        // if empty, or if remaining length is exactly the MAC length (20), assume we do not have
-        // extensions
-        return Ok((i, Vec::new()));
-    }
-    if i.len() < 20 {
-        return Err(Err::Error(make_error(i, ErrorKind::Eof)));
-    }
-    map_parser(take(i.len() - 20), many1(complete(parse_ntp_extension)))(i)
+/// Parse a complete NTP version 3 packet
+///
+/// See [`parse_ntp_complete`].
+#[inline]
+pub fn parse_ntpv3_complete(i: &[u8]) -> IResult<&[u8], NtpV3Packet<'_>> {
+    complete(parse_ntpv3)(i)
 }
 
-/// Parse an NTP version 3 packet (RFC 1305)
+/// Parse a complete NTP version 4 packet
+///
+/// See [`parse_ntp_complete`].
 #[inline]
-pub fn parse_ntpv3(i: &[u8]) -> IResult<&[u8], NtpV3Packet> {
-    NtpV3Packet::parse(i)
+pub fn parse_ntpv4_complete(i: &[u8]) -> IResult<&[u8], NtpV4Packet<'_>> {
+    complete(parse_ntpv4)(i)
 }
 
-/// Parse an NTP version 4 packet (RFC 1305)
+/// Parse a complete NTP packet, version 3 or 4
+///
+/// The default parsers are streaming parsers: on short input, they return `Err::Incomplete`,
+/// so that more data can be read, which is what is needed for a stream transport (for ex.
+/// NTP over TCP, or a reassembled flow). When the input is always a full packet (for ex. the
+/// payload of a UDP datagram), more data will never arrive; these functions return an error
+/// (`ErrorKind::Complete`) instead, so short input does not need to be handled separately.
 #[inline]
-pub fn parse_ntpv4(i: &[u8]) -> IResult<&[u8], NtpV4Packet> {
-    NtpV4Packet::parse(i)
+pub fn parse_ntp_complete(i: &[u8]) -> IResult<&[u8], NtpPacket<'_>> {
+    complete(parse_ntp)(i)
 }
 
-/// Parse an NTP packet, version 3 or 4
+/// Parse an NTP packet, treating incomplete input as an error, with a generic error type
+///
+/// This is the same as [`parse_ntp_complete`], but the error type is `E`, see [`parse_ntp_e`].
 #[inline]
-pub fn parse_ntp(i: &[u8]) -> IResult<&[u8], NtpPacket> {
-    let (_, b0) = be_u8(i)?;
-    match (b0 >> 3) & 0b111 {
-        3 => map(NtpV3Packet::parse, NtpPacket::V3)(i),
-        4 => map(NtpV4Packet::parse, NtpPacket::V4)(i),
-        _ => Err(Err::Error(make_error(i, ErrorKind::Tag))),
-    }
+pub fn parse_ntp_complete_e<'a, E>(i: &'a [u8]) -> NtpResult<'a, NtpPacket<'a>, E>
+where
+    E: ParseError<&'a [u8]> + ContextError<&'a [u8]> + FromExternalError<&'a [u8], NtpError>,
+{
+    complete(parse_ntp_e)(i)
 }
 
 #[cfg(test)]
 mod tests {
     use crate::ntp::*;
 
+    /// Return a copy of `data`, with the byte at `offset` replaced by `value`
+    fn patch(data: &[u8], offset: usize, value: u8) -> Vec<u8> {
+        let mut v = data.to_vec();
+        v[offset] = value;
+        v
+    }
+
     static NTP_REQ1: &[u8] = &[
         0xd9, 0x00, 0x0a, 0xfa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x90, 0x00, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -174,18 +2385,20 @@ mod tests {
             mode: NtpMode::SymmetricActive,
             stratum: 0,
             poll: 10,
-            precision: -6,
-            root_delay: 0,
-            root_dispersion: 0x010290,
+            precision: Precision(-6),
+            root_delay: NtpShortFormat(0),
+            root_dispersion: NtpShortFormat(0x010290),
             ref_id: 0,
-            ts_ref: 0,
-            ts_orig: 0,
-            ts_recv: 0,
-            ts_xmit: 14195914391047827090u64,
-            extensions: Vec::new(),
+            ts_ref: NtpTimestamp(0),
+            ts_orig: NtpTimestamp(0),
+            ts_recv: NtpTimestamp(0),
+            ts_xmit: NtpTimestamp(14195914391047827090u64),
+            extensions: NtpExtensions::new(),
+            header_bytes: raw_bytes(bytes, empty),
             auth: None,
+            auth_bytes: raw_bytes(empty, empty),
         };
-        let res = parse_ntpv4(&bytes);
+        let res = parse_ntpv4(bytes);
         assert_eq!(res, Ok((empty, expected)));
     }
 
@@ -207,21 +2420,23 @@ mod tests {
             mode: NtpMode::Client,
             stratum: 0,
             poll: 0,
-            precision: 0,
-            root_delay: 12,
-            root_dispersion: 0,
+            precision: Precision(0),
+            root_delay: NtpShortFormat(12),
+            root_dispersion: NtpShortFormat(0),
             ref_id: 0,
-            ts_ref: 0,
-            ts_orig: 0,
-            ts_recv: 0,
-            ts_xmit: 14710388140573593600,
-            extensions: Vec::new(),
+            ts_ref: NtpTimestamp(0),
+            ts_orig: NtpTimestamp(0),
+            ts_recv: NtpTimestamp(0),
+            ts_xmit: NtpTimestamp(14710388140573593600),
+            extensions: NtpExtensions::new(),
+            header_bytes: raw_bytes(bytes, &bytes[48..]),
             auth: Some(NtpMac {
                 key_id: 1,
                 mac: &bytes[52..],
             }),
+            auth_bytes: raw_bytes(&bytes[48..], empty),
         };
-        let res = parse_ntpv4(&bytes);
+        let res = parse_ntpv4(bytes);
         assert_eq!(res, Ok((empty, expected)));
     }
 
@@ -229,7 +2444,7 @@ mod tests {
         0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
         0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x25, 0xcc, 0x13, 0x2b,
-        0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x52, 0x80, 0x0c, 0x2b,
+        0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x52, 0x80, 0x0c, 0x2b,
         0x59, 0x00, 0x64, 0x66, 0x84, 0xf4, 0x4c, 0xa4, 0xee, 0xce, 0x12, 0xb8,
     ];
 
@@ -243,25 +2458,30 @@ mod tests {
             mode: NtpMode::Client,
             stratum: 0,
             poll: 0,
-            precision: 0,
-            root_delay: 12,
-            root_dispersion: 0,
+            precision: Precision(0),
+            root_delay: NtpShortFormat(12),
+            root_dispersion: NtpShortFormat(0),
             ref_id: 0,
-            ts_ref: 0,
-            ts_orig: 0,
-            ts_recv: 0,
-            ts_xmit: 14710388140573593600,
+            ts_ref: NtpTimestamp(0),
+            ts_orig: NtpTimestamp(0),
+            ts_recv: NtpTimestamp(0),
+            ts_xmit: NtpTimestamp(14710388140573593600),
             extensions: vec![NtpExtension {
                 field_type: 0,
-                length: 0,
+                length: 4,
                 value: empty,
-            }],
+            }]
+            .into_iter()
+            .collect(),
+            header_bytes: raw_bytes(bytes, &bytes[52..]),
             auth: Some(NtpMac {
                 key_id: 1,
                 mac: &bytes[56..],
             }),
+            auth_bytes: raw_bytes(&bytes[52..], empty),
         };
-        let res = parse_ntpv4(&bytes);
+        let res = parse_ntpv4(bytes);
+        assert_eq!(res.as_ref().map(|(_, pkt)| pkt.extensions().len()), Ok(1));
         assert_eq!(res, Ok((empty, expected)));
     }
 
@@ -283,17 +2503,1364 @@ mod tests {
             mode: NtpMode::Client,
             stratum: 4,
             poll: 6,
-            precision: -11,
-            root_delay: 4109,
-            root_dispersion: 0x0557,
+            precision: Precision(-11),
+            root_delay: NtpShortFormat(4109),
+            root_dispersion: NtpShortFormat(0x0557),
             ref_id: 0x82dc1818,
-            ts_ref: 0xba296636_7dd00000,
-            ts_orig: 0xba296636_7d584000,
-            ts_recv: 0xba296636_7dd00000,
-            ts_xmit: 0xba296676_7d505000,
+            ts_ref: NtpTimestamp(0xba296636_7dd00000),
+            ts_orig: NtpTimestamp(0xba296636_7d584000),
+            ts_recv: NtpTimestamp(0xba296636_7dd00000),
+            ts_xmit: NtpTimestamp(0xba296676_7d505000),
             authenticator: None,
         };
-        let res = NtpV3Packet::parse(&bytes);
+        let res = NtpV3Packet::parse(bytes);
         assert_eq!(res, Ok((empty, expected)));
     }
+
+    #[test]
+    fn test_ntp_field_endianness() {
+        // all multi-byte fields are big-endian: compare with the bytes at their offset, and
+        // check that the little-endian value would differ
+        let be32 = |o: usize| {
+            u32::from_be_bytes([
+                NTPV3_REQ[o],
+                NTPV3_REQ[o + 1],
+                NTPV3_REQ[o + 2],
+                NTPV3_REQ[o + 3],
+            ])
+        };
+        let be64 = |o: usize| u64::from(be32(o)) << 32 | u64::from(be32(o + 4));
+        let (_, v3) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        let (_, v4) = parse_ntpv4(NTPV3_REQ).expect("parsing failed");
+        for fields in &[
+            [v3.root_delay.0, v3.root_dispersion.0, v3.ref_id],
+            [v4.root_delay.0, v4.root_dispersion.0, v4.ref_id],
+        ] {
+            assert_eq!(*fields, [0x0000_100d, 0x0000_0557, 0x82dc_1818]);
+            assert_eq!(*fields, [be32(4), be32(8), be32(12)]);
+            assert!(fields.iter().all(|f| f.swap_bytes() != *f));
+        }
+        for ts in &[
+            [v3.ts_ref.0, v3.ts_orig.0, v3.ts_recv.0, v3.ts_xmit.0],
+            [v4.ts_ref.0, v4.ts_orig.0, v4.ts_recv.0, v4.ts_xmit.0],
+        ] {
+            assert_eq!(*ts, [be64(16), be64(24), be64(32), be64(40)]);
+            assert_eq!(ts[3], 0xba29_6676_7d50_5000);
+            assert!(ts.iter().all(|t| t.swap_bytes() != *t));
+        }
+    }
+
+    #[test]
+    fn test_ntp_parse_as() {
+        // NTP_REQ1 has version 3 in its header, but is a v4 packet
+        let (rem, pkt) = parse_ntp_as(NTP_REQ1, NtpVersion::V4).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(matches!(pkt, NtpPacket::V4(_)));
+        let (_, pkt) = parse_ntp(NTP_REQ1).expect("parsing failed");
+        assert!(matches!(pkt, NtpPacket::V3(_)));
+        assert!(parse_ntp_as(NTP_REQ1, NtpVersion(6)).is_err());
+    }
+
+    #[test]
+    fn test_ntp_validate_leap_indicator() {
+        // li = 3, version 3, mode 3 (client): normal for a request
+        let mut buf = NTP_REQ1.to_vec();
+        buf[0] = 0xdb;
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(!pkt.is_synchronized());
+        assert_eq!(pkt.validate(), Ok(()));
+        // li = 3, version 3, mode 4 (server): the server is not synchronized
+        drop(pkt);
+        buf[0] = 0xdc;
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(!pkt.is_synchronized());
+        assert_eq!(pkt.validate(), Err(NtpValidationError::Unsynchronized));
+        // li = 0, version 3, mode 4 (server)
+        drop(pkt);
+        buf[0] = 0x1c;
+        let (_, pkt) = parse_ntpv3(&buf).expect("parsing failed");
+        assert!(pkt.is_synchronized());
+        assert_eq!(pkt.validate(), Ok(()));
+        // mode 0 is reserved
+        buf[0] = 0x18;
+        let (_, pkt) = parse_ntpv3(&buf).expect("parsing failed");
+        assert_eq!(pkt.validate(), Err(NtpValidationError::InvalidMode));
+    }
+
+    #[test]
+    fn test_ntp_parse_batch() {
+        let mut buf = NTPV3_REQ.to_vec();
+        buf.extend_from_slice(&[0x23, 0x00, 0x01]);
+        buf.extend_from_slice(NTP_REQ2);
+        let lengths = [NTPV3_REQ.len(), 3, NTP_REQ2.len(), 48];
+        let res = parse_ntp_batch(&buf, &lengths);
+        assert_eq!(res.len(), 4);
+        assert!(matches!(res[0], Ok(NtpPacket::V3(_))));
+        assert_eq!(res[1], Err(NtpError::Incomplete(Needed::new(1))));
+        assert!(matches!(res[2], Ok(NtpPacket::V4(_))));
+        assert_eq!(res[3], Err(NtpError::Incomplete(Needed::new(48))));
+        // overflowing length
+        let lengths = [NTPV3_REQ.len(), usize::MAX, 3, 0];
+        let res = parse_ntp_batch(&buf, &lengths);
+        assert!(matches!(res[0], Ok(NtpPacket::V3(_))));
+        assert_eq!(res[1], Err(NtpError::Nom(ErrorKind::TooLarge)));
+        assert!(matches!(res[2], Err(NtpError::Incomplete(_))));
+        assert!(matches!(res[3], Err(NtpError::Incomplete(_))));
+    }
+
+    #[test]
+    fn test_ntp_parse_from_records() {
+        let v7 = patch(NTP_REQ1, 0, 0xf9);
+        let records: Vec<&[u8]> = vec![NTPV3_REQ, &[0x23, 0x00, 0x01], NTP_REQ2, &v7];
+        let res: Vec<_> = parse_ntp_from_records(records.into_iter()).collect();
+        assert_eq!(res.len(), 4);
+        assert!(matches!(res[0], Ok(NtpPacket::V3(_))));
+        assert_eq!(res[1], Err(NtpError::Incomplete(Needed::new(1))));
+        assert!(matches!(res[2], Ok(NtpPacket::V4(_))));
+        assert_eq!(res[3], Err(NtpError::UnsupportedVersion(7)));
+    }
+
+    #[test]
+    fn test_ntp_timestamps_plausible() {
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        let skew = Duration::from_secs(60);
+        let now = NtpTimestamp(pkt.ts_xmit.0 + (30 << 32));
+        assert!(pkt.timestamps_plausible(now, skew));
+        let now = NtpTimestamp(pkt.ts_xmit.0 - (30 << 32));
+        assert!(pkt.timestamps_plausible(now, skew));
+        let now = NtpTimestamp(pkt.ts_xmit.0 + (90 << 32));
+        assert!(!pkt.timestamps_plausible(now, skew));
+        // reference timestamp in the future
+        let mut pkt2 = pkt.clone();
+        pkt2.ts_ref = NtpTimestamp(pkt.ts_xmit.0 + (3600 << 32));
+        assert!(!pkt2.timestamps_plausible(pkt.ts_xmit, skew));
+        // unset transmit timestamp
+        let (_, pkt) = parse_ntpv4(NTP_REQ2).expect("parsing failed");
+        assert!(pkt.timestamps_plausible(pkt.ts_xmit, skew));
+        let (_, mut pkt) = parse_ntpv3(&NTP_REQ1[..48]).expect("parsing failed");
+        pkt.ts_xmit = NtpTimestamp(0);
+        assert!(!pkt.timestamps_plausible(NtpTimestamp(0), skew));
+    }
+
+    #[test]
+    fn test_ntp_parse_located() {
+        let mut buf = NTP_REQ1.to_vec();
+        buf[0] = 0xf9; // version 7
+        let e = parse_ntp_located(&buf).expect_err("parsing should fail");
+        assert_eq!(e.offset, 0);
+        assert_eq!(e.error, NtpError::UnsupportedVersion(7));
+        // v4 packet with a truncated extension before the MAC
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x20, 0, 0, 0, 0]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let e = parse_ntp_located(&buf).expect_err("parsing should fail");
+        assert_eq!(e.offset, 48);
+        assert_eq!(e.error, NtpError::InvalidExtensionLength);
+        let e = parse_ntp_located(&NTP_REQ2[..20]).expect_err("parsing should fail");
+        assert_eq!(e.offset, 20);
+        assert!(matches!(e.error, NtpError::Incomplete(_)));
+    }
+
+    #[test]
+    fn test_ntp_packet_multi_mac() {
+        let mut buf = NTP_REQ2.to_vec();
+        let second_mac = [
+            0x00, 0x00, 0x00, 0x02, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a,
+            0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10,
+        ];
+        buf.extend_from_slice(&second_mac);
+        let (rem, (pkt, macs)) = parse_ntpv4_multi_mac(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(macs.len(), 2);
+        assert_eq!(macs[0].key_id, 1);
+        assert_eq!(macs[0].mac, &NTP_REQ2[52..]);
+        assert_eq!(macs[1].key_id, 2);
+        assert_eq!(macs[1].mac, &second_mac[4..]);
+        assert_eq!(pkt.auth, Some(macs[0].clone()));
+        // no MAC
+        let (_, (pkt, macs)) = parse_ntpv4_multi_mac(NTP_REQ1).expect("parsing failed");
+        assert!(macs.is_empty());
+        assert!(pkt.auth.is_none());
+        // trailing bytes which are not a MAC
+        assert!(parse_ntpv4_multi_mac(&buf[..buf.len() - 1]).is_err());
+        // two 24-byte SHA-1 MACs
+        let mut buf = NTP_REQ1.to_vec();
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0x11; 20]);
+        buf.extend_from_slice(&[0, 0, 0, 2]);
+        buf.extend_from_slice(&[0x22; 20]);
+        let (rem, (pkt, macs)) = parse_ntpv4_multi_mac(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(macs.len(), 2);
+        assert_eq!((macs[0].key_id, macs[0].mac), (1, &[0x11; 20][..]));
+        assert_eq!((macs[1].key_id, macs[1].mac), (2, &[0x22; 20][..]));
+        assert_eq!(pkt.auth, Some(macs[0].clone()));
+    }
+
+    #[test]
+    fn test_ntp_expected_response_len() {
+        let (_, pkt) = parse_ntp(NTP_REQ2).expect("parsing failed");
+        assert_eq!(expected_response_len(&pkt), 68);
+        let (_, pkt) = parse_ntp(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(expected_response_len(&pkt), 48);
+        let mut buf = NTP_REQ2.to_vec();
+        buf[0] = 0x24; // server
+        let (_, pkt) = parse_ntp(&buf).expect("parsing failed");
+        assert_eq!(expected_response_len(&pkt), 0);
+        drop(pkt);
+        buf[0] = 0x27; // private
+        let (_, pkt) = parse_ntp(&buf).expect("parsing failed");
+        assert_eq!(expected_response_len(&pkt), 44000);
+    }
+
+    #[test]
+    fn test_ntp_interleaved_response() {
+        let mut request = NtpV4Packet::server_reply(0, 0, NtpTimestamp(0), NtpTimestamp(0));
+        request.mode = NtpMode::Client;
+        request.ts_recv = NtpTimestamp(0xe98a_f870_0000_0000);
+        request.ts_xmit = NtpTimestamp(0xe98a_f871_0000_0000);
+        let mut reply = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        // basic mode
+        reply.ts_orig = request.ts_xmit;
+        let basic = NtpPacket::V4(reply.clone());
+        let request = NtpPacket::V4(request);
+        assert!(!is_interleaved_response(&request, &basic));
+        // interleaved mode
+        reply.ts_orig = NtpTimestamp(0xe98a_f870_0000_0000);
+        let interleaved = NtpPacket::V4(reply);
+        assert!(is_interleaved_response(&request, &interleaved));
+        assert!(!is_interleaved_response(&interleaved, &request));
+        let (_, v3) = parse_ntp(NTPV3_REQ).expect("parsing failed");
+        assert!(!is_interleaved_response(&v3, &interleaved));
+    }
+
+    #[test]
+    fn test_ntp_amplification_factor() {
+        assert_eq!(amplification_factor(48, 48), 1.0);
+        assert_eq!(amplification_factor(8, 44000), 5500.0);
+        assert_eq!(amplification_factor(48, 0), 0.0);
+        assert_eq!(amplification_factor(0, 480), 0.0);
+    }
+
+    #[test]
+    fn test_ntp_offset_delay() {
+        let (_, mut pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        // T1 origin, T2 = T1 + 1.5s, T3 = T2 + 0.25s, T4 = T3 + 0.5s
+        pkt.ts_orig = NtpTimestamp(0xba29_6636_0000_0000);
+        pkt.ts_recv = NtpTimestamp(0xba29_6637_8000_0000);
+        pkt.ts_xmit = NtpTimestamp(0xba29_6637_c000_0000);
+        let dst = NtpTimestamp(0xba29_6638_4000_0000);
+        assert_eq!(pkt.clock_offset(dst), 0.5);
+        assert_eq!(pkt.round_trip_delay(dst), 2.0);
+        // same exchange, across the 2036 era boundary
+        pkt.ts_orig = NtpTimestamp(0xffff_ffff_0000_0000);
+        pkt.ts_recv = NtpTimestamp(0x0000_0000_8000_0000);
+        pkt.ts_xmit = NtpTimestamp(0x0000_0000_c000_0000);
+        let dst = NtpTimestamp(0x0000_0001_4000_0000);
+        assert_eq!(pkt.clock_offset(dst), 0.5);
+        assert_eq!(pkt.round_trip_delay(dst), 2.0);
+        // zero (unset) origin timestamp does not overflow
+        pkt.ts_orig = NtpTimestamp(0);
+        assert!(pkt.clock_offset(dst).is_finite());
+        assert!(pkt.round_trip_delay(dst).is_finite());
+    }
+
+    #[test]
+    fn test_ntp_server_reply() {
+        let (_, request) = parse_ntpv4(NTP_REQ2).expect("parsing failed");
+        let t1 = request.ts_xmit.0;
+        let recv = NtpTimestamp(t1 + (2 << 32));
+        let xmit = NtpTimestamp(t1 + (3 << 32));
+        let mut reply = NtpV4Packet::server_reply(2, 0xc0a8_0001, recv, xmit);
+        reply.ts_orig = request.ts_xmit;
+        assert_eq!(reply.mode, NtpMode::Server);
+        assert_eq!(reply.validate(), Ok(()));
+        let dst = NtpTimestamp(t1 + (1 << 32));
+        assert_eq!(reply.round_trip_delay(dst), 0.0);
+        assert_eq!(reply.clock_offset(dst), 2.0);
+    }
+
+    #[test]
+    fn test_ntp_flags_byte() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ1).expect("parsing failed");
+        assert_eq!(pkt.flags_byte(), NTP_REQ1[0]);
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(pkt.flags_byte(), NTPV3_REQ[0]);
+    }
+
+    #[test]
+    fn test_ntp_direction() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2).expect("parsing failed");
+        assert_eq!(pkt.direction(), Direction::Query);
+        let reply = NtpV4Packet::server_reply(1, 0, NtpTimestamp(1), NtpTimestamp(2));
+        assert_eq!(reply.direction(), Direction::Response);
+        // symmetric active, zero origin timestamp
+        let (_, mut pkt) = parse_ntpv4(NTP_REQ1).expect("parsing failed");
+        assert_eq!(pkt.direction(), Direction::Query);
+        pkt.ts_orig = NtpTimestamp(1);
+        assert_eq!(pkt.direction(), Direction::Response);
+        pkt.mode = NtpMode::Private;
+        assert_eq!(pkt.direction(), Direction::Unknown);
+    }
+
+    #[test]
+    fn test_ntp_serialize_round_trip() {
+        for &data in &[NTP_REQ1, NTP_REQ2, NTP_REQ2B, NTPV3_REQ, NTP_REQ_NO_MAC] {
+            let (rem, pkt) = parse_ntp(data).expect("parsing failed");
+            assert!(rem.is_empty());
+            let bytes = match pkt {
+                NtpPacket::V3(pkt) => pkt.to_bytes(),
+                NtpPacket::V4(pkt) => pkt.to_bytes(),
+                _ => panic!("unexpected packet type"),
+            };
+            assert_eq!(bytes, data);
+        }
+    }
+
+    #[test]
+    fn test_ntp_transmit_timestamp_entropy() {
+        let mut pkt = NtpV4Packet::server_reply(2, 0, NtpTimestamp(0), NtpTimestamp(0));
+        for &(ts, suspicious) in &[
+            (0xe98a_f870_0000_0000, true),
+            (0xe98a_f870_1234_0000, true),
+            (0xe98a_f870_1234_ffff, true),
+            (0xe98a_f870_1234_5a5a, true),
+            (0xe98a_f870_1234_5a5b, false),
+            (0xe98a_f870_0000_c3e1, false),
+        ] {
+            pkt.ts_xmit = NtpTimestamp(ts);
+            assert_eq!(pkt.transmit_timestamp_entropy_suspicious(), suspicious);
+        }
+    }
+
+    #[test]
+    fn test_ntp_extensions_raw() {
+        let (rem, (pkt, region)) = parse_ntpv4_extensions_raw(NTP_REQ2B).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(region, &NTP_REQ2B[48..52]);
+        assert!(pkt.extensions.is_empty());
+        let (_, expected) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.auth, expected.auth);
+        // the region is not validated
+        let mut buf = NTP_REQ1.to_vec();
+        buf.extend_from_slice(&[0xff; 8]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let (_, (pkt, region)) = parse_ntpv4_extensions_raw(&buf).expect("parsing failed");
+        assert_eq!(region, &[0xff; 8]);
+        assert_eq!(pkt.auth, expected.auth);
+        assert!(parse_ntpv4(&buf).is_err());
+        let (_, (pkt, region)) = parse_ntpv4_extensions_raw(NTP_REQ1).expect("parsing failed");
+        assert!(region.is_empty());
+        assert_eq!(pkt.auth, None);
+    }
+
+    #[test]
+    fn test_ntp_serialize_reparse() {
+        for &data in &[NTP_REQ1, NTP_REQ2, NTP_REQ2B] {
+            let (_, pkt) = parse_ntpv4(data).expect("parsing failed");
+            let bytes = pkt.to_bytes();
+            let (rem, reparsed) = parse_ntpv4(&bytes).expect("parsing failed");
+            assert!(rem.is_empty());
+            // compare owned packets, since `SmallVec` is invariant in the lifetime
+            assert_eq!(reparsed.into_owned(), pkt.into_owned());
+        }
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        let bytes = pkt.to_bytes();
+        assert_eq!(parse_ntpv3(&bytes), Ok((&[][..], pkt)));
+    }
+
+    #[test]
+    fn test_ntp_header_lazy() {
+        let (rem, header) = parse_ntp_header_lazy(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(rem, &NTP_REQ2B[48..]);
+        assert_eq!(header.raw_timestamps, &NTP_REQ2B[16..48]);
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(
+            (header.li, header.version, header.mode, header.stratum),
+            (pkt.li, pkt.version, pkt.mode, pkt.stratum)
+        );
+        assert_eq!(header.ref_id, pkt.ref_id);
+        assert_eq!(header.timestamps(), pkt.timestamps());
+        assert!(parse_ntp_header_lazy(&NTP_REQ2B[..47]).is_err());
+    }
+
+    #[test]
+    fn test_ntp_iter_extensions() {
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x09, 1, 2, 3, 4, 5, 0, 0, 0]);
+        buf.extend_from_slice(&[0x02, 0x04, 0x00, 0x08, 6, 7, 8, 9]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        let extensions: Result<Vec<_>, _> = iter_extensions(&buf[48..]).collect();
+        assert_eq!(extensions.as_deref(), Ok(&pkt.extensions[..]));
+        let ext =
+            iter_extensions(&buf[48..]).find(|e| matches!(e, Ok(e) if e.field_type == 0x0204));
+        assert_eq!(ext.map(|e| e.map(|e| e.value)), Some(Ok(&[6, 7, 8, 9][..])));
+        assert_eq!(iter_extensions(&NTP_REQ2[48..]).count(), 0);
+        // the error is the last item
+        let mut iter = iter_extensions(&buf[48..60]);
+        assert!(matches!(iter.next(), Some(Err(_))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_ntp_for_each_extension() {
+        let mut types = Vec::new();
+        let (rem, ()) = for_each_extension(&NTP_REQ2B[48..], |ext| types.push(ext.field_type))
+            .expect("parsing failed");
+        assert_eq!(rem, &NTP_REQ2B[52..]);
+        assert_eq!(types, vec![0]);
+        let mut count = 0;
+        let (rem, ()) =
+            for_each_extension(&NTP_REQ2[48..], |_| count += 1).expect("parsing failed");
+        assert_eq!(rem.len(), 20);
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_ntp_parse_extensions_region() {
+        let region = &[0x00, 0x01, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd, 0, 0, 0, 1];
+        let mut data = region.to_vec();
+        data.extend_from_slice(&[0x55; 20]);
+        let (rem, extensions) = parse_extensions_region(&data, 24).expect("parsing failed");
+        assert_eq!(rem, &data[8..]);
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0].value, &[0xaa, 0xbb, 0xcc, 0xdd]);
+        // MAC alone, key identifier alone, or nothing
+        for len in &[0, 4, 24] {
+            let (rem, extensions) =
+                parse_extensions_region(&data[8..8 + len], 24).expect("parsing failed");
+            assert_eq!(rem.len(), *len);
+            assert!(extensions.is_empty());
+        }
+        assert!(parse_extensions_region(&data[..12], 24).is_err());
+        let (rem, extensions) =
+            parse_extensions_region(&NTP_REQ2B[48..], 20).expect("parsing failed");
+        assert_eq!(rem, &NTP_REQ2B[52..]);
+        assert_eq!(extensions.len(), 1);
+    }
+
+    #[test]
+    fn test_ntp_best_effort_extensions() {
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        // two valid extensions, then a truncated one
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x06, 0xaa, 0xbb, 0x00, 0x00]);
+        buf.extend_from_slice(&[0x00, 0x02, 0x00, 0x04]);
+        buf.extend_from_slice(&[0x00, 0x03, 0x00, 0x10, 0xcc]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let (rem, (pkt, err)) = parse_ntpv4_best_effort(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 2);
+        assert_eq!(pkt.extensions[0].value, &[0xaa, 0xbb]);
+        assert_eq!(pkt.extensions[1].field_type, 2);
+        assert_eq!(err.map(|e| e.offset), Some(60));
+        // a valid packet gives the same result as the normal parser
+        let (_, (pkt, err)) = parse_ntpv4_best_effort(NTP_REQ2B).expect("parsing failed");
+        assert!(err.is_none());
+        assert_eq!(Ok((&b""[..], pkt)), parse_ntpv4(NTP_REQ2B));
+    }
+
+    #[test]
+    fn test_ntp_timestamp_accessors() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.reference_timestamp(), pkt.ts_ref);
+        assert_eq!(pkt.origin_timestamp(), NtpTimestamp(0));
+        assert_eq!(pkt.receive_timestamp(), pkt.ts_recv);
+        assert_eq!(pkt.transmit_timestamp().seconds(), 0xcc25_cc13);
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(pkt.transmit_timestamp(), pkt.ts_xmit);
+    }
+
+    #[test]
+    fn test_ntp_parse_complete() {
+        let short = &NTP_REQ1[..40];
+        assert!(matches!(parse_ntp(short), Err(Err::Incomplete(_))));
+        assert!(matches!(
+            parse_ntp_complete(short),
+            Err(Err::Error(e)) if e.code == ErrorKind::Complete
+        ));
+        assert!(matches!(parse_ntpv4_complete(short), Err(Err::Error(_))));
+        assert!(matches!(
+            parse_ntpv3_complete(&NTPV3_REQ[..10]),
+            Err(Err::Error(_))
+        ));
+        assert_eq!(parse_ntp_complete(NTP_REQ1), parse_ntp(NTP_REQ1));
+    }
+
+    #[test]
+    fn test_ntp_poll_interval_checked() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.poll_interval_checked(), Some(Duration::from_secs(64)));
+        pkt.poll = 17;
+        assert_eq!(
+            pkt.poll_interval_checked(),
+            Some(Duration::from_secs(131_072))
+        );
+        pkt.poll = -6;
+        assert_eq!(
+            pkt.poll_interval_checked(),
+            Some(Duration::from_micros(15_625))
+        );
+        pkt.poll = 18;
+        assert_eq!(pkt.poll_interval_checked(), None);
+        pkt.poll = i8::MIN;
+        assert_eq!(pkt.poll_interval_checked(), None);
+    }
+
+    #[test]
+    fn test_ntp_time_accessors() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.poll_interval(), 64.0);
+        pkt.poll = -6;
+        assert_eq!(pkt.poll_interval(), 0.015625);
+        pkt.precision = Precision(-6);
+        assert_eq!(pkt.precision_seconds(), 0.015625);
+        assert_eq!(pkt.precision_seconds() as f32, pkt.get_precision());
+        assert_eq!(
+            pkt.precision_duration(),
+            Some(Duration::from_micros(15_625))
+        );
+        pkt.precision = Precision(i8::MAX);
+        assert_eq!(pkt.precision_duration(), None);
+        pkt.root_delay = NtpShortFormat(0x0001_8000);
+        pkt.root_dispersion = NtpShortFormat(0x0000_4000);
+        assert_eq!(pkt.root_delay_seconds(), 1.5);
+        assert_eq!(pkt.root_delay_duration(), Duration::from_millis(1500));
+        assert_eq!(pkt.root_dispersion_seconds(), 0.25);
+        assert_eq!(pkt.root_dispersion_duration(), Duration::from_millis(250));
+        // version 3
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(pkt.poll_interval(), pow2(i32::from(pkt.poll)));
+        assert_eq!(pkt.root_delay_seconds(), pkt.root_delay.to_seconds());
+    }
+
+    #[test]
+    fn test_ntp_stratum_description() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.stratum_description(), "primary reference");
+        pkt.stratum = 15;
+        assert_eq!(pkt.stratum_description(), "secondary reference");
+        pkt.stratum = 16;
+        assert_eq!(pkt.stratum_description(), "unsynchronized");
+        pkt.stratum = 0;
+        assert_eq!(pkt.stratum_description(), "unspecified/kiss");
+        pkt.stratum = 200;
+        assert_eq!(pkt.stratum_description(), "reserved");
+    }
+
+    #[test]
+    fn test_ntp_leap_indicator_stratum_kind() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.leap_indicator(), NtpLeapIndicator::NoWarning);
+        assert_eq!(pkt.stratum_kind(), StratumKind::Primary);
+        pkt.li = 3;
+        pkt.stratum = 16;
+        assert_eq!(pkt.leap_indicator(), NtpLeapIndicator::Unknown);
+        assert_eq!(pkt.stratum_kind(), StratumKind::Unsynchronized);
+        let (_, v3) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(v3.leap_indicator(), NtpLeapIndicator::NoWarning);
+        assert_eq!(
+            NtpLeapIndicator::from(LeapIndicator::LastMinute61),
+            NtpLeapIndicator::AddSecond
+        );
+        assert_eq!(
+            NtpLeapIndicator::from(LeapIndicator::LastMinute59),
+            NtpLeapIndicator::SubSecond
+        );
+        assert_eq!(v3.stratum_kind(), StratumKind::Secondary);
+        assert_eq!(StratumKind::from(0), StratumKind::Unspecified);
+        assert_eq!(StratumKind::from(17), StratumKind::Reserved);
+    }
+
+    #[test]
+    fn test_ntp_clock_source_category() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0x4750_5331, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.clock_source_category(), Some(ClockCategory::Gnss));
+        pkt.stratum = 2;
+        assert_eq!(pkt.clock_source_category(), None);
+        assert_eq!(pkt.local_refclock(), None);
+        pkt.ref_id = 0x7f7f_0100;
+        assert_eq!(
+            pkt.local_refclock(),
+            Some(LocalRefClock {
+                clock_type: 1,
+                unit: 0
+            })
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ntp_reference_id() {
+        let mut pkt = NtpV4Packet::server_reply(0, 0x4445_4e59, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.reference_id(), RefId::KissCode(*b"DENY"));
+        pkt.stratum = 1;
+        pkt.ref_id = 0x4750_5300;
+        assert_eq!(pkt.reference_id(), RefId::ClockSource(*b"GPS\0"));
+        pkt.stratum = 3;
+        pkt.ref_id = 0xc0a8_0001;
+        assert_eq!(
+            pkt.reference_id(),
+            RefId::IpAddr(Ipv4Addr::new(192, 168, 0, 1))
+        );
+        pkt.stratum = 16;
+        assert_eq!(pkt.reference_id(), RefId::Other(0xc0a8_0001));
+        let (_, v3) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(
+            v3.reference_id(),
+            RefId::IpAddr(Ipv4Addr::new(130, 220, 24, 24))
+        );
+    }
+
+    #[test]
+    fn test_ntp_ref_id_as_ascii() {
+        let mut pkt = NtpV4Packet::server_reply(2, 0x5054_5030, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.ref_id_as_ascii().as_deref(), Some("PTP0"));
+        pkt.ref_id = 0x4750_5300;
+        assert_eq!(pkt.ref_id_as_ascii().as_deref(), Some("GPS"));
+        pkt.ref_id = 0xc0a8_0001;
+        assert_eq!(pkt.ref_id_as_ascii(), None);
+        pkt.ref_id = 0x4700_5300;
+        assert_eq!(pkt.ref_id_as_ascii(), None);
+    }
+
+    #[test]
+    fn test_ntp_kiss_code() {
+        let mut reply =
+            NtpV4Packet::server_reply(16, 0x494e_4954, NtpTimestamp(1), NtpTimestamp(2));
+        assert_eq!(reply.kiss_code(), None);
+        assert_eq!(reply.ref_id_kiss_code(), Some(KissCode::Init));
+        reply.stratum = 0;
+        reply.ref_id = 0x5241_5445;
+        assert_eq!(reply.kiss_code(), Some(KissCode::Rate));
+    }
+
+    #[test]
+    fn test_ntp_wire_len() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.wire_len(), NTP_REQ2B.len());
+        let (_, pkt) = parse_ntpv4(NTP_REQ1).expect("parsing failed");
+        assert_eq!(pkt.wire_len(), 48);
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(pkt.wire_len(), NTPV3_REQ.len());
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.extensions.push(NtpExtension {
+            field_type: 1,
+            length: 9,
+            value: &[0; 5],
+        });
+        assert_eq!(pkt.wire_len(), 48 + 12);
+    }
+
+    // client request with a Unique Identifier extension field, but no MAC
+    static NTP_REQ_NO_MAC: &[u8] = &[
+        0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xe3, 0x6b, 0x1c, 0x52, 0x4f,
+        0x2a, 0x90, 0x11, 0x01, 0x04, 0x00, 0x14, 0x5d, 0x2f, 0x8e, 0x01, 0x93, 0x47, 0xc2, 0x7a,
+        0x0b, 0xe4, 0x31, 0x66, 0xa8, 0x0d, 0x52, 0xfc,
+    ];
+
+    #[test]
+    fn test_ntp_lenient_mac() {
+        let (rem, pkt) = parse_ntpv4_lenient_mac(NTP_REQ_NO_MAC).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.extensions[0].field_type, 0x0104);
+        assert_eq!(pkt.extensions[0].value, &NTP_REQ_NO_MAC[52..]);
+        assert!(pkt.auth.is_none());
+        assert!(pkt.has_extensions_without_mac());
+        // packets with a MAC are parsed as usual
+        let res = parse_ntpv4_lenient_mac(NTP_REQ2B);
+        assert_eq!(res, parse_ntpv4(NTP_REQ2B));
+        let pkt = res.expect("parsing failed").1;
+        assert!(pkt.auth.is_some());
+        assert!(!pkt.has_extensions_without_mac());
+        let res = parse_ntpv4_lenient_mac(NTP_REQ1);
+        assert_eq!(res, parse_ntpv4(NTP_REQ1));
+        // MAC with key identifier 20, which also reads as a 20-byte extension field (type 0)
+        let mut buf = NTP_REQ1.to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0x00, 0x00, 0x00, 0x14]);
+        buf.extend_from_slice(&[0x55; 16]);
+        let (rem, pkt) = parse_ntpv4_lenient_mac(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.auth.as_ref().map(|m| m.key_id), Some(20));
+        assert_eq!((rem, pkt), parse_ntpv4(&buf).expect("parsing failed"));
+    }
+
+    #[test]
+    fn test_ntp_mode_role() {
+        assert_eq!(NtpMode::Client.role(), NtpRole::Client);
+        assert_eq!(NtpMode::SymmetricPassive.role(), NtpRole::Peer);
+        assert_eq!(NtpMode::Private.role(), NtpRole::Private);
+        assert_eq!(NtpMode(0).role(), NtpRole::Reserved);
+        assert_eq!(NtpMode(42).role(), NtpRole::Reserved);
+    }
+
+    #[test]
+    fn test_ntp_time_since_sync() {
+        let mut pkt = NtpV4Packet::server_reply(2, 0, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.ts_ref = NtpTimestamp(1000 << 32);
+        pkt.ts_xmit = NtpTimestamp((1064 << 32) | 0x8000_0000);
+        assert_eq!(pkt.time_since_sync(), Some(Duration::from_millis(64_500)));
+        pkt.ts_xmit = NtpTimestamp(999 << 32);
+        assert_eq!(pkt.time_since_sync(), None);
+        pkt.ts_ref = NtpTimestamp(0);
+        assert_eq!(pkt.time_since_sync(), None);
+    }
+
+    #[test]
+    fn test_ntp_v4_with_v3_authenticator() {
+        let mut buf = patch(NTPV3_REQ, 0, 0x23);
+        buf.extend_from_slice(&[0, 0, 0, 1, 0xde, 0xad, 0xbe, 0xef, 0x01, 0x02, 0x03, 0x04]);
+        assert!(parse_ntpv4(&buf).is_err());
+        let (rem, pkt) = parse_ntp(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        match pkt {
+            NtpPacket::V3(pkt) => {
+                assert_eq!(pkt.version, 4);
+                assert_eq!(pkt.authenticator, Some(&buf[48..]));
+            }
+            _ => panic!("unexpected packet type"),
+        }
+        // 8-byte extension field and crypto-NAK: same length, but valid version 4
+        let buf = [
+            &NTPV3_REQ[..48],
+            &[0, 1, 0, 8, 0xaa, 0xbb, 0xcc, 0xdd],
+            &[0; 4],
+        ]
+        .concat();
+        let buf = patch(&buf, 0, 0x23);
+        let (rem, pkt) = parse_ntp(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        match pkt {
+            NtpPacket::V4(pkt) => {
+                assert_eq!(pkt.extensions.len(), 1);
+                assert!(pkt.auth.expect("missing MAC").is_crypto_nak());
+            }
+            _ => panic!("unexpected packet type"),
+        }
+    }
+
+    #[test]
+    fn test_ntp_mode_display_complete() {
+        for n in 0..=7 {
+            let s = NtpMode(n).to_string();
+            assert!(!s.starts_with("unknown"), "no name for mode {}", n);
+        }
+        assert_eq!(NtpMode(8).to_string(), "unknown (8)");
+    }
+
+    #[test]
+    fn test_ntp_jumbo_extension() {
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0x02, 0x04, 0x10, 0x04]);
+        buf.extend((0..4096).map(|b| b as u8));
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let (rem, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.extensions[0].length, 4100);
+        assert_eq!(pkt.extensions[0].value, &buf[52..52 + 4096]);
+        assert!(pkt.auth.is_some());
+        assert_eq!(pkt.wire_len(), buf.len());
+    }
+
+    #[test]
+    fn test_ntp_unaligned_extensions() {
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        // values of 5 and 2 bytes, each followed by padding
+        buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x09, 1, 2, 3, 4, 5, 0, 0, 0]);
+        buf.extend_from_slice(&[0x02, 0x04, 0x00, 0x06, 6, 7, 0, 0]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let (rem, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 2);
+        assert_eq!(pkt.extensions[0].length, 9);
+        assert_eq!(pkt.extensions[0].value, &[1, 2, 3, 4, 5]);
+        assert_eq!(pkt.extensions[1].length, 6);
+        assert_eq!(pkt.extensions[1].value, &[6, 7]);
+        let auth = pkt.auth.expect("missing MAC");
+        assert_eq!(auth.key_id, 1);
+        assert_eq!(auth.mac, &NTP_REQ2B[56..]);
+        // a length shorter than the header is invalid
+        let mut invalid = buf.clone();
+        invalid[51] = 2;
+        assert!(parse_ntpv4(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_ntp_parse_partial() {
+        let hdr = parse_ntp_partial(&NTP_REQ1[..30]);
+        assert_eq!(hdr.li, Some(3));
+        assert_eq!(hdr.version, Some(3));
+        assert_eq!(hdr.mode, Some(NtpMode::SymmetricActive));
+        assert_eq!(hdr.poll, Some(10));
+        assert_eq!(hdr.precision, Some(Precision(-6)));
+        assert_eq!(hdr.root_dispersion, Some(NtpShortFormat(0x0001_0290)));
+        assert_eq!(hdr.ts_ref, Some(NtpTimestamp(0)));
+        assert_eq!(hdr.ts_orig, None);
+        assert_eq!(hdr.ts_xmit, None);
+        let (_, pkt) = parse_ntpv4(NTP_REQ1).expect("parsing failed");
+        assert_eq!(parse_ntp_partial(NTP_REQ1).ts_xmit, Some(pkt.ts_xmit));
+        assert_eq!(parse_ntp_partial(&[]), PartialNtpHeader::default());
+    }
+
+    #[test]
+    fn test_ntp_compose_flags_byte() {
+        for b0 in 0..=255u8 {
+            let buf = patch(NTP_REQ1, 0, b0);
+            let (_, pkt) = NtpV4Packet::parse(&buf).expect("parsing failed");
+            assert_eq!(compose_flags_byte(pkt.li, pkt.version, pkt.mode), b0);
+        }
+        assert_eq!(compose_flags_byte(0, 4, NtpMode::Client), 0x23);
+    }
+
+    #[test]
+    fn test_ntp_custom_mac() {
+        let mut buf = NTP_REQ2B[..52].to_vec();
+        // 2-byte key identifier, 20-byte digest
+        buf.extend_from_slice(&[0x01, 0x02]);
+        buf.extend((0..20).map(|b| b as u8));
+        let layout = MacLayout {
+            key_id_len: 2,
+            digest_len: 20,
+        };
+        let (rem, (pkt, mac)) = parse_ntpv4_custom_mac(&buf, layout).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        assert!(pkt.auth.is_none());
+        let mac = mac.expect("missing MAC");
+        assert_eq!(mac.key_id, 0x0102);
+        assert_eq!(mac.digest, &buf[54..]);
+        // the standard layout gives the same MAC as the default parser
+        let (_, (_, mac)) =
+            parse_ntpv4_custom_mac(NTP_REQ2B, MacLayout::STANDARD).expect("parsing failed");
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        let auth = pkt.auth.expect("missing MAC");
+        let mac = mac.expect("missing MAC");
+        assert_eq!(mac.key_id, u64::from(auth.key_id));
+        assert_eq!(mac.digest, auth.mac);
+        // the MAC length overflows
+        let layout = MacLayout {
+            key_id_len: 4,
+            digest_len: usize::MAX,
+        };
+        assert_eq!(
+            parse_ntpv4_custom_mac(NTP_REQ2B, layout),
+            Err(Err::Error(make_error(NTP_REQ2B, ErrorKind::TooLarge)))
+        );
+    }
+
+    #[test]
+    fn test_ntp_packet_hash() {
+        use std::collections::HashSet;
+        let mut set = HashSet::new();
+        for bytes in &[NTP_REQ1, NTP_REQ2, NTP_REQ2B, NTP_REQ1] {
+            let (_, pkt) = parse_ntp(bytes).expect("parsing failed");
+            set.insert(pkt);
+        }
+        assert_eq!(set.len(), 3);
+        let (_, pkt) = parse_ntp(NTP_REQ2).expect("parsing failed");
+        assert!(set.contains(&pkt));
+    }
+
+    #[test]
+    fn test_ntp_supported_versions() {
+        for &v in supported_versions() {
+            assert!(parse_ntp_as(NTP_REQ1, NtpVersion(v)).is_ok());
+        }
+        assert_eq!(supported_versions().contains(&5), cfg!(feature = "ntpv5"));
+        assert!(!supported_versions().contains(&6));
+        // version 2 client request
+        let buf = patch(NTP_REQ1, 0, 0x13);
+        let res = parse_ntp(&buf);
+        assert!(matches!(res, Ok((_, NtpPacket::V3(ref pkt))) if pkt.version == 2));
+    }
+
+    #[cfg(feature = "smallvec")]
+    #[test]
+    fn test_ntp_extensions_inline() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), 1);
+        assert!(!pkt.extensions.spilled());
+    }
+
+    #[test]
+    fn test_ntp_max_extensions() {
+        let packet = |count: usize| {
+            let mut buf = NTP_REQ2B[..48].to_vec();
+            for _ in 0..count {
+                buf.extend_from_slice(&[0, 0, 0, 4]);
+            }
+            buf.extend_from_slice(&NTP_REQ2B[52..]);
+            buf
+        };
+        let buf = packet(MAX_EXTENSIONS);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), MAX_EXTENSIONS);
+        let buf = packet(300);
+        let res = parse_ntpv4(&buf);
+        assert!(matches!(res, Err(Err::Error(e)) if e.code == ErrorKind::TooLarge));
+        let (_, (pkt, err)) = parse_ntpv4_best_effort(&buf).expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), MAX_EXTENSIONS);
+        assert_eq!(
+            err.map(|e| e.error),
+            Some(NtpError::Nom(ErrorKind::TooLarge))
+        );
+    }
+
+    #[test]
+    fn test_ntp_packet_with_trailer() {
+        let trailer = [0x56, 0x4e, 0x44, 0x52, 0x01, 0x02, 0x03, 0x04];
+        let mut buf = NTP_REQ2.to_vec();
+        buf.extend_from_slice(&trailer);
+        let (rem, (pkt, t)) = parse_ntpv4_with_trailer(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(t, &trailer);
+        assert!(pkt.extensions.is_empty());
+        assert_eq!(pkt.auth.map(|m| m.mac), Some(&NTP_REQ2[52..]));
+        // extension, MAC and trailer
+        let mut buf = NTP_REQ2B.to_vec();
+        buf.extend_from_slice(&trailer);
+        let (_, (pkt, t)) = parse_ntpv4_with_trailer(&buf).expect("parsing failed");
+        assert_eq!(t, &trailer);
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.auth.map(|m| m.key_id), Some(1));
+        // no trailer
+        let (_, (pkt, t)) = parse_ntpv4_with_trailer(NTP_REQ2).expect("parsing failed");
+        assert!(t.is_empty());
+        assert!(pkt.auth.is_some());
+        // unaligned trailer
+        assert!(parse_ntpv4_with_trailer(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_ntp_check_extension_alignment() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert!(pkt.check_extension_alignment(NTP_REQ2B.len()));
+        assert!(!pkt.check_extension_alignment(NTP_REQ2B.len() + 4));
+        let (_, pkt) = parse_ntpv4(NTP_REQ1).expect("parsing failed");
+        assert!(pkt.check_extension_alignment(48));
+    }
+
+    #[test]
+    fn test_ntp_effective_dispersion() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.poll = 4;
+        pkt.precision = Precision(-1);
+        pkt.root_dispersion = NtpShortFormat(0x0000_8000);
+        let d = pkt.effective_dispersion(Duration::from_secs(16), 0.5);
+        assert_eq!(d, 0.5 + 0.5 + 0.5 * 32.0);
+        assert_eq!(pkt.effective_dispersion(Duration::ZERO, 0.0), 1.0);
+    }
+
+    #[test]
+    fn test_ntp_root_distance() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.root_distance(), 0.0);
+        pkt.root_delay = NtpShortFormat(0x0001_0000);
+        pkt.root_dispersion = NtpShortFormat(0x0000_4000);
+        assert_eq!(pkt.root_distance(), 0.75);
+    }
+
+    #[cfg(feature = "header-bytes")]
+    #[test]
+    fn test_ntp_header_bytes() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.header_bytes, &NTP_REQ2B[..52]);
+        assert_eq!(pkt.raw_auth_bytes(), Some(&NTP_REQ2B[52..]));
+        let (_, pkt) = parse_ntpv4_lenient_mac(&NTP_REQ2B[..52]).expect("parsing failed");
+        assert_eq!(pkt.header_bytes, &NTP_REQ2B[..52]);
+        assert_eq!(pkt.raw_auth_bytes(), None);
+        let (_, (pkt, _)) = parse_ntpv4_with_trailer(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt.raw_auth_bytes(), Some(&NTP_REQ2B[52..]));
+    }
+
+    #[test]
+    fn test_ntp_clock_quality() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert_eq!(pkt.clock_quality(), ClockQuality::High);
+        pkt.root_dispersion = NtpShortFormat::from_duration(Duration::from_millis(50));
+        assert_eq!(pkt.clock_quality(), ClockQuality::Medium);
+        pkt.stratum = 5;
+        assert_eq!(pkt.clock_quality(), ClockQuality::Low);
+        pkt.stratum = 2;
+        pkt.root_dispersion = NtpShortFormat(0);
+        pkt.precision = Precision(-6);
+        assert_eq!(pkt.clock_quality(), ClockQuality::Low);
+        pkt.precision = Precision(-20);
+        pkt.li = 3;
+        assert_eq!(pkt.clock_quality(), ClockQuality::Low);
+    }
+
+    #[test]
+    fn test_ntp_reference_accuracy() {
+        let mut pkt = NtpV4Packet::server_reply(1, 0x4750_5300, NtpTimestamp(0), NtpTimestamp(0));
+        pkt.precision = Precision(-24);
+        pkt.root_dispersion = NtpShortFormat(0);
+        let accuracy = pkt.reference_accuracy();
+        assert_eq!(accuracy.class, AccuracyClass::SubMicrosecond);
+        assert_eq!(accuracy.to_string(), "sub-microsecond GNSS primary");
+        pkt.stratum = 3;
+        pkt.root_delay = NtpShortFormat::from_duration(Duration::from_millis(10));
+        let accuracy = pkt.reference_accuracy();
+        assert_eq!(accuracy.class, AccuracyClass::Millisecond);
+        assert_eq!(accuracy.to_string(), "millisecond secondary");
+        assert!((accuracy.error_bound - 0.005).abs() < 1e-4);
+        pkt.root_dispersion = NtpShortFormat::from_duration(Duration::from_millis(200));
+        assert_eq!(pkt.reference_accuracy().class, AccuracyClass::Coarse);
+        pkt.stratum = 16;
+        assert_eq!(pkt.reference_accuracy().to_string(), "unsynchronized");
+        // local clock
+        pkt.stratum = 1;
+        pkt.root_delay = NtpShortFormat(0);
+        pkt.root_dispersion = NtpShortFormat(0);
+        pkt.ref_id = 0x4c4f_434c;
+        assert_eq!(pkt.reference_accuracy().to_string(), "coarse local primary");
+    }
+
+    #[test]
+    fn test_ntp_parse_skipping() {
+        let mut buf = vec![0xff; 8];
+        buf.extend_from_slice(NTP_REQ2);
+        let (rem, pkt) = parse_ntp_skipping(&buf, 8).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(Ok((rem, pkt)), parse_ntp(NTP_REQ2));
+        assert!(parse_ntp_skipping(&buf[..4], 8).is_err());
+    }
+
+    #[test]
+    fn test_ntp_byte_layout() {
+        let layout = byte_layout(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(layout.header, 0..48);
+        assert_eq!(layout.extensions, vec![48..52]);
+        assert_eq!(layout.mac, Some(52..72));
+        let layout = byte_layout(NTP_REQ1).expect("parsing failed");
+        assert!(layout.extensions.is_empty());
+        assert_eq!(layout.mac, None);
+        assert!(byte_layout(&NTP_REQ1[..20]).is_err());
+    }
+
+    #[test]
+    fn test_ntp_variable_mac() {
+        // 24-byte SHA-1 MAC, with and without extension field
+        let mut buf = NTP_REQ1.to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0x55; 20]);
+        let (rem, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        let auth = pkt.auth.expect("missing MAC");
+        assert_eq!((auth.key_id, auth.mac), (1, &[0x55; 20][..]));
+        let sha1_only = [&buf[..48], &buf[56..]].concat();
+        let (_, pkt) = parse_ntpv4(&sha1_only).expect("parsing failed");
+        assert!(pkt.extensions.is_empty());
+        assert_eq!(pkt.auth.map(|m| m.mac.len()), Some(20));
+        // crypto-NAK, with and without extension field
+        let nak = [&buf[..56], &[0; 4]].concat();
+        let (rem, pkt) = parse_ntpv4(&nak).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        assert!(pkt.auth.expect("missing MAC").is_crypto_nak());
+        let nak_only = [&buf[..48], &[0; 4]].concat();
+        let (_, pkt) = parse_ntpv4(&nak_only).expect("parsing failed");
+        assert!(pkt.auth.expect("missing MAC").is_crypto_nak());
+        // extension field without MAC
+        assert!(parse_ntpv4(&buf[..56]).is_err());
+    }
+
+    #[test]
+    fn test_ntp_malformed_trailer() {
+        // version 4: 7 bytes are neither an extension field nor a MAC
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0, 0, 0, 1, 0xaa, 0xbb, 0xcc]);
+        assert!(parse_ntpv4(&buf).is_err());
+        let res = parse_ntp_e::<NtpError>(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::TruncatedMac)));
+        let (rem, pkt) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(pkt.auth.is_some());
+        // version 3: only 12 and 20-byte authenticators are accepted
+        for &len in &[12, 20] {
+            let buf = [NTPV3_REQ, &[0x55; 20][..len]].concat();
+            let (rem, pkt) = parse_ntpv3(&buf).expect("parsing failed");
+            assert!(rem.is_empty());
+            assert_eq!(pkt.authenticator.map(<[u8]>::len), Some(len));
+        }
+        for &len in &[1, 4, 11, 13, 19, 21] {
+            let buf = [NTPV3_REQ, &[0x55; 21][..len]].concat();
+            assert!(parse_ntpv3(&buf).is_err());
+            let res = parse_ntp_e::<NtpError>(&buf);
+            assert_eq!(res, Err(Err::Error(NtpError::TruncatedMac)));
+        }
+    }
+
+    #[test]
+    fn test_ntp_parse_with_options() {
+        let mut buf = NTP_REQ1.to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0x55; 20]);
+        // 24-byte SHA-1 MAC
+        let sha1 = NtpParseOptions { mac_len: 24 };
+        let (rem, pkt) = parse_ntpv4_with_options(&buf, sha1).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 1);
+        let auth = pkt.auth.expect("missing MAC");
+        assert_eq!((auth.key_id, auth.mac), (1, &[0x55; 20][..]));
+        assert!(parse_ntpv4_with_options(&buf[..48 + 12], sha1).is_err());
+        let (_, pkt) = parse_ntpv4_with_options(&buf[..48], sha1).expect("parsing failed");
+        assert_eq!(pkt.auth, None);
+        let mut sha1_only = NTP_REQ1.to_vec();
+        sha1_only.extend_from_slice(&buf[56..]);
+        let (_, pkt) = parse_ntpv4_with_options(&sha1_only, sha1).expect("parsing failed");
+        assert!(pkt.extensions.is_empty());
+        assert_eq!(pkt.auth.map(|m| m.mac.len()), Some(20));
+        let md5 = NtpParseOptions::default();
+        // with the default length, the key identifier is parsed as an extension field
+        assert!(parse_ntpv4_with_options(&sha1_only, md5).is_err());
+        // 20-byte MD5 MAC, same as parse_ntpv4
+        let (_, pkt) = parse_ntpv4_with_options(NTP_REQ2B, md5).expect("parsing failed");
+        let (_, expected) = parse_ntpv4(NTP_REQ2B).expect("parsing failed");
+        assert_eq!(pkt, expected);
+    }
+
+    #[test]
+    fn test_ntp_sntp_request() {
+        let is_request = |data: Vec<u8>| {
+            let res = parse_ntp(&data).expect("parsing failed");
+            match res {
+                (_, NtpPacket::V3(pkt)) => pkt.is_valid_sntp_request(),
+                (_, NtpPacket::V4(pkt)) => pkt.is_valid_sntp_request(),
+                _ => false,
+            }
+        };
+        let mut buf = vec![0xe3];
+        buf.resize(48, 0);
+        buf[40..48].copy_from_slice(&[0xe9, 0x8a, 0xf8, 0x70, 0, 0, 0, 1]);
+        assert!(is_request(buf.clone()));
+        // stratum, receive timestamp
+        assert!(!is_request(patch(&buf, 1, 2)));
+        assert!(!is_request(patch(&buf, 39, 1)));
+        // server mode
+        assert!(!is_request(patch(&buf, 0, 0xe4)));
+        // version 3
+        assert!(is_request(patch(&buf, 0, 0xdb)));
+    }
+
+    #[test]
+    fn test_ntp_parse_generic_error() {
+        use nom::error::{VerboseError, VerboseErrorKind};
+
+        let (rem, pkt) = parse_ntp_e::<VerboseError<&[u8]>>(NTP_REQ2B).expect("parsing failed");
+        assert_eq!((rem, pkt), parse_ntp(NTP_REQ2B).expect("parsing failed"));
+        // 10 bytes after the header: neither extension fields nor a MAC
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0; 10]);
+        let err = match parse_ntp_e::<VerboseError<&[u8]>>(&buf) {
+            Err(Err::Error(e)) => e,
+            e => panic!("unexpected result {:?}", e),
+        };
+        let kinds: Vec<&VerboseErrorKind> = err.errors.iter().map(|(_, k)| k).collect();
+        assert_eq!(
+            kinds,
+            [
+                &VerboseErrorKind::Nom(ErrorKind::Eof),
+                &VerboseErrorKind::Context("extension fields and MAC"),
+                &VerboseErrorKind::Context("NTPv4 packet"),
+            ]
+        );
+        // the error of the authenticator parser is threaded to the caller
+        let buf = [NTPV3_REQ, &[0x55; 13]].concat();
+        let err = match parse_ntp_e::<VerboseError<&[u8]>>(&buf) {
+            Err(Err::Error(e)) => e,
+            e => panic!("unexpected result {:?}", e),
+        };
+        let kinds: Vec<&VerboseErrorKind> = err.errors.iter().map(|(_, k)| k).collect();
+        assert_eq!(
+            kinds,
+            [
+                &VerboseErrorKind::Nom(ErrorKind::LengthValue),
+                &VerboseErrorKind::Context("authenticator"),
+                &VerboseErrorKind::Context("NTPv3 packet"),
+            ]
+        );
+        assert_eq!(err.errors[0].0, &buf[48..]);
+    }
+
+    #[test]
+    fn test_ntp_parse_typed_error() {
+        use crate::error::NtpResult;
+
+        let res = parse_ntp_e::<NtpError>(NTP_REQ2B);
+        assert_eq!(res.ok(), parse_ntp(NTP_REQ2B).ok());
+        // version 7
+        let buf = patch(NTP_REQ1, 0, 0xf9);
+        let res = parse_ntp_e::<NtpError>(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::UnsupportedVersion(7))));
+        // 10 bytes after the header
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0; 10]);
+        let res = parse_ntp_e::<NtpError>(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::TruncatedMac)));
+        // extension field exceeding the packet
+        let mut buf = NTP_REQ2B[..48].to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x40, 0, 0, 0, 0]);
+        buf.extend_from_slice(&NTP_REQ2B[52..]);
+        let res: NtpResult<NtpPacket> = parse_ntp_e(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::InvalidExtensionLength)));
+        let res: NtpResult<NtpV4Packet> = parse_ntpv4_e(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::InvalidExtensionLength)));
+        // the variants are emitted by the version-specific parsers
+        let res = parse_ntp_as_e::<NtpError>(NTP_REQ1, NtpVersion(0));
+        assert_eq!(res, Err(Err::Error(NtpError::UnsupportedVersion(0))));
+        let buf = [NTPV3_REQ, &[0x55; 13]].concat();
+        let res = parse_ntpv3_e::<NtpError>(&buf);
+        assert_eq!(res, Err(Err::Error(NtpError::TruncatedMac)));
+        // nom errors keep the error kind
+        let res = parse_ntpv3_e::<Error<&[u8]>>(&buf).map_err(|e| e.map(|e| e.code));
+        assert_eq!(res, Err(Err::Error(ErrorKind::LengthValue)));
+    }
+
+    #[test]
+    fn test_ntp_looks_like_ntp() {
+        assert!(looks_like_ntp(NTP_REQ1));
+        assert!(looks_like_ntp(NTP_REQ2B));
+        assert!(looks_like_ntp(NTPV3_REQ));
+        assert!(!looks_like_ntp(&NTP_REQ2[..47]));
+        // version 2
+        assert!(!looks_like_ntp(&patch(NTP_REQ2, 0, 0x13)));
+        // control message
+        assert!(!looks_like_ntp(&patch(NTP_REQ2, 0, 0x26)));
+        // stratum 17
+        assert!(!looks_like_ntp(&patch(NTP_REQ2, 1, 17)));
+    }
+
+    #[test]
+    fn test_ntpv3_authenticator_layout() {
+        let (_, pkt) = parse_ntpv3(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(pkt.authenticator_layout(), None);
+        // key identifier and DES checksum
+        let mut des = NTPV3_REQ.to_vec();
+        des.extend_from_slice(&[0, 0, 0, 5, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let (rem, pkt) = parse_ntpv3(&des).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.authenticator, Some(&des[48..]));
+        assert_eq!(pkt.authenticator_layout(), Some(NtpV3AuthLayout::Des));
+        // key identifier and MD5 digest
+        let mut md5 = NTPV3_REQ.to_vec();
+        md5.extend_from_slice(&[0, 0, 0, 5]);
+        md5.extend_from_slice(&[0xab; 16]);
+        let (rem, pkt) = parse_ntpv3(&md5).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.authenticator, Some(&md5[48..]));
+        assert_eq!(pkt.authenticator_layout(), Some(NtpV3AuthLayout::Md5));
+    }
+
+    #[test]
+    fn test_ntp_timestamps() {
+        let (_, pkt) = parse_ntpv4(NTP_REQ2).expect("parsing failed");
+        let ts = pkt.timestamps();
+        assert_eq!(ts.reference, pkt.reference_timestamp());
+        assert_eq!(ts.origin, pkt.ts_orig);
+        assert_eq!(ts.receive, pkt.ts_recv);
+        assert_eq!(ts.transmit, NtpTimestamp(0xcc25_cc13_2b02_1000));
+    }
+
+    #[test]
+    fn test_ntp_exchange_key() {
+        let (_, request) = parse_ntpv4(NTP_REQ2).expect("parsing failed");
+        let mut reply = NtpV4Packet::server_reply(1, 0, NtpTimestamp(1), NtpTimestamp(2));
+        reply.ts_orig = request.ts_xmit;
+        assert_eq!(request.exchange_key(), request.ts_xmit);
+        assert_eq!(reply.exchange_key(), request.exchange_key());
+        reply.mode = NtpMode::Broadcast;
+        assert_eq!(reply.exchange_key(), NtpTimestamp(2));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ntp_parse_datagram() {
+        let mut buf = NTP_REQ2.to_vec();
+        buf.resize(512, 0xff);
+        let res = parse_ntp_datagram(&buf, NTP_REQ2.len());
+        assert!(matches!(res, Ok(NtpPacket::V4(ref pkt)) if pkt.auth.is_some()));
+        // stale bytes included
+        let res = parse_ntp_datagram(&buf, NTP_REQ2.len() + 8);
+        assert!(res.is_err());
+        let res = parse_ntp_datagram(&buf[..48], 52);
+        assert_eq!(res, Err(NtpError::Incomplete(Needed::new(4))));
+        // version 3 packet followed by garbage
+        let mut buf = NTPV3_REQ.to_vec();
+        buf.extend_from_slice(&[0; 4]);
+        let res = parse_ntp_datagram(&buf, buf.len());
+        assert_eq!(res, Err(NtpError::TruncatedMac));
+    }
+
+    #[test]
+    fn test_ntp_parse_tolerant() {
+        let (pkt, warnings) = parse_ntpv4_tolerant(NTP_REQ2B);
+        assert!(warnings.is_empty());
+        let pkt = pkt.expect("parsing failed");
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.auth.map(|m| m.key_id), Some(1));
+        // truncated header
+        let (pkt, warnings) = parse_ntpv4_tolerant(&NTP_REQ2B[..40]);
+        assert!(pkt.is_none());
+        assert_eq!(warnings, [NtpWarning::TruncatedHeader { len: 40 }]);
+        // misaligned extension, followed by a 24-byte MAC (SHA-1)
+        let mut buf = NTP_REQ2[..48].to_vec();
+        buf.extend_from_slice(&[0x00, 0x01, 0x00, 0x06, 0xaa, 0xbb]);
+        buf.extend_from_slice(&[0x00, 0x00, 0x00, 0x07]);
+        buf.extend_from_slice(&[0xcc; 20]);
+        let (pkt, warnings) = parse_ntpv4_tolerant(&buf);
+        assert_eq!(
+            warnings,
+            [
+                NtpWarning::MisalignedExtension { offset: 48 },
+                NtpWarning::UnexpectedMacLength {
+                    offset: 54,
+                    len: 24
+                }
+            ]
+        );
+        let pkt = pkt.expect("parsing failed");
+        assert_eq!(pkt.extensions[0].value, &[0xaa, 0xbb]);
+        assert_eq!(pkt.auth.map(|m| (m.key_id, m.mac.len())), Some((7, 20)));
+        // trailing bytes
+        let (pkt, warnings) = parse_ntpv4_tolerant(&NTP_REQ2[..51]);
+        assert!(pkt.is_some());
+        assert_eq!(warnings, [NtpWarning::TrailingBytes { offset: 48, len: 3 }]);
+    }
+
+    #[test]
+    fn test_ntp_peek() {
+        assert_eq!(peek_version(NTP_REQ2), Some(4));
+        assert_eq!(peek_mode(NTP_REQ2), Some(NtpMode::Client));
+        assert_eq!(peek_stratum(NTPV3_REQ), Some(4));
+        assert_eq!(peek_ref_id(NTPV3_REQ), Some(0x82dc_1818));
+        assert_eq!(peek_ref_id(&NTPV3_REQ[..15]), None);
+        assert_eq!(peek_stratum(&NTPV3_REQ[..1]), None);
+        assert_eq!(peek_version(&[]), None);
+        assert_eq!(classify(NTP_REQ2), Some((NtpVersion::V4, NtpMode::Client)));
+        assert_eq!(classify(NTPV3_REQ), Some((NtpVersion::V3, NtpMode::Client)));
+        assert_eq!(classify(&[]), None);
+    }
+
+    #[test]
+    fn test_ntp_parse_consumed() {
+        let (rem, (_, consumed)) = parse_ntp_consumed(NTPV3_REQ).expect("parsing failed");
+        assert_eq!(consumed, NTPV3_REQ.len());
+        assert!(rem.is_empty());
+        // a control message is followed by the next one
+        let mut buf = vec![0x16, 0x02, 0x00, 0x01, 0, 0, 0, 0, 0, 0, 0, 0];
+        buf.extend_from_slice(&[0xff; 5]);
+        let (rem, (_, consumed)) = parse_ntp_consumed(&buf).expect("parsing failed");
+        assert_eq!(consumed, 12);
+        assert_eq!(rem, &[0xff; 5]);
+    }
 }
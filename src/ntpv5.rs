@@ -0,0 +1,438 @@
+//! NTP version 5 packets (draft, with the `ntpv5` feature)
+//!
+//! The layout follows [draft-ietf-ntp-ntpv5-02]. The draft is not stable: the layout and the
+//! meaning of the fields may change with the next revisions, and so may the types of this
+//! module.
+//!
+//! [draft-ietf-ntp-ntpv5-02]: https://datatracker.ietf.org/doc/html/draft-ietf-ntp-ntpv5-02
+
+use crate::ntp::{parse_extension_list, NtpExtension, NtpExtensions, NtpMode};
+use crate::timestamp::{pow2, NtpTimestamp, Precision};
+use core::fmt;
+use nom::number::streaming::be_u8;
+use nom::IResult;
+use nom_derive::*;
+
+/// Timescale of the timestamps of an NTP version 5 packet
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpTimescale(pub u8);
+
+#[allow(non_upper_case_globals)]
+impl NtpTimescale {
+    pub const Utc: NtpTimescale = NtpTimescale(0);
+    pub const Tai: NtpTimescale = NtpTimescale(1);
+    pub const Ut1: NtpTimescale = NtpTimescale(2);
+    pub const LeapSmearedUtc: NtpTimescale = NtpTimescale(3);
+}
+
+impl fmt::Display for NtpTimescale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match *self {
+            NtpTimescale::Utc => "UTC",
+            NtpTimescale::Tai => "TAI",
+            NtpTimescale::Ut1 => "UT1",
+            NtpTimescale::LeapSmearedUtc => "leap-smeared UTC",
+            _ => return write!(f, "unknown ({})", self.0),
+        };
+        f.write_str(s)
+    }
+}
+
+/// An NTP version 5 packet ([draft-ietf-ntp-ntpv5-02])
+///
+/// The header is 48 bytes long, and is followed by extension fields, which use the format of
+/// version 4 (the length includes the 4-byte header). There is no MAC: authentication is done
+/// with extension fields. The reference identifier and the reference and origin timestamps of
+/// version 4 are replaced by the timescale, era, flags and the server and client cookies.
+///
+/// [draft-ietf-ntp-ntpv5-02]: https://datatracker.ietf.org/doc/html/draft-ietf-ntp-ntpv5-02
+#[derive(Clone, Debug, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpV5Packet<'a> {
+    #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
+    #[nom(Value(b0 >> 6))]
+    pub li: u8,
+    #[nom(Value((b0 >> 3) & 0b111))]
+    pub version: u8,
+    #[nom(Value(NtpMode(b0 & 0b111)))]
+    pub mode: NtpMode,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: Precision,
+    pub timescale: NtpTimescale,
+    /// Era of the timestamps: number of rollovers of the 32-bit seconds since 1900
+    pub era: u8,
+    /// Flags, see the `FLAG_` constants
+    pub flags: u16,
+    /// Root delay, in the 32-bit format of version 5 (4 integer and 28 fraction bits)
+    pub root_delay: u32,
+    /// Root dispersion, in the 32-bit format of version 5 (4 integer and 28 fraction bits)
+    pub root_dispersion: u32,
+    pub server_cookie: u64,
+    pub client_cookie: u64,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
+
+    #[nom(Parse = "parse_v5_extensions")]
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            borrow,
+            serialize_with = "crate::serde_util::serialize_slice",
+            deserialize_with = "crate::serde_util::deserialize_extensions"
+        )
+    )]
+    pub extensions: NtpExtensions<'a>,
+}
+
+/// Identifier of the draft revision implemented by this module
+///
+/// This is also the value of the Draft Identification field sent by implementations of this
+/// revision.
+pub const NTPV5_DRAFT_REVISION: &str = "draft-ietf-ntp-ntpv5-02";
+
+/// Extension field type of the version 5 Padding field
+pub const NTPV5_EXT_PADDING: u16 = 0xf501;
+/// Extension field type of the version 5 MAC field
+pub const NTPV5_EXT_MAC: u16 = 0xf502;
+/// Extension field type of the Reference IDs Request field
+pub const NTPV5_EXT_REFERENCE_IDS_REQUEST: u16 = 0xf503;
+/// Extension field type of the Reference IDs Response field
+pub const NTPV5_EXT_REFERENCE_IDS_RESPONSE: u16 = 0xf504;
+/// Extension field type of the Server Information field
+pub const NTPV5_EXT_SERVER_INFORMATION: u16 = 0xf505;
+/// Extension field type of the Draft Identification field
+pub const NTPV5_EXT_DRAFT_IDENTIFICATION: u16 = 0xf5ff;
+
+/// Decoded content of the capability and negotiation extension fields of version 5
+///
+/// This follows [draft-ietf-ntp-ntpv5-02] (see [`NTPV5_DRAFT_REVISION`]), and is **unstable**:
+/// variants may be added, changed or removed with the next revisions of the draft.
+///
+/// [draft-ietf-ntp-ntpv5-02]: https://datatracker.ietf.org/doc/html/draft-ietf-ntp-ntpv5-02
+#[derive(Clone, Debug, PartialEq)]
+pub enum NtpV5ExtensionContent<'a> {
+    /// Padding, the content is ignored
+    Padding(&'a [u8]),
+    /// Request for a part of the Bloom filter of the reference identifiers of the server
+    ReferenceIdsRequest {
+        /// Offset of the requested part in the filter, in bytes
+        offset: u16,
+        /// Length of the requested part (length of the padding following the offset)
+        length: usize,
+    },
+    /// Part of the Bloom filter of the reference identifiers of the server
+    ReferenceIdsResponse(&'a [u8]),
+    /// Versions supported by the server (in a request, the field is sent empty to be filled)
+    ServerInformation {
+        /// Bit `n` is set if version `n` is supported
+        supported_versions: u16,
+    },
+    /// Revision of the draft implemented by the sender, for ex. [`NTPV5_DRAFT_REVISION`]
+    DraftIdentification(&'a str),
+    /// Other or malformed field, value is not decoded
+    Raw(&'a [u8]),
+}
+
+impl<'a> NtpV5ExtensionContent<'a> {
+    /// Return true if `version` is advertised by a Server Information field
+    pub fn supports_version(&self, version: u8) -> bool {
+        match *self {
+            NtpV5ExtensionContent::ServerInformation { supported_versions } => {
+                version < 16 && supported_versions & (1 << version) != 0
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> NtpExtension<'a> {
+    /// Decode the value of a version 5 extension field, according to its type
+    ///
+    /// See [`NtpV5ExtensionContent`]: this is unstable, as the draft. If the field type is
+    /// unknown, or if the value cannot be decoded, the raw value is returned.
+    pub fn parse_v5_typed(&self) -> NtpV5ExtensionContent<'a> {
+        let value = self.value;
+        match self.field_type {
+            NTPV5_EXT_PADDING => NtpV5ExtensionContent::Padding(value),
+            NTPV5_EXT_REFERENCE_IDS_REQUEST if value.len() >= 2 => {
+                NtpV5ExtensionContent::ReferenceIdsRequest {
+                    offset: u16::from_be_bytes([value[0], value[1]]),
+                    length: value.len() - 2,
+                }
+            }
+            NTPV5_EXT_REFERENCE_IDS_RESPONSE => NtpV5ExtensionContent::ReferenceIdsResponse(value),
+            NTPV5_EXT_SERVER_INFORMATION if value.len() >= 2 => {
+                NtpV5ExtensionContent::ServerInformation {
+                    supported_versions: u16::from_be_bytes([value[0], value[1]]),
+                }
+            }
+            NTPV5_EXT_DRAFT_IDENTIFICATION => {
+                let len = value.iter().rposition(|&b| b != 0).map_or(0, |p| p + 1);
+                match core::str::from_utf8(&value[..len]) {
+                    Ok(draft) if draft.is_ascii() => {
+                        NtpV5ExtensionContent::DraftIdentification(draft)
+                    }
+                    _ => NtpV5ExtensionContent::Raw(value),
+                }
+            }
+            _ => NtpV5ExtensionContent::Raw(value),
+        }
+    }
+}
+
+// Parse the extension fields following the header. Parsing fails if the first field is
+// invalid (for ex. trailing bytes shorter than a field header), otherwise it stops at the
+// first invalid field and the remaining data is returned.
+fn parse_v5_extensions<'a, 'i: 'a>(i: &'i [u8]) -> IResult<&'i [u8], NtpExtensions<'a>> {
+    if i.is_empty() {
+        return Ok((i, NtpExtensions::new()));
+    }
+    parse_extension_list(i)
+}
+
+// Convert a value with 4 integer and 28 fraction bits to seconds
+#[inline]
+fn time32_to_seconds(v: u32) -> f64 {
+    f64::from(v) / f64::from(1u32 << 28)
+}
+
+impl<'a> NtpV5Packet<'a> {
+    /// The leap indicator is not known (the leap indicator field is not meaningful)
+    pub const FLAG_UNKNOWN_LEAP: u16 = 0x0001;
+    /// The packet is sent in interleaved mode
+    pub const FLAG_INTERLEAVED: u16 = 0x0002;
+    /// Authentication negative acknowledgment
+    pub const FLAG_AUTH_NAK: u16 = 0x0004;
+
+    /// Return the length of the packet on the wire, in bytes (header and extension fields)
+    pub fn wire_len(&self) -> usize {
+        let ext_len: usize = self
+            .extensions
+            .iter()
+            .map(|e| 4 + ((e.value.len() + 3) & !3))
+            .sum();
+        48 + ext_len
+    }
+
+    /// Return true if all the bits of `flag` are set
+    #[inline]
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// Return true if the packet is sent in interleaved mode ([`FLAG_INTERLEAVED`])
+    ///
+    /// Unlike versions 3 and 4, where the interleaved mode can only be guessed by comparing
+    /// the timestamps of the request and the response (see
+    /// [`is_interleaved_response`](crate::is_interleaved_response)), version 5 signals it
+    /// explicitly.
+    ///
+    /// [`FLAG_INTERLEAVED`]: Self::FLAG_INTERLEAVED
+    #[inline]
+    pub fn is_interleaved(&self) -> bool {
+        self.has_flag(Self::FLAG_INTERLEAVED)
+    }
+
+    /// Return the decoded capability and negotiation fields of the packet
+    ///
+    /// Padding, MAC and unknown fields are skipped. This is unstable, see
+    /// [`NtpV5ExtensionContent`].
+    pub fn negotiation_fields(&self) -> impl Iterator<Item = NtpV5ExtensionContent<'a>> + '_ {
+        self.extensions
+            .iter()
+            .map(NtpExtension::parse_v5_typed)
+            .filter(|c| {
+                !matches!(
+                    c,
+                    NtpV5ExtensionContent::Padding(_) | NtpV5ExtensionContent::Raw(_)
+                )
+            })
+    }
+
+    /// Return the receive timestamp in seconds since the Unix epoch, using the era of the packet
+    #[inline]
+    pub fn recv_unix_time(&self) -> f64 {
+        self.ts_recv.to_unix_seconds_in_era(u32::from(self.era))
+    }
+
+    /// Return the transmit timestamp in seconds since the Unix epoch, using the era of the
+    /// packet
+    #[inline]
+    pub fn xmit_unix_time(&self) -> f64 {
+        self.ts_xmit.to_unix_seconds_in_era(u32::from(self.era))
+    }
+
+    /// Return the poll interval in seconds
+    #[inline]
+    pub fn poll_interval(&self) -> f64 {
+        pow2(i32::from(self.poll))
+    }
+
+    /// Return the root delay in seconds
+    #[inline]
+    pub fn root_delay_seconds(&self) -> f64 {
+        time32_to_seconds(self.root_delay)
+    }
+
+    /// Return the root dispersion in seconds
+    #[inline]
+    pub fn root_dispersion_seconds(&self) -> f64 {
+        time32_to_seconds(self.root_dispersion)
+    }
+}
+
+/// Parse an NTP version 5 packet ([draft-ietf-ntp-ntpv5-02])
+///
+/// The version field is not checked, see [`parse_ntp`](crate::parse_ntp) to dispatch on the
+/// version.
+///
+/// [draft-ietf-ntp-ntpv5-02]: https://datatracker.ietf.org/doc/html/draft-ietf-ntp-ntpv5-02
+#[inline]
+pub fn parse_ntpv5(i: &[u8]) -> IResult<&[u8], NtpV5Packet<'_>> {
+    NtpV5Packet::parse(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp::{is_interleaved_response, parse_ntp, NtpPacket};
+
+    // client request with one extension field (hand-assembled, the draft does not include
+    // example packets)
+    static NTPV5_REQ: &[u8] = &[
+        0x2b, 0x00, 0x0a, 0xe8, 0x01, 0x00, 0x00, 0x02, 0x18, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+        0x07, 0x08, 0xe9, 0x8a, 0xf8, 0x70, 0x00, 0x00, 0x00, 0x00, 0xe9, 0x8a, 0xf8, 0x71, 0x80,
+        0x00, 0x00, 0x00, 0xf5, 0x01, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_ntpv5_packet_parse() {
+        let (rem, pkt) = parse_ntpv5(NTPV5_REQ).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!((pkt.li, pkt.version, pkt.mode), (0, 5, NtpMode::Client));
+        assert_eq!(
+            (pkt.stratum, pkt.poll, pkt.precision),
+            (0, 10, Precision(-24))
+        );
+        assert_eq!(pkt.timescale, NtpTimescale::Tai);
+        assert_eq!(pkt.era, 0);
+        assert!(pkt.has_flag(NtpV5Packet::FLAG_INTERLEAVED));
+        assert!(pkt.is_interleaved());
+        assert!(!pkt.has_flag(NtpV5Packet::FLAG_AUTH_NAK));
+        assert_eq!(pkt.root_delay_seconds(), 1.5);
+        assert_eq!(pkt.root_dispersion_seconds(), 0.5);
+        assert_eq!(pkt.server_cookie, 0);
+        assert_eq!(pkt.client_cookie, 0x0102_0304_0506_0708);
+        assert_eq!(pkt.ts_recv, NtpTimestamp(0xe98a_f870_0000_0000));
+        assert_eq!(pkt.ts_xmit, NtpTimestamp(0xe98a_f871_8000_0000));
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.extensions[0].field_type, 0xf501);
+        assert_eq!(pkt.extensions[0].value, &[0; 4]);
+        assert_eq!(pkt.wire_len(), NTPV5_REQ.len());
+        assert_eq!(pkt.timescale.to_string(), "TAI");
+        // dispatch on the version
+        let (_, pkt2) = parse_ntp(NTPV5_REQ).expect("parsing failed");
+        // the explicit flag is used, whatever the timestamps
+        assert!(is_interleaved_response(&pkt2, &pkt2));
+        assert_eq!(pkt2, NtpPacket::V5(pkt));
+        // header only
+        let (rem, pkt) = parse_ntpv5(&NTPV5_REQ[..48]).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(pkt.extensions.is_empty());
+        assert!(parse_ntpv5(&NTPV5_REQ[..40]).is_err());
+    }
+
+    #[test]
+    fn test_ntpv5_header_offsets() {
+        // each byte of the header is its offset
+        let buf: Vec<u8> = (0..48).collect();
+        let (rem, pkt) = parse_ntpv5(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!((pkt.li, pkt.version, pkt.mode), (0, 0, NtpMode(0)));
+        assert_eq!((pkt.stratum, pkt.poll, pkt.precision), (1, 2, Precision(3)));
+        assert_eq!((pkt.timescale, pkt.era), (NtpTimescale(4), 5));
+        assert_eq!(pkt.flags, 0x0607);
+        assert_eq!(pkt.root_delay, 0x0809_0a0b);
+        assert_eq!(pkt.root_dispersion, 0x0c0d_0e0f);
+        assert_eq!(pkt.server_cookie, 0x1011_1213_1415_1617);
+        assert_eq!(pkt.client_cookie, 0x1819_1a1b_1c1d_1e1f);
+        assert_eq!(pkt.ts_recv, NtpTimestamp(0x2021_2223_2425_2627));
+        assert_eq!(pkt.ts_xmit, NtpTimestamp(0x2829_2a2b_2c2d_2e2f));
+    }
+
+    #[test]
+    fn test_ntpv5_trailing_junk() {
+        // header followed by 3 bytes, shorter than a field header
+        let buf = [&NTPV5_REQ[..48], &[0xaa, 0xbb, 0xcc]].concat();
+        assert!(parse_ntpv5(&buf).is_err());
+        assert!(parse_ntp(&buf).is_err());
+        // after a valid field, the junk is returned
+        let buf = [NTPV5_REQ, &[0xaa, 0xbb, 0xcc]].concat();
+        let (rem, pkt) = parse_ntpv5(&buf).expect("parsing failed");
+        assert_eq!(rem, &[0xaa, 0xbb, 0xcc]);
+        assert_eq!(pkt.extensions.len(), 1);
+    }
+
+    #[test]
+    fn test_ntpv5_era() {
+        let (_, mut pkt) = parse_ntpv5(NTPV5_REQ).expect("parsing failed");
+        assert_eq!(pkt.recv_unix_time(), 1_709_210_096.0);
+        assert_eq!(pkt.xmit_unix_time(), 1_709_210_097.5);
+        // last second of era 0, and first seconds of era 1 (2036-02-07)
+        pkt.ts_recv = NtpTimestamp(0xffff_ffff_0000_0000);
+        assert_eq!(pkt.recv_unix_time(), 2_085_978_495.0);
+        pkt.era = 1;
+        pkt.ts_xmit = NtpTimestamp(0x0000_0001_8000_0000);
+        assert_eq!(pkt.xmit_unix_time(), 2_085_978_497.5);
+        assert_eq!(pkt.ts_xmit.to_unix_seconds_in_era(0), 1.5 - 2_208_988_800.0);
+    }
+
+    #[test]
+    fn test_ntpv5_negotiation_fields() {
+        let mut buf = NTPV5_REQ.to_vec();
+        // Server Information: versions 4 and 5
+        buf.extend_from_slice(&[0xf5, 0x05, 0x00, 0x08, 0x00, 0x30, 0x00, 0x00]);
+        // Reference IDs Request: 10 bytes at offset 16
+        buf.extend_from_slice(&[0xf5, 0x03, 0x00, 0x10, 0x00, 0x10, 0x00, 0x00]);
+        buf.extend_from_slice(&[0; 8]);
+        // Draft Identification, padded
+        buf.extend_from_slice(&[0xf5, 0xff, 0x00, 0x1b]);
+        buf.extend_from_slice(NTPV5_DRAFT_REVISION.as_bytes());
+        buf.push(0);
+        let (rem, pkt) = parse_ntpv5(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(pkt.extensions.len(), 4);
+        assert_eq!(
+            pkt.extensions[0].parse_v5_typed(),
+            NtpV5ExtensionContent::Padding(&[0; 4])
+        );
+        let fields: Vec<_> = pkt.negotiation_fields().collect();
+        assert_eq!(
+            fields,
+            vec![
+                NtpV5ExtensionContent::ServerInformation {
+                    supported_versions: 0x30
+                },
+                NtpV5ExtensionContent::ReferenceIdsRequest {
+                    offset: 16,
+                    length: 10
+                },
+                NtpV5ExtensionContent::DraftIdentification(NTPV5_DRAFT_REVISION),
+            ]
+        );
+        assert!(fields[0].supports_version(5));
+        assert!(fields[0].supports_version(4));
+        assert!(!fields[0].supports_version(3));
+        assert!(!fields[1].supports_version(5));
+        // truncated Server Information
+        let ext = NtpExtension {
+            field_type: NTPV5_EXT_SERVER_INFORMATION,
+            length: 5,
+            value: &[0x30],
+        };
+        assert_eq!(ext.parse_v5_typed(), NtpV5ExtensionContent::Raw(&[0x30]));
+    }
+}
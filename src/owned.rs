@@ -0,0 +1,762 @@
+//! Owned versions of the NTP packets
+//!
+//! The parsed packets borrow their variable-length fields (extension values, MAC) from the
+//! input. The types of this module copy all these fields, so they are not tied to the lifetime
+//! of the input and can be stored or sent to other threads.
+//!
+//! With the `serde` feature, the owned types can be serialized. To persist packets (for ex. in
+//! a cache on disk), wrap them in a [`NtpPacketRecord`], which stores a format version:
+//!
+//! - the serialized representation of a given format version will not change: changes of the
+//!   packet structures (adding, removing or changing the type of a field) increment
+//!   [`NTP_PACKET_RECORD_VERSION`], and [`NtpPacketRecord::into_packet`] rejects records with
+//!   an unsupported version. Formats which are not self-describing (for ex. bincode) cannot
+//!   read a record written with other fields
+//! - with self-describing formats (for ex. JSON), missing fields of the packet structures are
+//!   read as their default value, so records with fewer fields can still be decoded
+//! - new variants of [`NtpPacketOwned`] may be added (for ex. `Control` and `V5`) without
+//!   changing the format version: older records can still be read, but records of a new
+//!   variant cannot be read by older versions of this crate
+//! - formats which encode variants by index (for ex. bincode) depend on the `ntpv5` feature:
+//!   the `V5` variant only exists with this feature, so records must be read with the same
+//!   features as they were written
+//!
+//! The borrowed packet types can also be serialized, with the same representation as the
+//! owned types. They can be deserialized, borrowing the byte fields from the input, but only
+//! from formats able to borrow byte strings (for ex. bincode). In JSON, the bytes are arrays of
+//! integers, so a serialized packet is read back as the owned type.
+
+use crate::control::{NtpControlMessage, NtpControlOpcode};
+use crate::extension::{
+    split_nts_authenticator, NTP_EXT_NTS_AUTHENTICATOR, NTP_EXT_NTS_COOKIE,
+    NTP_EXT_UNIQUE_IDENTIFIER,
+};
+use crate::ntp::*;
+#[cfg(feature = "ntpv5")]
+use crate::ntpv5::{NtpTimescale, NtpV5Packet};
+use crate::timestamp::{NtpShortFormat, NtpTimestamp, Precision};
+use alloc::vec::Vec;
+
+/// An owned NTP extension field
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpExtensionOwned {
+    pub field_type: u16,
+    pub length: u16,
+    pub value: Vec<u8>,
+}
+
+/// Owned, typed content of an NTP extension field
+///
+/// The NTS fields ([RFC8915]) are decoded, other types are kept as raw values.
+///
+/// [RFC8915]: https://tools.ietf.org/html/rfc8915
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NtpExtensionContentOwned {
+    /// Unique Identifier
+    UniqueId(Vec<u8>),
+    /// NTS Cookie
+    Cookie(Vec<u8>),
+    /// NTS Authenticator and Encrypted Extension Fields, without padding
+    Authenticator { nonce: Vec<u8>, ciphertext: Vec<u8> },
+    /// Other or malformed field, value is not decoded
+    Unknown { field_type: u16, value: Vec<u8> },
+}
+
+/// An owned NTP MAC
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpMacOwned {
+    pub key_id: u32,
+    pub mac: Vec<u8>,
+}
+
+/// An owned NTP version 3 packet
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpV3PacketOwned {
+    pub li: u8,
+    pub version: u8,
+    pub mode: NtpMode,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: Precision,
+    pub root_delay: NtpShortFormat,
+    pub root_dispersion: NtpShortFormat,
+    pub ref_id: u32,
+    pub ts_ref: NtpTimestamp,
+    pub ts_orig: NtpTimestamp,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
+    pub authenticator: Option<Vec<u8>>,
+}
+
+/// An owned NTP version 4 packet
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpV4PacketOwned {
+    pub li: u8,
+    pub version: u8,
+    pub mode: NtpMode,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: Precision,
+    pub root_delay: NtpShortFormat,
+    pub root_dispersion: NtpShortFormat,
+    pub ref_id: u32,
+    pub ts_ref: NtpTimestamp,
+    pub ts_orig: NtpTimestamp,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
+    pub extensions: Vec<NtpExtensionOwned>,
+    pub auth: Option<NtpMacOwned>,
+}
+
+/// An owned NTP version 5 packet (draft, with the `ntpv5` feature)
+#[cfg(feature = "ntpv5")]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpV5PacketOwned {
+    pub li: u8,
+    pub version: u8,
+    pub mode: NtpMode,
+    pub stratum: u8,
+    pub poll: i8,
+    pub precision: Precision,
+    pub timescale: NtpTimescale,
+    pub era: u8,
+    pub flags: u16,
+    pub root_delay: u32,
+    pub root_dispersion: u32,
+    pub server_cookie: u64,
+    pub client_cookie: u64,
+    pub ts_recv: NtpTimestamp,
+    pub ts_xmit: NtpTimestamp,
+    pub extensions: Vec<NtpExtensionOwned>,
+}
+
+/// An owned NTP control message (mode 6)
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct NtpControlMessageOwned {
+    pub li: u8,
+    pub version: u8,
+    pub mode: NtpMode,
+    pub response: bool,
+    pub error: bool,
+    pub more: bool,
+    pub opcode: NtpControlOpcode,
+    pub sequence: u16,
+    pub status: u16,
+    pub association_id: u16,
+    pub offset: u16,
+    pub count: u16,
+    pub data: Vec<u8>,
+    pub padding: Vec<u8>,
+    pub auth: Option<NtpMacOwned>,
+}
+
+/// An owned NTP packet, version 3 or 4, or control message
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NtpPacketOwned {
+    V3(NtpV3PacketOwned),
+    V4(NtpV4PacketOwned),
+    Control(NtpControlMessageOwned),
+    #[cfg(feature = "ntpv5")]
+    V5(NtpV5PacketOwned),
+}
+
+/// Format version of the serialized owned packets
+pub const NTP_PACKET_RECORD_VERSION: u16 = 1;
+
+/// An owned NTP packet, tagged with the version of the serialization format
+///
+/// See the [module documentation](self) for the stability guarantees.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpPacketRecord {
+    pub format_version: u16,
+    pub packet: NtpPacketOwned,
+}
+
+impl NtpPacketRecord {
+    /// Wrap a packet, using the current format version
+    pub fn new(packet: NtpPacketOwned) -> NtpPacketRecord {
+        NtpPacketRecord {
+            format_version: NTP_PACKET_RECORD_VERSION,
+            packet,
+        }
+    }
+
+    /// Return the packet, or `None` if the format version is not supported
+    pub fn into_packet(self) -> Option<NtpPacketOwned> {
+        if self.format_version == NTP_PACKET_RECORD_VERSION {
+            Some(self.packet)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> NtpExtension<'a> {
+    /// Convert to an owned extension, copying the value
+    pub fn into_owned(self) -> NtpExtensionOwned {
+        NtpExtensionOwned {
+            field_type: self.field_type,
+            length: self.length,
+            value: self.value.to_vec(),
+        }
+    }
+}
+
+impl NtpExtensionOwned {
+    /// Return a borrowed view of this extension
+    pub fn as_extension(&self) -> NtpExtension<'_> {
+        NtpExtension {
+            field_type: self.field_type,
+            length: self.length,
+            value: &self.value,
+        }
+    }
+
+    /// Decode the value of the extension field, according to its type
+    pub fn content(&self) -> NtpExtensionContentOwned {
+        NtpExtensionContentOwned::from(self.as_extension())
+    }
+}
+
+impl<'a> From<NtpExtension<'a>> for NtpExtensionContentOwned {
+    fn from(ext: NtpExtension<'a>) -> Self {
+        match ext.field_type {
+            NTP_EXT_UNIQUE_IDENTIFIER => NtpExtensionContentOwned::UniqueId(ext.value.to_vec()),
+            NTP_EXT_NTS_COOKIE => NtpExtensionContentOwned::Cookie(ext.value.to_vec()),
+            NTP_EXT_NTS_AUTHENTICATOR => match split_nts_authenticator(ext.value) {
+                Some((nonce, ciphertext)) => NtpExtensionContentOwned::Authenticator {
+                    nonce: nonce.to_vec(),
+                    ciphertext: ciphertext.to_vec(),
+                },
+                None => NtpExtensionContentOwned::Unknown {
+                    field_type: ext.field_type,
+                    value: ext.value.to_vec(),
+                },
+            },
+            _ => NtpExtensionContentOwned::Unknown {
+                field_type: ext.field_type,
+                value: ext.value.to_vec(),
+            },
+        }
+    }
+}
+
+impl<'a> From<NtpExtension<'a>> for NtpExtensionOwned {
+    fn from(ext: NtpExtension<'a>) -> Self {
+        ext.into_owned()
+    }
+}
+
+impl<'a> NtpMac<'a> {
+    /// Convert to an owned MAC, copying the digest
+    pub fn into_owned(self) -> NtpMacOwned {
+        NtpMacOwned {
+            key_id: self.key_id,
+            mac: self.mac.to_vec(),
+        }
+    }
+}
+
+impl NtpMacOwned {
+    /// Return a borrowed view of this MAC
+    pub fn as_mac(&self) -> NtpMac<'_> {
+        NtpMac {
+            key_id: self.key_id,
+            mac: &self.mac,
+        }
+    }
+}
+
+impl<'a> NtpV3Packet<'a> {
+    /// Convert to an owned packet, copying all borrowed data
+    pub fn into_owned(self) -> NtpV3PacketOwned {
+        NtpV3PacketOwned {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            ref_id: self.ref_id,
+            ts_ref: self.ts_ref,
+            ts_orig: self.ts_orig,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            authenticator: self.authenticator.map(<[u8]>::to_vec),
+        }
+    }
+}
+
+impl NtpV3PacketOwned {
+    /// Return a borrowed view of this packet
+    ///
+    /// This gives access to all the methods of the borrowed packet type.
+    pub fn as_packet(&self) -> NtpV3Packet<'_> {
+        NtpV3Packet {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            ref_id: self.ref_id,
+            ts_ref: self.ts_ref,
+            ts_orig: self.ts_orig,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            authenticator: self.authenticator.as_deref(),
+        }
+    }
+
+    /// Clear sensitive data from the packet, for ex. before sharing a capture
+    ///
+    /// All the bytes of the authenticator (key identifier and checksum) are set to 0, so the
+    /// serialized packet keeps its length and layout. If `clear_origin` is true, the origin
+    /// timestamp (which can be used to identify clients) is also set to 0. All other fields
+    /// are kept.
+    pub fn sanitize(&mut self, clear_origin: bool) {
+        if let Some(auth) = &mut self.authenticator {
+            auth.iter_mut().for_each(|b| *b = 0);
+        }
+        if clear_origin {
+            self.ts_orig = NtpTimestamp(0);
+        }
+    }
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Convert to an owned packet, copying all borrowed data (extension values and MAC)
+    pub fn into_owned(self) -> NtpV4PacketOwned {
+        NtpV4PacketOwned {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            ref_id: self.ref_id,
+            ts_ref: self.ts_ref,
+            ts_orig: self.ts_orig,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            extensions: self
+                .extensions
+                .into_iter()
+                .map(NtpExtension::into_owned)
+                .collect(),
+            auth: self.auth.map(NtpMac::into_owned),
+        }
+    }
+}
+
+impl NtpV4PacketOwned {
+    /// Return a borrowed view of this packet
+    ///
+    /// This gives access to all the methods of the borrowed packet type.
+    pub fn as_packet(&self) -> NtpV4Packet<'_> {
+        NtpV4Packet {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            ref_id: self.ref_id,
+            ts_ref: self.ts_ref,
+            ts_orig: self.ts_orig,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            extensions: self
+                .extensions
+                .iter()
+                .map(NtpExtensionOwned::as_extension)
+                .collect(),
+            header_bytes: HeaderBytes::default(),
+            auth: self.auth.as_ref().map(NtpMacOwned::as_mac),
+            auth_bytes: HeaderBytes::default(),
+        }
+    }
+
+    /// Clear sensitive data from the packet, for ex. before sharing a capture
+    ///
+    /// The key identifier and all the bytes of the digest of the MAC are set to 0, so the
+    /// serialized packet keeps its length and layout. If `clear_origin` is true, the origin
+    /// timestamp (which can be used to identify clients) is also set to 0. All other fields,
+    /// including extensions, are kept.
+    pub fn sanitize(&mut self, clear_origin: bool) {
+        if let Some(auth) = &mut self.auth {
+            auth.key_id = 0;
+            auth.mac.iter_mut().for_each(|b| *b = 0);
+        }
+        if clear_origin {
+            self.ts_orig = NtpTimestamp(0);
+        }
+    }
+}
+
+impl<'a> NtpControlMessage<'a> {
+    /// Convert to an owned message, copying all borrowed data
+    pub fn into_owned(self) -> NtpControlMessageOwned {
+        NtpControlMessageOwned {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            response: self.response,
+            error: self.error,
+            more: self.more,
+            opcode: self.opcode,
+            sequence: self.sequence,
+            status: self.status,
+            association_id: self.association_id,
+            offset: self.offset,
+            count: self.count,
+            data: self.data.to_vec(),
+            padding: self.padding.to_vec(),
+            auth: self.auth.map(NtpMac::into_owned),
+        }
+    }
+}
+
+impl NtpControlMessageOwned {
+    /// Return a borrowed view of this message
+    pub fn as_message(&self) -> NtpControlMessage<'_> {
+        NtpControlMessage {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            response: self.response,
+            error: self.error,
+            more: self.more,
+            opcode: self.opcode,
+            sequence: self.sequence,
+            status: self.status,
+            association_id: self.association_id,
+            offset: self.offset,
+            count: self.count,
+            data: &self.data,
+            padding: &self.padding,
+            auth: self.auth.as_ref().map(NtpMacOwned::as_mac),
+        }
+    }
+}
+
+#[cfg(feature = "ntpv5")]
+impl<'a> NtpV5Packet<'a> {
+    /// Convert to an owned packet, copying all borrowed data (extension values)
+    pub fn into_owned(self) -> NtpV5PacketOwned {
+        NtpV5PacketOwned {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            timescale: self.timescale,
+            era: self.era,
+            flags: self.flags,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            server_cookie: self.server_cookie,
+            client_cookie: self.client_cookie,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            extensions: self
+                .extensions
+                .into_iter()
+                .map(NtpExtension::into_owned)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(feature = "ntpv5")]
+impl NtpV5PacketOwned {
+    /// Return a borrowed view of this packet
+    ///
+    /// This gives access to all the methods of the borrowed packet type.
+    pub fn as_packet(&self) -> NtpV5Packet<'_> {
+        NtpV5Packet {
+            li: self.li,
+            version: self.version,
+            mode: self.mode,
+            stratum: self.stratum,
+            poll: self.poll,
+            precision: self.precision,
+            timescale: self.timescale,
+            era: self.era,
+            flags: self.flags,
+            root_delay: self.root_delay,
+            root_dispersion: self.root_dispersion,
+            server_cookie: self.server_cookie,
+            client_cookie: self.client_cookie,
+            ts_recv: self.ts_recv,
+            ts_xmit: self.ts_xmit,
+            extensions: self
+                .extensions
+                .iter()
+                .map(NtpExtensionOwned::as_extension)
+                .collect(),
+        }
+    }
+}
+
+impl<'a> NtpPacket<'a> {
+    /// Convert to an owned packet, copying all borrowed data
+    pub fn into_owned(self) -> NtpPacketOwned {
+        match self {
+            NtpPacket::V3(pkt) => NtpPacketOwned::V3(pkt.into_owned()),
+            NtpPacket::V4(pkt) => NtpPacketOwned::V4(pkt.into_owned()),
+            NtpPacket::Control(msg) => NtpPacketOwned::Control(msg.into_owned()),
+            #[cfg(feature = "ntpv5")]
+            NtpPacket::V5(pkt) => NtpPacketOwned::V5(pkt.into_owned()),
+        }
+    }
+}
+
+impl NtpPacketOwned {
+    /// Return a borrowed view of this packet
+    pub fn as_packet(&self) -> NtpPacket<'_> {
+        match self {
+            NtpPacketOwned::V3(pkt) => NtpPacket::V3(pkt.as_packet()),
+            NtpPacketOwned::V4(pkt) => NtpPacket::V4(pkt.as_packet()),
+            NtpPacketOwned::Control(msg) => NtpPacket::Control(msg.as_message()),
+            #[cfg(feature = "ntpv5")]
+            NtpPacketOwned::V5(pkt) => NtpPacket::V5(pkt.as_packet()),
+        }
+    }
+}
+
+impl<'a> From<NtpPacket<'a>> for NtpPacketOwned {
+    fn from(pkt: NtpPacket<'a>) -> Self {
+        pkt.into_owned()
+    }
+}
+
+impl<'a> From<NtpV3Packet<'a>> for NtpV3PacketOwned {
+    fn from(pkt: NtpV3Packet<'a>) -> Self {
+        pkt.into_owned()
+    }
+}
+
+impl<'a> From<NtpV4Packet<'a>> for NtpV4PacketOwned {
+    fn from(pkt: NtpV4Packet<'a>) -> Self {
+        pkt.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_owned_packet_outlives_input() {
+        let buf = vec![
+            0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x25,
+            0xcc, 0x13, 0x2b, 0x02, 0x10, 0x00, 0x00, 0x02, 0x00, 0x06, 0xab, 0xcd, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x01, 0x52, 0x80, 0x0c, 0x2b, 0x59, 0x00, 0x64, 0x66, 0x84, 0xf4,
+            0x4c, 0xa4, 0xee, 0xce, 0x12, 0xb8,
+        ];
+        let owned = {
+            let (_, pkt) = parse_ntp(&buf).expect("parsing failed");
+            pkt.into_owned()
+        };
+        let expected_mac = buf[60..].to_vec();
+        drop(buf);
+        let pkt = match &owned {
+            NtpPacketOwned::V4(pkt) => pkt,
+            _ => panic!("unexpected version"),
+        };
+        assert_eq!(pkt.extensions.len(), 1);
+        assert_eq!(pkt.extensions[0].field_type, 2);
+        assert_eq!(pkt.extensions[0].value, vec![0xab, 0xcd]);
+        let auth = pkt.auth.as_ref().expect("missing MAC");
+        assert_eq!(auth.key_id, 1);
+        assert_eq!(auth.mac, expected_mac);
+        // converting back to a borrowed packet gives the same values
+        let view = pkt.as_packet();
+        assert_eq!(view.clone().into_owned(), *pkt);
+        assert_eq!(view.auth.map(|a| a.mac), Some(&expected_mac[..]));
+    }
+
+    #[test]
+    fn test_owned_packet_sanitize() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        buf[24..32].copy_from_slice(&[0, 0, 0, 0, 0, 0, 0x04, 0xd2]);
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0x55; 16]);
+        let (_, pkt) = parse_ntpv4(&buf).expect("parsing failed");
+        let mut pkt = pkt.into_owned();
+        let mut pkt2 = pkt.clone();
+        pkt.sanitize(false);
+        let auth = pkt.auth.as_ref().expect("missing MAC");
+        assert_eq!((auth.key_id, &auth.mac[..]), (0, &[0; 16][..]));
+        assert_eq!(pkt.ts_orig, NtpTimestamp(1234));
+        let bytes = pkt.as_packet().to_bytes();
+        assert_eq!(bytes.len(), buf.len());
+        assert_eq!(&bytes[..48], &buf[..48]);
+        pkt2.sanitize(true);
+        assert_eq!(pkt2.ts_orig, NtpTimestamp(0));
+        assert_eq!(pkt2.ts_xmit, pkt.ts_xmit);
+        // version 3
+        let mut v3 = buf[..60].to_vec();
+        v3[0] = 0x1b;
+        let (_, pkt) = parse_ntpv3(&v3).expect("parsing failed");
+        let mut pkt = pkt.into_owned();
+        pkt.sanitize(true);
+        assert_eq!(pkt.authenticator, Some(vec![0; 12]));
+        assert_eq!(pkt.as_packet().to_bytes().len(), 60);
+    }
+
+    #[test]
+    fn test_owned_extension_content() {
+        let ext = NtpExtension {
+            field_type: NTP_EXT_NTS_AUTHENTICATOR,
+            length: 16,
+            value: &[0, 3, 0, 4, 1, 2, 3, 0, 4, 5, 6, 7],
+        };
+        let owned = NtpExtensionOwned::from(ext.clone());
+        assert_eq!(
+            owned.content(),
+            NtpExtensionContentOwned::Authenticator {
+                nonce: vec![1, 2, 3],
+                ciphertext: vec![4, 5, 6, 7],
+            }
+        );
+        // ciphertext length exceeds the value
+        let ext = NtpExtension {
+            value: &[0, 3, 0, 8, 1, 2, 3, 0, 4, 5, 6, 7],
+            ..ext
+        };
+        assert!(matches!(
+            NtpExtensionContentOwned::from(ext),
+            NtpExtensionContentOwned::Unknown {
+                field_type: NTP_EXT_NTS_AUTHENTICATOR,
+                ..
+            }
+        ));
+        let ext = NtpExtension {
+            field_type: NTP_EXT_UNIQUE_IDENTIFIER,
+            length: 8,
+            value: &[0xab; 4],
+        };
+        assert_eq!(
+            NtpExtensionContentOwned::from(ext),
+            NtpExtensionContentOwned::UniqueId(vec![0xab; 4])
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_owned_packet_serde() {
+        use crate::timestamp::NtpTimestamp;
+
+        let pkt = NtpV4Packet::server_reply(2, 0x7f00_0001, NtpTimestamp(1), NtpTimestamp(2));
+        let record = NtpPacketRecord::new(NtpPacket::V4(pkt).into_owned());
+        let json = serde_json::to_string(&record).expect("serialization failed");
+        let decoded: NtpPacketRecord = serde_json::from_str(&json).expect("deserialization failed");
+        assert_eq!(decoded, record);
+        let mut future = decoded;
+        future.format_version += 1;
+        assert!(future.into_packet().is_none());
+        assert_eq!(record.clone().into_packet(), Some(record.packet));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_owned_packet_serde_missing_field() {
+        // record written without the `extensions` and `auth` fields
+        let json = r#"{"format_version":1,"packet":{"V4":{"li":0,"version":4,"mode":4,
+            "stratum":2,"poll":6,"precision":-20,"root_delay":0,"root_dispersion":0,
+            "ref_id":2130706433,"ts_ref":0,"ts_orig":1,"ts_recv":2,"ts_xmit":2}}}"#;
+        let record: NtpPacketRecord = serde_json::from_str(json).expect("deserialization failed");
+        let pkt = match record.into_packet() {
+            Some(NtpPacketOwned::V4(pkt)) => pkt,
+            other => panic!("unexpected packet {:?}", other),
+        };
+        assert_eq!(pkt.mode, NtpMode::Server);
+        assert_eq!(pkt.ts_xmit, NtpTimestamp(2));
+        assert!(pkt.extensions.is_empty());
+        assert!(pkt.auth.is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_owned_packet_serde_old_record() {
+        // record written before the `Control` and `V5` variants were added
+        let json = r#"{"format_version":1,"packet":{"V3":{"li":0,"version":3,"mode":3,
+            "stratum":0,"poll":6,"precision":-20,"root_delay":65536,"root_dispersion":65536,
+            "ref_id":0,"ts_ref":0,"ts_orig":0,"ts_recv":0,"ts_xmit":16817982455184097280,
+            "authenticator":[0,0,0,1,222,173,190,239,1,2,3,4]}}}"#;
+        let record: NtpPacketRecord = serde_json::from_str(json).expect("deserialization failed");
+        let pkt = match record.into_packet() {
+            Some(NtpPacketOwned::V3(pkt)) => pkt,
+            other => panic!("unexpected packet {:?}", other),
+        };
+        assert_eq!(pkt.mode, NtpMode::Client);
+        assert_eq!(pkt.root_delay, NtpShortFormat(0x1_0000));
+        assert_eq!(pkt.ts_xmit, NtpTimestamp(0xe965_79f0_0000_0000));
+        assert_eq!(pkt.authenticator.map(|a| a.len()), Some(12));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_borrowed_packet_serde() {
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        buf.extend_from_slice(&[0x01, 0x04, 0x00, 0x08, 0xaa, 0xbb, 0xcc, 0xdd]);
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0x55; 16]);
+        let (_, pkt) = parse_ntp(&buf).expect("parsing failed");
+        let json = serde_json::to_string(&pkt).expect("serialization failed");
+        // the mode is serialized as its value, and slices as arrays of bytes
+        assert!(json.contains("\"mode\":3,"));
+        assert!(json.contains("\"value\":[170,187,204,221]"));
+        let decoded: NtpPacketOwned = serde_json::from_str(&json).expect("deserialization failed");
+        assert_eq!(decoded, pkt.into_owned());
+        let mut v3 = buf[..60].to_vec();
+        v3[0] = 0x1b;
+        let (_, pkt) = parse_ntp(&v3).expect("parsing failed");
+        let json = serde_json::to_string(&pkt).expect("serialization failed");
+        let decoded: NtpPacketOwned = serde_json::from_str(&json).expect("deserialization failed");
+        assert_eq!(decoded, pkt.into_owned());
+        // arrays of integers cannot be borrowed
+        assert!(serde_json::from_str::<NtpPacket>(&json).is_err());
+        // JSON strings without escapes can
+        let json = r#"{"V3":{"li":0,"version":3,"mode":3,"stratum":0,"poll":6,"precision":-20,
+            "root_delay":0,"root_dispersion":0,"ref_id":0,"ts_ref":0,"ts_orig":0,"ts_recv":0,
+            "ts_xmit":1,"authenticator":"0123456789ab"}}"#;
+        let pkt: NtpPacket = serde_json::from_str(json).expect("deserialization failed");
+        let auth = match pkt {
+            NtpPacket::V3(pkt) => pkt.authenticator.expect("missing authenticator"),
+            _ => panic!("unexpected packet type"),
+        };
+        assert_eq!(auth, b"0123456789ab");
+        assert!(json.as_bytes().as_ptr_range().contains(&auth.as_ptr()));
+    }
+}
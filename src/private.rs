@@ -0,0 +1,140 @@
+//! NTP private messages (mode 7)
+//!
+//! Mode 7 is not standardized: it is the format used by the reference implementation (ntpd)
+//! for the `ntpdc` management tool. Only the header is decoded, the data items are returned
+//! as raw bytes.
+
+use crate::ntp::NtpMode;
+use nom::bytes::streaming::take;
+use nom::number::streaming::{be_u16, be_u8};
+use nom::IResult;
+use nom_derive::*;
+
+/// Error code of a mode 7 response
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mode7Error {
+    /// The implementation number is not supported (`INFO_ERR_IMPL`)
+    ImplementationMismatch,
+    /// The request code is not implemented (`INFO_ERR_REQ`)
+    UnknownRequest,
+    /// The request has a format error (`INFO_ERR_FMT`)
+    FormatError,
+    /// No data is available (`INFO_ERR_NODATA`)
+    NoData,
+    /// Authentication failure (`INFO_ERR_AUTH`)
+    AuthenticationFailure,
+    /// Unknown error code
+    Unknown(u8),
+}
+
+impl Mode7Error {
+    /// Decode an error code, returning `None` if the code means "no error"
+    pub fn from_code(code: u8) -> Option<Mode7Error> {
+        match code {
+            0 => None,
+            1 => Some(Mode7Error::ImplementationMismatch),
+            2 => Some(Mode7Error::UnknownRequest),
+            3 => Some(Mode7Error::FormatError),
+            4 => Some(Mode7Error::NoData),
+            7 => Some(Mode7Error::AuthenticationFailure),
+            n => Some(Mode7Error::Unknown(n)),
+        }
+    }
+}
+
+/// An NTP private message (mode 7)
+#[derive(Clone, Debug, PartialEq, NomBE)]
+pub struct NtpPrivateMessage<'a> {
+    /// Response bit: set in responses, clear in requests
+    #[nom(PreExec = "let (i, b0) = be_u8(i)?;")]
+    #[nom(Value(b0 & 0x80 != 0))]
+    pub response: bool,
+    /// More bit: set if more response packets follow
+    #[nom(Value(b0 & 0x40 != 0))]
+    pub more: bool,
+    #[nom(Value((b0 >> 3) & 0b111))]
+    pub version: u8,
+    #[nom(Value(NtpMode(b0 & 0b111)))]
+    pub mode: NtpMode,
+    /// Authenticated bit: set if the request is authenticated
+    #[nom(PreExec = "let (i, b1) = be_u8(i)?;")]
+    #[nom(Value(b1 & 0x80 != 0))]
+    pub authenticated: bool,
+    #[nom(Value(b1 & 0x7f))]
+    pub sequence: u8,
+    pub implementation: u8,
+    pub request_code: u8,
+    /// Error code (4 bits), see [`Mode7Error`]
+    #[nom(PreExec = "let (i, err_nitems) = be_u16(i)?;")]
+    #[nom(Value((err_nitems >> 12) as u8))]
+    pub err: u8,
+    /// Number of data items
+    #[nom(Value(err_nitems & 0xfff))]
+    pub nitems: u16,
+    /// Must be zero (4 bits)
+    #[nom(PreExec = "let (i, mbz_itemsize) = be_u16(i)?;")]
+    #[nom(Value((mbz_itemsize >> 12) as u8))]
+    pub mbz: u8,
+    /// Size of each data item, in bytes
+    #[nom(Value(mbz_itemsize & 0xfff))]
+    pub item_size: u16,
+    /// Data items (`nitems * item_size` bytes)
+    #[nom(Parse = "take(usize::from(nitems) * usize::from(item_size))")]
+    pub data: &'a [u8],
+}
+
+impl<'a> NtpPrivateMessage<'a> {
+    /// Return the decoded error of a response, or `None` if there is no error
+    pub fn error(&self) -> Option<Mode7Error> {
+        Mode7Error::from_code(self.err)
+    }
+}
+
+/// Parse an NTP private message (mode 7)
+///
+/// Only the header and the data items are parsed, and the remaining bytes (for ex. the
+/// authenticator of a request) are returned.
+#[inline]
+pub fn parse_ntp_private(i: &[u8]) -> IResult<&[u8], NtpPrivateMessage<'_>> {
+    NtpPrivateMessage::parse(i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ntp_private_error() {
+        // response to a MON_GETLIST_1 request (42): no data
+        let bytes = &[0x97, 0x00, 0x03, 0x2a, 0x40, 0x00, 0x00, 0x48];
+        let (rem, msg) = parse_ntp_private(bytes).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert!(msg.response);
+        assert!(!msg.more);
+        assert_eq!(msg.version, 2);
+        assert_eq!(msg.mode, NtpMode::Private);
+        assert_eq!(msg.implementation, 3);
+        assert_eq!(msg.request_code, 42);
+        assert_eq!(msg.nitems, 0);
+        assert_eq!(msg.item_size, 72);
+        assert_eq!(msg.error(), Some(Mode7Error::NoData));
+    }
+
+    #[test]
+    fn test_ntp_private_data() {
+        // response with 2 items of 4 bytes
+        let bytes = &[
+            0x97, 0x01, 0x03, 0x2a, 0x00, 0x02, 0x00, 0x04, 1, 2, 3, 4, 5, 6, 7, 8, 0xff,
+        ];
+        let (rem, msg) = parse_ntp_private(bytes).expect("parsing failed");
+        assert_eq!(rem, &[0xff]);
+        assert_eq!(msg.sequence, 1);
+        assert_eq!(msg.error(), None);
+        assert_eq!(msg.data, &bytes[8..16]);
+        assert_eq!(
+            Mode7Error::from_code(1),
+            Some(Mode7Error::ImplementationMismatch)
+        );
+        assert_eq!(Mode7Error::from_code(9), Some(Mode7Error::Unknown(9)));
+    }
+}
@@ -0,0 +1,101 @@
+//! Integration with protocol detection engines
+
+use crate::error::NtpError;
+use crate::ntp::{looks_like_ntp, parse_ntp_complete, parse_ntp_complete_e, NtpPacket};
+use nom::error::ErrorKind;
+
+/// Result of probing data for a protocol
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ProbeResult {
+    /// The data does not belong to this protocol
+    NotForUs,
+    /// The data may belong to this protocol, but is not a complete valid message
+    Unsure,
+    /// The data is a complete valid message of this protocol
+    Certain,
+}
+
+/// A protocol parser, which can be registered in a generic protocol detection engine
+///
+/// The lifetime `'a` is the lifetime of the input, so that parsed messages can borrow from it.
+/// Engines can require `for<'a> ProtocolParser<'a>` to accept any input.
+pub trait ProtocolParser<'a> {
+    /// Type of the parsed messages
+    type Message;
+    /// Type of the parsing errors
+    type Error;
+
+    /// Return the name of the protocol
+    fn name(&self) -> &'static str;
+
+    /// Check if `i` belongs to this protocol
+    fn probe(&self, i: &[u8]) -> ProbeResult;
+
+    /// Parse `i` as a single message
+    fn parse(&self, i: &'a [u8]) -> Result<Self::Message, Self::Error>;
+}
+
+/// Parser of NTP packets (versions 3 and 4), for protocol detection engines
+///
+/// The input is expected to be the payload of a UDP datagram: it must contain exactly one
+/// packet.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NtpProtocolParser;
+
+impl<'a> ProtocolParser<'a> for NtpProtocolParser {
+    type Message = NtpPacket<'a>;
+    type Error = NtpError;
+
+    fn name(&self) -> &'static str {
+        "ntp"
+    }
+
+    /// Returns `NotForUs` if the data fails [`looks_like_ntp`], `Certain` if it can be parsed
+    /// as a packet without trailing data, and `Unsure` otherwise.
+    fn probe(&self, i: &[u8]) -> ProbeResult {
+        if !looks_like_ntp(i) {
+            return ProbeResult::NotForUs;
+        }
+        match parse_ntp_complete(i) {
+            Ok(([], _)) => ProbeResult::Certain,
+            _ => ProbeResult::Unsure,
+        }
+    }
+
+    /// Trailing data returns `NtpError::Nom(ErrorKind::Eof)`.
+    fn parse(&self, i: &'a [u8]) -> Result<NtpPacket<'a>, NtpError> {
+        match parse_ntp_complete_e::<NtpError>(i) {
+            Ok((rem, _)) if !rem.is_empty() => Err(NtpError::Nom(ErrorKind::Eof)),
+            Ok((_, pkt)) => Ok(pkt),
+            Err(e) => Err(NtpError::from(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect<P: for<'a> ProtocolParser<'a>>(parser: &P, i: &[u8]) -> Option<&'static str> {
+        match parser.probe(i) {
+            ProbeResult::Certain => Some(parser.name()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_ntp_protocol_parser() {
+        let parser = NtpProtocolParser;
+        let mut buf = vec![0x23];
+        buf.resize(48, 0);
+        assert_eq!(detect(&parser, &buf), Some("ntp"));
+        assert!(parser.parse(&buf).is_ok());
+        // trailing data, too short to be a MAC
+        let mut trailing = buf.clone();
+        trailing.extend_from_slice(&[0; 3]);
+        assert_eq!(parser.probe(&trailing), ProbeResult::Unsure);
+        assert_eq!(parser.parse(&trailing).err(), Some(NtpError::TruncatedMac));
+        assert_eq!(parser.probe(&buf[..20]), ProbeResult::NotForUs);
+        assert!(parser.parse(&buf[..20]).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Classification of the reference clocks of primary servers
+
+/// Category of the reference clock of a primary (stratum 1) server
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockCategory {
+    /// Satellite navigation system (GPS, Galileo, GLONASS, BeiDou, ...)
+    Gnss,
+    /// Radio time signal (WWV, DCF77, MSF, JJY, ...)
+    Radio,
+    /// Pulse per second signal
+    Pps,
+    /// Atomic clock (cesium, rubidium, ...)
+    Atomic,
+    /// Local, undisciplined clock
+    Local,
+}
+
+// Prefixes of reference identifiers, tested in order
+const PREFIXES: &[(&[u8], ClockCategory)] = &[
+    (b"GPS", ClockCategory::Gnss),
+    (b"GNS", ClockCategory::Gnss),
+    (b"GAL", ClockCategory::Gnss),
+    (b"GLO", ClockCategory::Gnss),
+    (b"BDS", ClockCategory::Gnss),
+    (b"NMEA", ClockCategory::Gnss),
+    (b"GOES", ClockCategory::Gnss),
+    (b"WWV", ClockCategory::Radio),
+    (b"DCF", ClockCategory::Radio),
+    (b"MSF", ClockCategory::Radio),
+    (b"JJY", ClockCategory::Radio),
+    (b"CHU", ClockCategory::Radio),
+    (b"TDF", ClockCategory::Radio),
+    (b"HBG", ClockCategory::Radio),
+    (b"LORC", ClockCategory::Radio),
+    (b"PPS", ClockCategory::Pps),
+    (b"ATOM", ClockCategory::Atomic),
+    (b"CESM", ClockCategory::Atomic),
+    (b"RBDM", ClockCategory::Atomic),
+    (b"LOCL", ClockCategory::Local),
+    (b"LCL", ClockCategory::Local),
+];
+
+impl ClockCategory {
+    /// Guess the category of a reference clock from the reference identifier of a primary
+    /// server
+    ///
+    /// Identifiers are not standardized, so the prefix of the identifier is matched against
+    /// common names (ignoring case), for ex. `GPS`, `GPSD` and `GPS1` are all GNSS receivers.
+    /// Returns `None` if the identifier is not recognized.
+    pub fn from_ref_id(ref_id: u32) -> Option<ClockCategory> {
+        let b = ref_id.to_be_bytes();
+        let len = b.iter().position(|&c| c == 0).unwrap_or(4);
+        let name = &b[..len];
+        PREFIXES
+            .iter()
+            .find(|(prefix, _)| {
+                name.len() >= prefix.len() && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+            })
+            .map(|&(_, category)| category)
+    }
+}
+
+/// A local reference clock, identified by a reference identifier of the form `127.127.t.u`
+///
+/// The reference implementation (ntpd) addresses its reference clock drivers with the
+/// pseudo-address `127.127.t.u`, where `t` is the driver type and `u` the unit number. Older
+/// versions used this address as the reference identifier of packets synchronized to such a
+/// clock, which would otherwise be mistaken for a loopback IPv4 address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LocalRefClock {
+    /// Driver type (for ex. 1 for the undisciplined local clock, 20 for NMEA)
+    pub clock_type: u8,
+    /// Unit number of the driver
+    pub unit: u8,
+}
+
+// Names of some common reference clock drivers of ntpd, by type
+const DRIVER_NAMES: &[(u8, &str)] = &[
+    (1, "Undisciplined Local Clock"),
+    (3, "PSTI/Traconex 1020 WWV/WWVH Receiver"),
+    (4, "Spectracom WWVB/GPS Receivers"),
+    (5, "TrueTime GPS/GOES/OMEGA Receivers"),
+    (6, "IRIG Audio Decoder"),
+    (7, "Radio CHU Audio Demodulator/Decoder"),
+    (8, "Generic Reference Driver (PARSE)"),
+    (20, "Generic NMEA GPS Receiver"),
+    (22, "PPS Clock Discipline"),
+    (28, "Shared Memory Driver"),
+    (44, "NeoClock4X"),
+    (46, "GPSD NG client protocol"),
+];
+
+impl LocalRefClock {
+    /// Decode a reference identifier of the form `127.127.t.u`
+    ///
+    /// Returns `None` for other identifiers.
+    pub fn from_ref_id(ref_id: u32) -> Option<LocalRefClock> {
+        let b = ref_id.to_be_bytes();
+        if b[0] != 127 || b[1] != 127 {
+            return None;
+        }
+        Some(LocalRefClock {
+            clock_type: b[2],
+            unit: b[3],
+        })
+    }
+
+    /// Return the name of the ntpd driver for this clock type, if known
+    pub fn driver_name(&self) -> Option<&'static str> {
+        DRIVER_NAMES
+            .iter()
+            .find(|&&(t, _)| t == self.clock_type)
+            .map(|&(_, name)| name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ref_id(s: &[u8]) -> u32 {
+        let mut b = [0u8; 4];
+        b[..s.len()].copy_from_slice(s);
+        u32::from_be_bytes(b)
+    }
+
+    #[test]
+    fn test_clock_category() {
+        for id in [&b"GPS"[..], b"GPSD", b"GPS1", b"gal"].iter() {
+            assert_eq!(
+                ClockCategory::from_ref_id(ref_id(id)),
+                Some(ClockCategory::Gnss)
+            );
+        }
+        assert_eq!(
+            ClockCategory::from_ref_id(ref_id(b"DCFa")),
+            Some(ClockCategory::Radio)
+        );
+        assert_eq!(
+            ClockCategory::from_ref_id(ref_id(b"WWVB")),
+            Some(ClockCategory::Radio)
+        );
+        assert_eq!(
+            ClockCategory::from_ref_id(ref_id(b"PPS0")),
+            Some(ClockCategory::Pps)
+        );
+        assert_eq!(
+            ClockCategory::from_ref_id(ref_id(b"CESM")),
+            Some(ClockCategory::Atomic)
+        );
+        assert_eq!(
+            ClockCategory::from_ref_id(ref_id(b"LOCL")),
+            Some(ClockCategory::Local)
+        );
+        assert_eq!(ClockCategory::from_ref_id(ref_id(b"XYZ")), None);
+        assert_eq!(ClockCategory::from_ref_id(0), None);
+    }
+
+    #[test]
+    fn test_local_refclock() {
+        let clock = LocalRefClock::from_ref_id(0x7f7f_1402).expect("not a local clock");
+        assert_eq!(clock.clock_type, 20);
+        assert_eq!(clock.unit, 2);
+        assert_eq!(clock.driver_name(), Some("Generic NMEA GPS Receiver"));
+        let clock = LocalRefClock::from_ref_id(0x7f7f_ff00).expect("not a local clock");
+        assert_eq!(clock.driver_name(), None);
+        // loopback address
+        assert_eq!(LocalRefClock::from_ref_id(0x7f00_0001), None);
+    }
+}
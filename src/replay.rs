@@ -0,0 +1,266 @@
+//! Detection of duplicate and out-of-order packets
+
+use crate::ntp::{NtpPacket, NtpV3Packet, NtpV4Packet};
+use crate::timestamp::NtpTimestamp;
+use alloc::vec::Vec;
+use core::hash::{Hash, Hasher};
+
+/// Detector of duplicate or replayed packets, based on the transmit timestamp
+///
+/// The detector remembers the transmit timestamps of the last `window` packets. Since
+/// timestamps include a random-looking fraction, a repeated timestamp indicates a
+/// retransmission or a replay of the same packet. Packets from different sources should be
+/// observed by different detectors.
+#[derive(Clone, Debug)]
+pub struct ReplayDetector {
+    seen: Vec<NtpTimestamp>,
+    window: usize,
+    next: usize,
+}
+
+impl ReplayDetector {
+    /// Create a detector remembering the last `window` transmit timestamps
+    pub fn new(window: usize) -> ReplayDetector {
+        ReplayDetector {
+            seen: Vec::with_capacity(window),
+            window,
+            next: 0,
+        }
+    }
+
+    /// Record the packet, and return true if its transmit timestamp was already seen
+    ///
+    /// A zero transmit timestamp (unset) is never considered a duplicate.
+    pub fn observe(&mut self, pkt: &NtpV4Packet) -> bool {
+        let ts = pkt.ts_xmit;
+        if ts.is_unset() || self.window == 0 {
+            return false;
+        }
+        if self.seen.contains(&ts) {
+            return true;
+        }
+        if self.seen.len() < self.window {
+            self.seen.push(ts);
+        } else {
+            self.seen[self.next] = ts;
+            self.next = (self.next + 1) % self.window;
+        }
+        false
+    }
+}
+
+/// Checker of the monotonicity of the transmit timestamps of a source
+///
+/// The transmit timestamps of a well-behaved server increase from one response to the next.
+/// A decrease indicates that the clock of the server was stepped backward, or spoofed
+/// responses. As for [`ReplayDetector`], each source should be observed by a different checker.
+#[derive(Clone, Debug, Default)]
+pub struct MonotonicityChecker {
+    last: Option<NtpTimestamp>,
+}
+
+impl MonotonicityChecker {
+    /// Create a checker, without previous timestamp
+    pub fn new() -> MonotonicityChecker {
+        MonotonicityChecker::default()
+    }
+
+    /// Record the packet, and return true if its transmit timestamp is earlier than the
+    /// timestamp of the previous packet
+    ///
+    /// Timestamps are compared using wrapping arithmetic, so the era rollover is not a
+    /// violation. A zero transmit timestamp (unset) is ignored. The last timestamp is
+    /// always recorded, so only the first packet after a step is flagged.
+    pub fn observe(&mut self, pkt: &NtpV4Packet) -> bool {
+        let ts = pkt.ts_xmit;
+        if ts.0 == 0 {
+            return false;
+        }
+        let violation = matches!(self.last, Some(last) if ts.wrapping_diff(last) < 0);
+        self.last = Some(ts);
+        violation
+    }
+}
+
+// FNV-1a, a fast non-cryptographic hash
+//
+// The standard hasher is randomly keyed (and only available with std), while content hashes
+// must be the same across processes to be usable as cache keys.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    // integers are hashed in network byte order, and sizes as 64-bit values, so that the
+    // hash does not depend on the platform
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_be_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_be_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_be_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+}
+
+// Hash the fields common to versions 3 and 4, in wire order
+macro_rules! hash_header {
+    ($h:expr, $pkt:expr, $include_timestamps:expr) => {{
+        let (h, pkt) = ($h, $pkt);
+        h.write(&[pkt.li, pkt.version, pkt.mode.0, pkt.stratum]);
+        h.write(&[pkt.poll as u8, pkt.precision.0 as u8]);
+        h.write_u32(pkt.root_delay.0);
+        h.write_u32(pkt.root_dispersion.0);
+        h.write_u32(pkt.ref_id);
+        if $include_timestamps {
+            for ts in &[pkt.ts_ref, pkt.ts_orig, pkt.ts_recv, pkt.ts_xmit] {
+                h.write_u64(ts.0);
+            }
+        }
+    }};
+}
+
+impl<'a> NtpV3Packet<'a> {
+    /// Return a hash of the content of the packet, for use as a cache key
+    ///
+    /// The hash covers all the header fields, except the four timestamps if
+    /// `include_timestamps` is false, and the authenticator. It is computed with FNV-1a, so it
+    /// is the same across runs and platforms, but is not suitable against adversarial
+    /// collisions.
+    pub fn content_hash(&self, include_timestamps: bool) -> u64 {
+        let mut h = Fnv1a::default();
+        hash_header!(&mut h, self, include_timestamps);
+        if let Some(auth) = self.authenticator {
+            h.write_usize(auth.len());
+            h.write(auth);
+        }
+        h.finish()
+    }
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Return a hash of the content of the packet, for use as a cache key
+    ///
+    /// The hash covers all the header fields, except the four timestamps if
+    /// `include_timestamps` is false, the type and value of the extension fields, and the
+    /// key identifier and digest of the MAC. It is computed with FNV-1a, so it is the same
+    /// across runs and platforms, but is not suitable against adversarial collisions.
+    pub fn content_hash(&self, include_timestamps: bool) -> u64 {
+        let mut h = Fnv1a::default();
+        hash_header!(&mut h, self, include_timestamps);
+        for ext in self.extensions.iter() {
+            h.write_u16(ext.field_type);
+            h.write_usize(ext.value.len());
+            h.write(ext.value);
+        }
+        if let Some(auth) = &self.auth {
+            h.write_u32(auth.key_id);
+            h.write_usize(auth.mac.len());
+            h.write(auth.mac);
+        }
+        h.finish()
+    }
+}
+
+impl<'a> NtpPacket<'a> {
+    /// Return a hash of the content of the packet, for use as a cache key
+    ///
+    /// See [`NtpV4Packet::content_hash`]. The version is part of the hash, so versions 3 and
+    /// 4 packets never have the same hash except by collision. Control messages have no
+    /// timestamps, and all their fields are hashed.
+    pub fn content_hash(&self, include_timestamps: bool) -> u64 {
+        match self {
+            NtpPacket::V3(pkt) => pkt.content_hash(include_timestamps),
+            NtpPacket::V4(pkt) => pkt.content_hash(include_timestamps),
+            NtpPacket::Control(msg) => {
+                let mut h = Fnv1a::default();
+                msg.hash(&mut h);
+                h.finish()
+            }
+            #[cfg(feature = "ntpv5")]
+            NtpPacket::V5(pkt) => {
+                let mut pkt = pkt.clone();
+                if !include_timestamps {
+                    pkt.ts_recv = NtpTimestamp(0);
+                    pkt.ts_xmit = NtpTimestamp(0);
+                }
+                let mut h = Fnv1a::default();
+                pkt.hash(&mut h);
+                h.finish()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_detector() {
+        let mut detector = ReplayDetector::new(2);
+        let p1 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(1));
+        let p2 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(2));
+        let p3 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(3));
+        assert!(!detector.observe(&p1));
+        assert!(detector.observe(&p1));
+        assert!(!detector.observe(&p2));
+        assert!(!detector.observe(&p3));
+        // p1 is now out of the window
+        assert!(!detector.observe(&p1));
+        assert!(detector.observe(&p3));
+        let p0 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(0));
+        assert!(!detector.observe(&p0));
+        assert!(!detector.observe(&p0));
+    }
+
+    #[test]
+    fn test_content_hash() {
+        let p1 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(1));
+        let p2 = NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(2));
+        let p3 = NtpV4Packet::server_reply(2, 0, NtpTimestamp(0), NtpTimestamp(1));
+        assert_eq!(p1.content_hash(true), p1.clone().content_hash(true));
+        assert_ne!(p1.content_hash(true), p2.content_hash(true));
+        assert_eq!(p1.content_hash(false), p2.content_hash(false));
+        assert_ne!(p1.content_hash(false), p3.content_hash(false));
+        let v4 = NtpPacket::V4(p1.clone());
+        assert_eq!(v4.content_hash(true), p1.content_hash(true));
+    }
+
+    #[test]
+    fn test_monotonicity_checker() {
+        let reply = |xmit| NtpV4Packet::server_reply(1, 0, NtpTimestamp(0), NtpTimestamp(xmit));
+        let mut checker = MonotonicityChecker::new();
+        assert!(!checker.observe(&reply(10)));
+        assert!(!checker.observe(&reply(20)));
+        assert!(checker.observe(&reply(15)));
+        assert!(!checker.observe(&reply(16)));
+        assert!(!checker.observe(&reply(0)));
+        // era rollover
+        let mut checker = MonotonicityChecker::new();
+        assert!(!checker.observe(&reply(0xffff_ffff_0000_0000)));
+        assert!(!checker.observe(&reply(0x0000_0001_0000_0000)));
+    }
+}
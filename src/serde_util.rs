@@ -0,0 +1,55 @@
+//! Serialization helpers for the borrowed packet types, with the `serde` feature
+//!
+//! Borrowed byte slices are serialized with `serialize_bytes`, so formats with a native byte
+//! string type (for ex. CBOR or bincode) do not encode them as sequences of integers. In JSON,
+//! they are arrays of integers, as the `Vec<u8>` fields of the owned types.
+//!
+//! They are deserialized as borrowed byte strings, which requires a format able to borrow them
+//! from the input.
+
+use crate::ntp::{NtpExtension, NtpExtensions};
+use alloc::vec::Vec;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+pub(crate) fn serialize_bytes<S: Serializer>(
+    value: &[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(value)
+}
+
+pub(crate) fn serialize_option_bytes<S: Serializer>(
+    value: &Option<&[u8]>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.serialize_some(&Bytes(value)),
+        None => serializer.serialize_none(),
+    }
+}
+
+// Serialize the extension fields as a sequence, whether they are stored in a `Vec` or in a
+// `SmallVec`
+pub(crate) fn serialize_slice<T: Serialize, S: Serializer>(
+    value: &[T],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(value)
+}
+
+// Deserialize the extension fields from a sequence, whether they are stored in a `Vec` or in a
+// `SmallVec`
+pub(crate) fn deserialize_extensions<'de: 'a, 'a, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NtpExtensions<'a>, D::Error> {
+    let extensions = Vec::<NtpExtension<'a>>::deserialize(deserializer)?;
+    Ok(extensions.into_iter().collect())
+}
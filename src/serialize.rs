@@ -0,0 +1,310 @@
+//! Serialization of NTP packets
+
+use crate::error::SerializeError;
+use crate::ntp::{compose_flags_byte, NtpExtension, NtpV3Packet, NtpV4Packet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+// Writer of big-endian values into a fixed-size buffer
+struct SliceWriter<'b> {
+    buf: &'b mut [u8],
+    pos: usize,
+}
+
+impl<'b> SliceWriter<'b> {
+    fn write(&mut self, data: &[u8]) {
+        self.buf[self.pos..self.pos + data.len()].copy_from_slice(data);
+        self.pos += data.len();
+    }
+}
+
+// Write the 48-byte header of a version 3 or 4 packet
+macro_rules! write_header {
+    ($w:expr, $pkt:expr) => {
+        $w.write(&[
+            compose_flags_byte($pkt.li, $pkt.version, $pkt.mode),
+            $pkt.stratum,
+            $pkt.poll as u8,
+            $pkt.precision.0 as u8,
+        ]);
+        $w.write(&$pkt.root_delay.0.to_be_bytes());
+        $w.write(&$pkt.root_dispersion.0.to_be_bytes());
+        $w.write(&$pkt.ref_id.to_be_bytes());
+        $w.write(&$pkt.ts_ref.0.to_be_bytes());
+        $w.write(&$pkt.ts_orig.0.to_be_bytes());
+        $w.write(&$pkt.ts_recv.0.to_be_bytes());
+        $w.write(&$pkt.ts_xmit.0.to_be_bytes());
+    };
+}
+
+impl<'a> NtpV3Packet<'a> {
+    /// Serialize the packet (header and authenticator)
+    ///
+    /// The authenticator is written as is, after the header: in version 3, it is usually 12
+    /// bytes (4-byte key identifier and 8-byte DES checksum), and has no extension fields.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0; self.wire_len()];
+        let mut w = SliceWriter {
+            buf: &mut out,
+            pos: 0,
+        };
+        write_header!(w, self);
+        if let Some(authenticator) = self.authenticator {
+            w.write(authenticator);
+        }
+        out
+    }
+}
+
+// Return the length of the value of an extension field including padding
+//
+// Extension fields are padded to a multiple of 4 bytes, and the last field before a MAC must
+// be at least 28 bytes long (24 bytes of value) [RFC7822]. Empty fields of type 0, used as
+// padding by some implementations, are kept unchanged.
+fn padded_value_len(ext: &NtpExtension, last_before_mac: bool) -> usize {
+    let len = (ext.value.len() + 3) & !3;
+    if last_before_mac && !ext.is_padding() {
+        len.max(24)
+    } else {
+        len
+    }
+}
+
+// Return the length of an extension field (header and padded value), or `None` if it does not
+// fit in the 16-bit length
+fn extension_field_len(ext: &NtpExtension, last_before_mac: bool) -> Option<u16> {
+    u16::try_from(4 + padded_value_len(ext, last_before_mac)).ok()
+}
+
+impl<'a> NtpV3Packet<'a> {
+    /// Return the bytes covered by the authenticator: the 48-byte header, serialized
+    pub fn canonical_header_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0; 48];
+        let mut w = SliceWriter {
+            buf: &mut out,
+            pos: 0,
+        };
+        write_header!(w, self);
+        out
+    }
+}
+
+impl<'a> NtpV4Packet<'a> {
+    /// Serialize the packet (header, extension fields and MAC)
+    ///
+    /// The values of the extension fields are padded with zeroes to a multiple of 4 bytes, or
+    /// to 24 bytes for the last field before the MAC, as required by [RFC7822]. The length of
+    /// each field is written as the length of the entire field (header and padded value), so
+    /// that parsing the result returns the padded values. Padding fields (see
+    /// [`NtpExtension::is_padding`]) are written unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value of an extension field is longer than 65528 bytes once padded, so
+    /// its length does not fit in 16 bits. Use [`serialize_into`](Self::serialize_into) to get
+    /// an error instead.
+    ///
+    /// [RFC7822]: https://tools.ietf.org/html/rfc7822
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0; self.serialized_len(true)];
+        self.write_into(&mut out, true);
+        out
+    }
+
+    /// Serialize the packet into `buf`, and return the number of bytes written
+    ///
+    /// This does not allocate. If `buf` is too small, nothing is written and an error is
+    /// returned, with the required size. Extension fields are padded as by
+    /// [`to_bytes`](Self::to_bytes), and an extension field whose padded value is longer than
+    /// 65528 bytes returns [`SerializeError::ExtensionTooLarge`].
+    pub fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, SerializeError> {
+        if let Some((ext, _)) = self
+            .extensions_with_last_flag()
+            .find(|&(e, last)| extension_field_len(e, last).is_none())
+        {
+            let len = ext.value.len();
+            return Err(SerializeError::ExtensionTooLarge { len });
+        }
+        let needed = self.serialized_len(true);
+        if buf.len() < needed {
+            return Err(SerializeError::BufferTooSmall { needed });
+        }
+        Ok(self.write_into(&mut buf[..needed], true))
+    }
+
+    fn serialized_len(&self, with_mac: bool) -> usize {
+        let ext_len: usize = self
+            .extensions_with_last_flag()
+            .map(|(e, last)| 4 + padded_value_len(e, last))
+            .sum();
+        let mac_len = match &self.auth {
+            Some(auth) if with_mac => 4 + auth.mac.len(),
+            _ => 0,
+        };
+        48 + ext_len + mac_len
+    }
+
+    // Write the packet into `buf`, which must have the exact serialized length
+    fn write_into(&self, buf: &mut [u8], with_mac: bool) -> usize {
+        let mut w = SliceWriter { buf, pos: 0 };
+        write_header!(w, self);
+        for (ext, last) in self.extensions_with_last_flag() {
+            let len = padded_value_len(ext, last);
+            let field_len = extension_field_len(ext, last).expect("extension field too large");
+            w.write(&ext.field_type.to_be_bytes());
+            w.write(&field_len.to_be_bytes());
+            w.write(ext.value);
+            // the buffer is zero-initialized
+            w.pos += len - ext.value.len();
+        }
+        if let (Some(auth), true) = (&self.auth, with_mac) {
+            w.write(&auth.key_id.to_be_bytes());
+            w.write(auth.mac);
+        }
+        w.pos
+    }
+
+    /// Return the bytes covered by the MAC: the header and the extension fields, serialized
+    ///
+    /// The fields are serialized as by [`to_bytes`](Self::to_bytes) (in particular, extension
+    /// fields are padded), so this is the canonical form of the data to sign or verify. For a
+    /// received packet, this is equal to the bytes on the wire if the extension fields were
+    /// correctly padded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an extension field is too large, as [`to_bytes`](Self::to_bytes).
+    pub fn canonical_header_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0; self.serialized_len(false)];
+        self.write_into(&mut out, false);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ntp::{parse_ntpv3, parse_ntpv4, NtpMac};
+    use crate::timestamp::NtpTimestamp;
+
+    #[test]
+    fn test_ntpv4_to_bytes() {
+        let bytes = &[
+            0x23, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xcc, 0x25,
+            0xcc, 0x13, 0x2b, 0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01,
+            0x52, 0x80, 0x0c, 0x2b, 0x59, 0x00, 0x64, 0x66, 0x84, 0xf4, 0x4c, 0xa4, 0xee, 0xce,
+            0x12, 0xb8,
+        ];
+        let (_, pkt) = parse_ntpv4(bytes).expect("parsing failed");
+        assert_eq!(pkt.to_bytes(), bytes.to_vec());
+    }
+
+    #[test]
+    fn test_ntpv3_to_bytes() {
+        // from wireshark test captures 'ntp.pcap'
+        let bytes = &[
+            0x1b, 0x04, 0x06, 0xf5, 0x00, 0x00, 0x10, 0x0d, 0x00, 0x00, 0x05, 0x57, 0x82, 0xdc,
+            0x18, 0x18, 0xba, 0x29, 0x66, 0x36, 0x7d, 0xd0, 0x00, 0x00, 0xba, 0x29, 0x66, 0x36,
+            0x7d, 0x58, 0x40, 0x00, 0xba, 0x29, 0x66, 0x36, 0x7d, 0xd0, 0x00, 0x00, 0xba, 0x29,
+            0x66, 0x76, 0x7d, 0x50, 0x50, 0x00,
+        ];
+        let (_, pkt) = parse_ntpv3(bytes).expect("parsing failed");
+        assert_eq!(pkt.to_bytes(), bytes.to_vec());
+        // with a 12-byte authenticator
+        let mut buf = bytes.to_vec();
+        buf.extend_from_slice(&[0, 0, 0, 1, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let (_, pkt) = parse_ntpv3(&buf).expect("parsing failed");
+        assert!(pkt.authenticator.is_some());
+        assert_eq!(pkt.to_bytes(), buf);
+    }
+
+    #[test]
+    fn test_canonical_header_bytes() {
+        let mut pkt = NtpV4Packet::server_reply(2, 0, NtpTimestamp(1), NtpTimestamp(2));
+        pkt.extensions.push(NtpExtension {
+            field_type: 0x0104,
+            length: 28,
+            value: &[7; 24],
+        });
+        pkt.auth = Some(NtpMac {
+            key_id: 1,
+            mac: &[0xaa; 16],
+        });
+        let bytes = pkt.to_bytes();
+        let (_, parsed) = parse_ntpv4(&bytes).expect("parsing failed");
+        assert_eq!(parsed.canonical_header_bytes(), &bytes[..48 + 28]);
+        let (_, v3) = parse_ntpv3(&bytes[..60]).expect("parsing failed");
+        assert_eq!(v3.canonical_header_bytes(), &bytes[..48]);
+    }
+
+    #[test]
+    fn test_ntpv4_serialize_into() {
+        let reply = NtpV4Packet::server_reply(2, 0x7f00_0001, NtpTimestamp(1), NtpTimestamp(2));
+        let mut buf = [0xffu8; 64];
+        assert_eq!(reply.serialize_into(&mut buf), Ok(48));
+        assert_eq!(&buf[..48], &reply.to_bytes()[..]);
+        assert_eq!(buf[48], 0xff);
+        assert_eq!(
+            reply.serialize_into(&mut buf[..40]),
+            Err(SerializeError::BufferTooSmall { needed: 48 })
+        );
+        // the largest extension field, and a value which does not fit in the length
+        static VALUE: [u8; 65_529] = [0x55; 65_529];
+        let mut pkt = NtpV4Packet::server_reply(2, 0, NtpTimestamp(1), NtpTimestamp(2));
+        pkt.extensions.push(NtpExtension {
+            field_type: 0x0104,
+            length: 0,
+            value: &VALUE[..65_528],
+        });
+        pkt.auth = Some(NtpMac {
+            key_id: 1,
+            mac: &[0xaa; 16],
+        });
+        let mut buf = vec![0; 48 + 65_532 + 20];
+        assert_eq!(pkt.serialize_into(&mut buf), Ok(buf.len()));
+        let (rem, parsed) = parse_ntpv4(&buf).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(parsed.extensions[0].length, 65_532);
+        pkt.extensions[0].value = &VALUE;
+        assert_eq!(
+            pkt.serialize_into(&mut [0; 64]),
+            Err(SerializeError::ExtensionTooLarge { len: 65_529 })
+        );
+    }
+
+    #[test]
+    fn test_ntpv4_extension_padding() {
+        let mut pkt = NtpV4Packet::server_reply(2, 0, NtpTimestamp(1), NtpTimestamp(2));
+        let ext = NtpExtension {
+            field_type: 0x0104,
+            length: 9,
+            value: &[1, 2, 3, 4, 5],
+        };
+        pkt.extensions.push(ext.clone());
+        pkt.extensions.push(ext);
+        // without MAC, each value is padded to 8 bytes
+        let bytes = pkt.to_bytes();
+        assert_eq!(bytes.len(), 48 + 2 * 12);
+        assert_eq!(&bytes[48..60], &[1, 4, 0, 12, 1, 2, 3, 4, 5, 0, 0, 0]);
+        // with a MAC, the last field is padded to 28 bytes
+        pkt.auth = Some(NtpMac {
+            key_id: 1,
+            mac: &[0xaa; 16],
+        });
+        let bytes = pkt.to_bytes();
+        assert_eq!(bytes.len(), 48 + 12 + 28 + 20);
+        let (rem, parsed) = parse_ntpv4(&bytes).expect("parsing failed");
+        assert!(rem.is_empty());
+        assert_eq!(parsed.extensions.len(), 2);
+        assert_eq!(parsed.extensions[0].value, &[1, 2, 3, 4, 5, 0, 0, 0]);
+        assert_eq!(parsed.extensions[1].length, 28);
+        assert_eq!(&parsed.extensions[1].value[..5], &[1, 2, 3, 4, 5]);
+        assert!(parsed.extensions[1].value[5..].iter().all(|&b| b == 0));
+        assert_eq!(parsed.auth, pkt.auth);
+        // the padded packet is serialized unchanged
+        assert_eq!(parsed.to_bytes(), bytes);
+    }
+}
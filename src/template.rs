@@ -0,0 +1,148 @@
+//! Comparison of packets against expected values, for conformance testing
+
+use crate::ntp::{NtpMode, NtpV3Packet, NtpV4Packet};
+use crate::timestamp::{NtpShortFormat, NtpTimestamp, Precision};
+use alloc::vec::Vec;
+
+/// Expected values of the header fields of a packet
+///
+/// Fields set to `None` are not checked.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NtpTemplate {
+    pub li: Option<u8>,
+    pub version: Option<u8>,
+    pub mode: Option<NtpMode>,
+    pub stratum: Option<u8>,
+    pub poll: Option<i8>,
+    pub precision: Option<Precision>,
+    pub root_delay: Option<NtpShortFormat>,
+    pub root_dispersion: Option<NtpShortFormat>,
+    pub ref_id: Option<u32>,
+    pub ts_ref: Option<NtpTimestamp>,
+    pub ts_orig: Option<NtpTimestamp>,
+    pub ts_recv: Option<NtpTimestamp>,
+    pub ts_xmit: Option<NtpTimestamp>,
+}
+
+/// A field of a packet not matching the expected value of a template
+///
+/// Values are stored as integers (the raw value of the field), so that all fields can be
+/// represented.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    /// Name of the field, as in the packet structure
+    pub field: &'static str,
+    pub expected: i128,
+    pub actual: i128,
+}
+
+fn check<T: Into<i128>>(
+    v: &mut Vec<Mismatch>,
+    field: &'static str,
+    expected: Option<T>,
+    actual: T,
+) {
+    if let Some(expected) = expected {
+        let (expected, actual) = (expected.into(), actual.into());
+        if expected != actual {
+            v.push(Mismatch {
+                field,
+                expected,
+                actual,
+            });
+        }
+    }
+}
+
+macro_rules! impl_matches_template {
+    ($t:ident) => {
+        impl<'a> $t<'a> {
+            /// Compare the header fields of the packet with the expected values of `template`
+            ///
+            /// Returns the fields which do not match (an empty vector if the packet matches).
+            pub fn matches_template(&self, template: &NtpTemplate) -> Vec<Mismatch> {
+                let mut v = Vec::new();
+                check(&mut v, "li", template.li, self.li);
+                check(&mut v, "version", template.version, self.version);
+                check(&mut v, "mode", template.mode.map(|m| m.0), self.mode.0);
+                check(&mut v, "stratum", template.stratum, self.stratum);
+                check(&mut v, "poll", template.poll, self.poll);
+                check(
+                    &mut v,
+                    "precision",
+                    template.precision.map(|p| p.0),
+                    self.precision.0,
+                );
+                check(
+                    &mut v,
+                    "root_delay",
+                    template.root_delay.map(|d| d.0),
+                    self.root_delay.0,
+                );
+                check(
+                    &mut v,
+                    "root_dispersion",
+                    template.root_dispersion.map(|d| d.0),
+                    self.root_dispersion.0,
+                );
+                check(&mut v, "ref_id", template.ref_id, self.ref_id);
+                check(
+                    &mut v,
+                    "ts_ref",
+                    template.ts_ref.map(|t| t.0),
+                    self.ts_ref.0,
+                );
+                check(
+                    &mut v,
+                    "ts_orig",
+                    template.ts_orig.map(|t| t.0),
+                    self.ts_orig.0,
+                );
+                check(
+                    &mut v,
+                    "ts_recv",
+                    template.ts_recv.map(|t| t.0),
+                    self.ts_recv.0,
+                );
+                check(
+                    &mut v,
+                    "ts_xmit",
+                    template.ts_xmit.map(|t| t.0),
+                    self.ts_xmit.0,
+                );
+                v
+            }
+        }
+    };
+}
+
+impl_matches_template!(NtpV3Packet);
+impl_matches_template!(NtpV4Packet);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timestamp::NtpTimestamp;
+
+    #[test]
+    fn test_matches_template() {
+        let pkt = NtpV4Packet::server_reply(2, 0x7f00_0001, NtpTimestamp(1), NtpTimestamp(2));
+        let template = NtpTemplate {
+            version: Some(4),
+            mode: Some(NtpMode::Server),
+            stratum: Some(1),
+            precision: Some(Precision(-20)),
+            ts_orig: Some(NtpTimestamp(0)),
+            ..NtpTemplate::default()
+        };
+        assert_eq!(
+            pkt.matches_template(&template),
+            vec![Mismatch {
+                field: "stratum",
+                expected: 1,
+                actual: 2
+            }]
+        );
+        assert!(pkt.matches_template(&NtpTemplate::default()).is_empty());
+    }
+}
@@ -0,0 +1,384 @@
+//! NTP timestamps and time-related types and constants
+
+use core::fmt;
+use nom_derive::*;
+
+/// Number of seconds between the NTP epoch (1900-01-01) and the Unix epoch (1970-01-01)
+pub const NTP_EPOCH_OFFSET: u64 = 2_208_988_800;
+
+/// Number of seconds in an NTP era (the 32-bit seconds field wraps after this duration)
+pub const NTP_ERA_SECONDS: u64 = 1 << 32;
+
+/// An NTP timestamp, in the 64-bit format
+///
+/// The 32 most significant bits are the number of seconds since the NTP epoch (1900-01-01),
+/// and the 32 least significant bits are the fraction of second. The value 0 is the unset
+/// timestamp, which does not represent a date.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpTimestamp(pub u64);
+
+impl NtpTimestamp {
+    /// Return true if this is the unset timestamp (0)
+    #[inline]
+    pub const fn is_unset(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Return the seconds part of the timestamp
+    #[inline]
+    pub const fn seconds(&self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Return the fraction part of the timestamp, in units of 2^-32 seconds
+    #[inline]
+    pub const fn fraction(&self) -> u32 {
+        self.0 as u32
+    }
+
+    /// Return the signed difference `self - other`, in the 32.32 fixed-point format
+    ///
+    /// The difference is computed using wrapping arithmetic, as recommended by RFC 5905: the
+    /// result is correct as long as both timestamps are less than 68 years apart, even if they
+    /// are in different eras (for ex. across the 2036 wraparound).
+    #[inline]
+    pub const fn wrapping_diff(&self, other: NtpTimestamp) -> i64 {
+        self.0.wrapping_sub(other.0) as i64
+    }
+
+    /// Return the signed difference `self - other`, in seconds
+    ///
+    /// See [`wrapping_diff`](Self::wrapping_diff) for the validity range.
+    #[inline]
+    pub fn diff_seconds(&self, other: NtpTimestamp) -> f64 {
+        fixed64_to_seconds(self.wrapping_diff(other))
+    }
+
+    /// Return the number of seconds since the Unix epoch (1970-01-01)
+    ///
+    /// The timestamp is assumed to be in era 0 (1900-2036): after the wraparound of
+    /// 2036-02-07, timestamps of era 1 restart from 0, and are converted to dates before 1970
+    /// (negative values), see [`to_unix_seconds_in_era`](Self::to_unix_seconds_in_era).
+    /// Returns `NaN` for the unset timestamp (0), which does not represent a date (and is
+    /// converted back by [`from_unix_seconds`](Self::from_unix_seconds)).
+    pub fn to_unix_seconds(&self) -> f64 {
+        if self.is_unset() {
+            return f64::NAN;
+        }
+        self.to_unix_seconds_in_era(0)
+    }
+
+    /// Return the number of seconds since the Unix epoch (1970-01-01), with the timestamp in
+    /// the era `era`
+    ///
+    /// The era is the number of wraparounds of the seconds (era 1 starts on 2036-02-07). It is
+    /// not sent by versions 3 and 4, and must be guessed, for ex. from the local time. Version
+    /// 5 packets include it.
+    pub fn to_unix_seconds_in_era(&self, era: u32) -> f64 {
+        let secs = u64::from(era) * NTP_ERA_SECONDS + u64::from(self.seconds());
+        let secs = secs as i64 - NTP_EPOCH_OFFSET as i64;
+        secs as f64 + f64::from(self.fraction()) / (1u64 << 32) as f64
+    }
+
+    /// Convert a number of seconds since the Unix epoch to a timestamp
+    ///
+    /// The seconds are taken modulo 2^32, so dates after 2036-02-07 are converted to
+    /// timestamps of era 1, as sent on the wire. A `NaN` value returns the unset timestamp.
+    pub fn from_unix_seconds(seconds: f64) -> NtpTimestamp {
+        let t = seconds + NTP_EPOCH_OFFSET as f64;
+        // the conversion truncates toward zero (and saturates), round toward -infinity
+        let mut secs = t as i64;
+        if secs as f64 > t {
+            secs -= 1;
+        }
+        let frac = (((t - secs as f64) * (1u64 << 32) as f64) as u64).min(0xffff_ffff);
+        NtpTimestamp(((secs.rem_euclid(1 << 32) as u64) << 32) | frac)
+    }
+}
+
+/// Format the timestamp as an ISO 8601 date and time (UTC), with microseconds
+///
+/// The timestamp is assumed to be in era 0 (1900-2036). The unset timestamp (0) is displayed
+/// as `unset`.
+impl fmt::Display for NtpTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("unset");
+        }
+        let secs = i64::from(self.seconds()) - NTP_EPOCH_OFFSET as i64;
+        let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+        let (year, month, day) = civil_from_days(days);
+        let micros = (u64::from(self.fraction()) * 1_000_000) >> 32;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            year,
+            month,
+            day,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            micros
+        )
+    }
+}
+
+// Convert a number of days since 1970-01-01 to a date in the proleptic Gregorian calendar
+// (from http://howardhinnant.github.io/date_algorithms.html)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Convert a signed 32.32 fixed-point value to seconds
+#[inline]
+pub(crate) fn fixed64_to_seconds(v: i64) -> f64 {
+    v as f64 / (1u64 << 32) as f64
+}
+
+/// Convert an unsigned 32.32 fixed-point value to a duration
+pub(crate) fn fixed64_to_duration(v: u64) -> core::time::Duration {
+    let nanos = ((v & 0xffff_ffff) * 1_000_000_000) >> 32;
+    core::time::Duration::new(v >> 32, nanos as u32)
+}
+
+/// Convert a duration to the 32.32 fixed-point format, saturating on overflow
+pub(crate) fn duration_to_fixed64(d: core::time::Duration) -> u64 {
+    let secs = d.as_secs();
+    if secs >= 1 << 32 {
+        return u64::MAX;
+    }
+    let frac = (u64::from(d.subsec_nanos()) << 32) / 1_000_000_000;
+    (secs << 32) | frac
+}
+
+/// A time interval in the NTP short format (32-bit fixed-point: 16.16 seconds)
+///
+/// This format is used for the root delay and root dispersion fields. It is unsigned, but some
+/// buggy implementations send negative values, which can be decoded using
+/// [`to_seconds_signed`](Self::to_seconds_signed).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NtpShortFormat(pub u32);
+
+impl NtpShortFormat {
+    /// Return the value in seconds, as an unsigned value (as defined by RFC 5905)
+    #[inline]
+    pub fn to_seconds(&self) -> f64 {
+        f64::from(self.0) / 65536.0
+    }
+
+    /// Return the value in seconds, interpreting the most significant bit as a sign
+    ///
+    /// This can be used to detect and display values sent by implementations which
+    /// (incorrectly) use a signed format.
+    #[inline]
+    pub fn to_seconds_signed(&self) -> f64 {
+        f64::from(self.0 as i32) / 65536.0
+    }
+
+    /// Return the value as a duration, rounded to the nearest nanosecond
+    pub fn to_duration(&self) -> core::time::Duration {
+        let nanos = (u64::from(self.0) * 1_000_000_000 + (1 << 15)) >> 16;
+        core::time::Duration::from_nanos(nanos)
+    }
+
+    /// Convert a duration to the short format, rounded to the nearest unit (2^-16 seconds)
+    ///
+    /// Durations exceeding the representable range (about 65536 seconds) are clamped to the
+    /// maximum value.
+    pub fn from_duration(d: core::time::Duration) -> NtpShortFormat {
+        if d.as_secs() >= 1 << 16 {
+            return NtpShortFormat(u32::MAX);
+        }
+        let v = ((d.as_nanos() << 16) + 500_000_000) / 1_000_000_000;
+        NtpShortFormat(v.min(u128::from(u32::MAX)) as u32)
+    }
+}
+
+/// Return 2^exp, for exponents in the range of normal `f64` values
+pub(crate) fn pow2(exp: i32) -> f64 {
+    f64::from_bits(((exp + 1023) as u64) << 52)
+}
+
+/// Precision of a system clock, as a signed exponent of two, in seconds
+///
+/// For example, a value of -18 corresponds to a precision of about one microsecond.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, NomBE)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Precision(pub i8);
+
+impl Precision {
+    /// Return the precision in seconds
+    pub fn to_seconds(&self) -> f64 {
+        pow2(i32::from(self.0))
+    }
+
+    /// Return the precision whose value is the closest to `seconds`
+    ///
+    /// The exponent is rounded to the nearest value, and clamped to the range of `i8`.
+    /// Values that are not strictly positive return the minimum precision exponent.
+    pub fn from_seconds(seconds: f64) -> Precision {
+        if seconds.is_nan() || seconds <= pow2(-128) {
+            return Precision(i8::MIN);
+        }
+        if seconds >= pow2(127) {
+            return Precision(i8::MAX);
+        }
+        // seconds is a normal positive value here, read its exponent directly
+        let mut exp = ((seconds.to_bits() >> 52) & 0x7ff) as i32 - 1023;
+        if seconds >= pow2(exp) * core::f64::consts::SQRT_2 {
+            exp += 1;
+        }
+        Precision(exp.min(127) as i8)
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let secs = self.to_seconds();
+        let (v, unit) = if secs >= 0.1 {
+            (secs, "s")
+        } else if secs >= 1e-4 {
+            (secs * 1e3, "ms")
+        } else if secs >= 1e-7 {
+            (secs * 1e6, "µs")
+        } else {
+            (secs * 1e9, "ns")
+        };
+        write!(f, "2^{} s (~{:.2} {})", self.0, v, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamp_diff() {
+        let t1 = NtpTimestamp(0x0000_0010_8000_0000);
+        let t2 = NtpTimestamp(0x0000_0012_0000_0000);
+        assert_eq!(t2.wrapping_diff(t1), 0x1_8000_0000);
+        assert_eq!(t2.diff_seconds(t1), 1.5);
+        assert_eq!(t1.diff_seconds(t2), -1.5);
+        // across the era boundary (2036-02-07)
+        let t1 = NtpTimestamp(0xffff_ffff_0000_0000);
+        let t2 = NtpTimestamp(0x0000_0001_0000_0000);
+        assert_eq!(t2.diff_seconds(t1), 2.0);
+        assert_eq!(t1.diff_seconds(t2), -2.0);
+        assert_eq!(NtpTimestamp(0).diff_seconds(NtpTimestamp(0)), 0.0);
+    }
+
+    #[test]
+    fn test_timestamp_unix_seconds() {
+        assert!(NtpTimestamp(0).to_unix_seconds().is_nan());
+        let unix_epoch = NtpTimestamp((NTP_EPOCH_OFFSET << 32) | 0x8000_0000);
+        assert_eq!(unix_epoch.to_unix_seconds(), 0.5);
+        assert_eq!(NtpTimestamp::from_unix_seconds(0.5), unix_epoch);
+        let ts = NtpTimestamp::from_unix_seconds(1_709_210_096.25);
+        assert_eq!(
+            ts.0,
+            ((NTP_EPOCH_OFFSET + 1_709_210_096) << 32) | 0x4000_0000
+        );
+        assert_eq!(ts.to_unix_seconds(), 1_709_210_096.25);
+        assert_eq!(
+            NtpTimestamp::from_unix_seconds(-0.25).0,
+            ((NTP_EPOCH_OFFSET - 1) << 32) | 0xc000_0000
+        );
+        // 2036-02-07T06:28:16Z is the start of era 1
+        let era1 = NtpTimestamp::from_unix_seconds(2_085_978_496.0 + 1.0);
+        assert_eq!(era1, NtpTimestamp(1 << 32));
+        assert_eq!(NtpTimestamp::from_unix_seconds(f64::NAN), NtpTimestamp(0));
+        assert!(NtpTimestamp::from_unix_seconds(f64::NAN).is_unset());
+    }
+
+    #[test]
+    fn test_timestamp_display() {
+        assert_eq!(NtpTimestamp(0).to_string(), "unset");
+        assert_eq!(
+            NtpTimestamp(1 << 32).to_string(),
+            "1900-01-01T00:00:01.000000Z"
+        );
+        let unix_epoch = NtpTimestamp((NTP_EPOCH_OFFSET << 32) | 0x8000_0000);
+        assert_eq!(unix_epoch.to_string(), "1970-01-01T00:00:00.500000Z");
+        // 2024-02-29T12:34:56Z
+        let ts = NtpTimestamp((NTP_EPOCH_OFFSET + 1_709_210_096) << 32);
+        assert_eq!(ts.to_string(), "2024-02-29T12:34:56.000000Z");
+        assert_eq!(
+            NtpTimestamp(u64::MAX << 32).to_string(),
+            "2036-02-07T06:28:15.000000Z"
+        );
+    }
+
+    #[test]
+    fn test_short_format() {
+        let v = NtpShortFormat(0x0001_8000);
+        assert_eq!(v.to_seconds(), 1.5);
+        assert_eq!(v.to_seconds_signed(), 1.5);
+        // high bit set
+        let v = NtpShortFormat(0xffff_8000);
+        assert_eq!(v.to_seconds(), 65535.5);
+        assert_eq!(v.to_seconds_signed(), -0.5);
+        let v = NtpShortFormat(0x8000_0000);
+        assert_eq!(v.to_seconds(), 32768.0);
+        assert_eq!(v.to_seconds_signed(), -32768.0);
+    }
+
+    #[test]
+    fn test_short_format_duration() {
+        use core::time::Duration;
+        let d = Duration::from_millis(1500);
+        assert_eq!(NtpShortFormat(0x0001_8000).to_duration(), d);
+        assert_eq!(
+            NtpShortFormat::from_duration(d),
+            NtpShortFormat(0x0001_8000)
+        );
+        // one unit is ~15258.8 ns
+        assert_eq!(NtpShortFormat(1).to_duration(), Duration::from_nanos(15259));
+        assert_eq!(
+            NtpShortFormat::from_duration(Duration::from_nanos(7630)),
+            NtpShortFormat(1)
+        );
+        assert_eq!(
+            NtpShortFormat::from_duration(Duration::from_nanos(7629)),
+            NtpShortFormat(0)
+        );
+        // clamping
+        let max = NtpShortFormat(u32::MAX);
+        assert_eq!(
+            NtpShortFormat::from_duration(Duration::from_secs(65536)),
+            max
+        );
+        assert_eq!(NtpShortFormat::from_duration(max.to_duration()), max);
+        assert_eq!(
+            NtpShortFormat::from_duration(Duration::new(65535, 999_999_999)),
+            max
+        );
+    }
+
+    #[test]
+    fn test_precision() {
+        assert_eq!(Precision(0).to_seconds(), 1.0);
+        assert_eq!(Precision(-6).to_seconds(), 0.015625);
+        assert_eq!(Precision(3).to_seconds(), 8.0);
+        assert_eq!(Precision::from_seconds(0.015625), Precision(-6));
+        assert_eq!(Precision::from_seconds(1e-6), Precision(-20));
+        assert_eq!(Precision::from_seconds(0.0), Precision(i8::MIN));
+        assert_eq!(Precision::from_seconds(1e60), Precision(i8::MAX));
+        for p in i8::MIN..=i8::MAX {
+            let p = Precision(p);
+            assert_eq!(Precision::from_seconds(p.to_seconds()), p);
+        }
+        assert_eq!(Precision(-20).to_string(), "2^-20 s (~0.95 µs)");
+        assert_eq!(Precision(-6).to_string(), "2^-6 s (~15.62 ms)");
+    }
+}
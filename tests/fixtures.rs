@@ -0,0 +1,64 @@
+//! Parse the packets stored in the `fixtures` directory
+
+use ntp_parser::*;
+
+static V3_CLIENT: &[u8] = include_bytes!("fixtures/v3_client.bin");
+static V4_MAC: &[u8] = include_bytes!("fixtures/v4_mac.bin");
+static V4_EXT: &[u8] = include_bytes!("fixtures/v4_ext.bin");
+static V4_NTS: &[u8] = include_bytes!("fixtures/v4_nts.bin");
+static CONTROL_CV: &[u8] = include_bytes!("fixtures/control_cv.bin");
+
+#[test]
+fn fixture_v3_client() {
+    let (rem, pkt) = parse_ntp(V3_CLIENT).expect("parsing failed");
+    assert!(rem.is_empty());
+    let pkt = match pkt {
+        NtpPacket::V3(pkt) => pkt,
+        _ => panic!("unexpected version"),
+    };
+    assert_eq!(pkt.mode, NtpMode::Client);
+    assert_eq!(pkt.stratum, 4);
+    assert_eq!(pkt.authenticator, None);
+}
+
+#[test]
+fn fixture_v4_mac() {
+    let (rem, pkt) = parse_ntpv4(V4_MAC).expect("parsing failed");
+    assert!(rem.is_empty());
+    assert_eq!(pkt.mode, NtpMode::Client);
+    assert!(pkt.extensions.is_empty());
+    let auth = pkt.auth.expect("missing MAC");
+    assert_eq!(auth.key_id, 1);
+    assert_eq!(auth.mac, &V4_MAC[52..]);
+}
+
+#[test]
+fn fixture_v4_ext() {
+    let (rem, pkt) = parse_ntpv4(V4_EXT).expect("parsing failed");
+    assert!(rem.is_empty());
+    assert_eq!(pkt.extensions.len(), 1);
+    assert!(pkt.extensions[0].is_padding());
+    assert!(pkt.auth.is_some());
+    assert_eq!(pkt.wire_len(), V4_EXT.len());
+}
+
+#[test]
+fn fixture_v4_nts() {
+    let (rem, pkt) = parse_ntpv4_lenient_mac(V4_NTS).expect("parsing failed");
+    assert!(rem.is_empty());
+    assert!(pkt.has_extensions_without_mac());
+    let types: Vec<u16> = pkt.extensions.iter().map(|e| e.field_type).collect();
+    assert_eq!(types, [0x0104, 0x0204, 0x0404]);
+    assert_eq!(pkt.nts_unique_id(), Some(&V4_NTS[52..84]));
+}
+
+#[test]
+fn fixture_control_cv() {
+    let (rem, msg) = parse_ntp_control(CONTROL_CV).expect("parsing failed");
+    assert!(rem.is_empty());
+    assert_eq!(msg.opcode, NtpControlOpcode::ReadClockVariables);
+    let vars = msg
+        .parse_clock_variables()
+        .expect("decoding variables failed");
+    assert_eq!(vars[0], ("device", "GPS, NMEA"));
+}
@@ -0,0 +1,28 @@
+//! Check that the parsers can be used from a `no_std` crate
+//!
+//! This crate only uses `core` and `alloc`. Run with `--no-default-features` to also build the
+//! library without its `std` feature. This does not check that the parsers work on targets
+//! without `std`: the dependencies (`nom-derive`) still require it.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+use ntp_parser::{parse_ntp, NtpMode, NtpPacket};
+
+#[test]
+fn no_std_parse() {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&[0x24, 0x02, 0x06, 0xec]);
+    buf.resize(48, 0);
+    buf[12..16].copy_from_slice(&[192, 168, 0, 1]);
+    let (rem, pkt) = parse_ntp(&buf).expect("parsing failed");
+    assert!(rem.is_empty());
+    let pkt = match pkt {
+        NtpPacket::V4(pkt) => pkt,
+        _ => panic!("unexpected packet version"),
+    };
+    assert_eq!(pkt.mode, NtpMode::Server);
+    assert_eq!(pkt.ref_id, 0xc0a8_0001);
+}